@@ -0,0 +1,169 @@
+//! Compares the automaton lexer with the lexer it replaced, which found the
+//! longest literal with a trie and then tried each regex in turn.
+//! Run with 'cargo bench'.
+
+extern crate heck;
+extern crate regex;
+
+mod trie;
+
+use heck::{Token, TokenDef, GrammarToken, LexerRules, ModeChange, ParseError, parse_raw_rules, find_lexer_rules, lex};
+use regex::Regex;
+use std::time::{Duration, Instant};
+use trie::Trie;
+
+const TOML_GRAMMAR: &str = include_str!("../../examples/toml.heck");
+
+const TOML_SAMPLE: &str = r#"[package]
+name = "heck"
+version = "0.1.0"
+authors = ["Machtan <jako3047@gmail.com>", "someone else"]
+keywords = [ "parser", "lexer", "grammar" ]
+
+[dependencies]
+linked-hash-map = "0.5.0"
+argonaut = { path = "../argonaut", optional = true }
+
+[[bin]]
+name = "dero"
+doc = false
+threads = +16
+ratio = -0.125_5
+"#;
+
+const EXPR_GRAMMAR: &str = r##"
+program: $expr EOF
+expr: $NUM | $NAME | "(" $expr ")" | "-" $expr | "let" $NAME "=" $expr "in" $expr
+NUM: r#"[0-9]+(\.[0-9]+)?"#
+NAME: r#"[a-zA-Z_][a-zA-Z_0-9]*"#
+_WS: r#"\s+"#
+@left expr "+" "-"
+@left expr "*" "/"
+@right expr "^"
+"##;
+
+const EXPR_SAMPLE: &str = "let letter = (12 + 345.5) * -6 / inner ^ 8 in\n    letter - (lets + 90) * 1 ";
+
+/// The size of the lexed texts.
+const TEXT_LEN: usize = 1 << 20;
+
+/// How many times each text is lexed, keeping the fastest time.
+const RUNS: usize = 10;
+
+/// Lexes the text like 'lex' did before it used an automaton.
+fn trie_lex(text: &str, rules: &LexerRules) -> Result<Vec<Token>, ParseError> {
+    let mut modes = rules.modes().iter().map(|_| (Trie::new(), Vec::new())).collect::<Vec<_>>();
+    for token_def in rules {
+        let (name, token) = match *token_def {
+            TokenDef::Named(ref name, ref token, _) => (name, token),
+            TokenDef::Unnamed(ref token, _) => match *token {
+                GrammarToken::Str(ref name) | GrammarToken::Re(ref name) => (name, token),
+                GrammarToken::Named(..) => continue,
+            },
+        };
+        let id = rules.symbols().token_id(name).unwrap();
+        let skipped = name.starts_with("_");
+        let in_modes = rules.modes().iter().enumerate()
+            .filter(|&(_, mode)| mode.tokens().contains(&id))
+            .map(|(index, _)| index);
+        match *token {
+            GrammarToken::Str(ref string) => {
+                let alpha = string.chars().all(|ch| ch.is_alphabetic());
+                for index in in_modes {
+                    modes[index].0.insert(string, ((id, skipped), alpha));
+                }
+            }
+            GrammarToken::Re(ref regex) => {
+                let regex = Regex::new(&format!("^{}", regex)).unwrap();
+                for index in in_modes {
+                    modes[index].1.push((regex.clone(), (id, skipped)));
+                }
+            }
+            GrammarToken::Named(..) => {}
+        }
+    }
+    let mut found_tokens = Vec::new();
+    let mut mode_stack = vec![0];
+    let mut start = 0;
+    while start < text.len() {
+        let slice = &text[start..];
+        let (ref literals, ref regexes) = modes[*mode_stack.last().unwrap()];
+        let mut found = None;
+        if let Some((prefix, &((id, skipped), alpha))) = literals.find_longest_match(slice) {
+            let end = start + prefix.len();
+            let is_valid = ! alpha || ! text[end..].chars().next().map_or(false, |ch| ch.is_alphabetic());
+            if is_valid {
+                found = Some((id, skipped, end));
+            }
+        }
+        if found.is_none() {
+            for &(ref regex, (id, skipped)) in regexes {
+                if let Some(m) = regex.find(slice) {
+                    found = Some((id, skipped, start + m.end()));
+                    break;
+                }
+            }
+        }
+        let (id, skipped, end) = match found {
+            Some(found) => found,
+            None => panic!("The text can't be lexed at {}", start),
+        };
+        if ! skipped {
+            found_tokens.push(Token::new(id, start, end));
+        }
+        start = end;
+        match rules.mode_change(id) {
+            Some(ModeChange::Push(mode)) => mode_stack.push(mode),
+            Some(ModeChange::Pop) => if mode_stack.len() > 1 {
+                mode_stack.pop();
+            },
+            None => {}
+        }
+    }
+    Ok(found_tokens)
+}
+
+/// Returns the fastest time of lexing the text, and the lexed tokens.
+fn time<F>(text: &str, rules: &LexerRules, lex: F) -> (Duration, Vec<Token>)
+    where F: Fn(&str, &LexerRules) -> Result<Vec<Token>, ParseError>
+{
+    let mut best = None;
+    let mut tokens = Vec::new();
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        tokens = lex(text, rules).expect("The text couldn't be lexed");
+        let elapsed = start.elapsed();
+        if best.map_or(true, |best| elapsed < best) {
+            best = Some(elapsed);
+        }
+    }
+    (best.unwrap(), tokens)
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9
+}
+
+fn bench(name: &str, grammar: &str, sample: &str) {
+    let raw_rules = parse_raw_rules(grammar).expect("The grammar couldn't be parsed");
+    let start = Instant::now();
+    let rules = find_lexer_rules(&raw_rules);
+    let build = start.elapsed();
+    let mut text = String::with_capacity(TEXT_LEN + sample.len());
+    while text.len() < TEXT_LEN {
+        text.push_str(sample);
+    }
+    let megabytes = text.len() as f64 / (1 << 20) as f64;
+    let (trie_time, trie_tokens) = time(&text, &rules, trie_lex);
+    let (dfa_time, dfa_tokens) = time(&text, &rules, lex);
+    println!("{} ({:.1} MB, {} tokens, rules found in {:.2} ms):",
+        name, megabytes, dfa_tokens.len(), seconds(build) * 1e3);
+    println!("    trie and regexes: {:8.2} ms, {:7.1} MB/s", seconds(trie_time) * 1e3, megabytes / seconds(trie_time));
+    println!("    automaton:        {:8.2} ms, {:7.1} MB/s", seconds(dfa_time) * 1e3, megabytes / seconds(dfa_time));
+    println!("    speedup: {:.2}x, same tokens: {}", seconds(trie_time) / seconds(dfa_time), trie_tokens == dfa_tokens);
+}
+
+fn main() {
+    bench("toml", TOML_GRAMMAR, TOML_SAMPLE);
+    bench("expressions", EXPR_GRAMMAR, EXPR_SAMPLE);
+}
@@ -0,0 +1,524 @@
+//! A lexer and parser generated by heck. Do not edit by hand.
+#![allow(dead_code, unused_mut, unused_variables, unreachable_code, 
+    unreachable_patterns, non_camel_case_types)]
+
+/// The kinds of tokens in the grammar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    /// "EOF"
+    EOF,
+    /// "_WS"
+    _WS,
+    /// "NUM"
+    NUM,
+    /// "("
+    Unnamed3,
+    /// ","
+    Unnamed4,
+    /// ")"
+    Unnamed5,
+    /// "["
+    Unnamed6,
+    /// "]"
+    Unnamed7,
+    /// "{"
+    Unnamed8,
+    /// "}"
+    Unnamed9,
+}
+impl TokenKind {
+    /// Returns the name of the token in the grammar.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            TokenKind::EOF => "EOF",
+            TokenKind::_WS => "_WS",
+            TokenKind::NUM => "NUM",
+            TokenKind::Unnamed3 => "(",
+            TokenKind::Unnamed4 => ",",
+            TokenKind::Unnamed5 => ")",
+            TokenKind::Unnamed6 => "[",
+            TokenKind::Unnamed7 => "]",
+            TokenKind::Unnamed8 => "{",
+            TokenKind::Unnamed9 => "}",
+        }
+    }
+
+    /// Returns whether tokens of this kind are left out by the lexer.
+    pub fn is_skipped(&self) -> bool {
+        match *self {
+            TokenKind::_WS => true,
+            _ => false,
+        }
+    }
+}
+
+/// The automaton of each lexer mode, starting with the default mode.
+const MODES: &'static [ModeLexer] = &[
+    // default
+    ModeLexer {
+        range_starts: &[0, 9, 14, 32, 33, 40, 41, 42, 44, 45, 48, 58, 91, 92, 93, 94, 123, 124, 125, 126, 133, 134, 160, 161, 5760, 5761, 8192, 8203, 8232, 8234, 8239, 8240, 8287, 8288, 12288, 12289],
+        range_classes: &[0, 1, 0, 1, 0, 2, 3, 0, 4, 0, 5, 0, 6, 0, 7, 0, 8, 0, 9, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0],
+        nof_classes: 10,
+        transitions: &[
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 2, 3, 4, 5, 6, 7, 8, 9, 10,
+            0, 11, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 12, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 11, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 12, 0, 0, 0, 0,
+        ],
+        accepts: &[
+            &[],
+            &[],
+            &[(TokenKind::_WS, false)],
+            &[(TokenKind::Unnamed3, false)],
+            &[(TokenKind::Unnamed5, false)],
+            &[(TokenKind::Unnamed4, false)],
+            &[(TokenKind::NUM, false)],
+            &[(TokenKind::Unnamed6, false)],
+            &[(TokenKind::Unnamed7, false)],
+            &[(TokenKind::Unnamed8, false)],
+            &[(TokenKind::Unnamed9, false)],
+            &[(TokenKind::_WS, false)],
+            &[(TokenKind::NUM, false)],
+        ],
+    },
+];
+
+/// The tokens that enter a lexer mode, with the index of the mode.
+const PUSHES: &'static [(TokenKind, usize)] = &[];
+/// The tokens that return to the lexer mode entered before the current one.
+const POPS: &'static [TokenKind] = &[];
+
+/// Returns the tokens, since the grammar doesn't track indentation.
+fn add_layout_tokens(tokens: Vec<Token>, text: &str) -> Result<Vec<Token>, ParseError> {
+    Ok(tokens)
+}
+
+/// A description of a small part of a source text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    /// The 'type' of this token; what kind of function this text part serves.
+    pub kind: TokenKind,
+    /// The starting byte index of this token in the source text.
+    pub start: usize,
+    /// The ending (excl) byte index of this token in the source text.
+    pub end: usize,
+}
+impl Token {
+    /// Returns the name of this token in the grammar.
+    pub fn name(&self) -> &'static str {
+        self.kind.name()
+    }
+
+    /// Returns the slice of the source text that this token represents.
+    pub fn slice<'a>(&self, text: &'a str) -> &'a str {
+        &text[self.start..self.end]
+    }
+}
+
+/// Describes the value of a Rule or Token matched and captured by a '$' capture pattern.
+#[derive(Debug, Clone)]
+pub enum Capture {
+    /// A single value that is always present.
+    Single(Box<Match>),
+    /// A value that may or may not be assigned when parsing.
+    Optional(Option<Box<Match>>),
+    /// A set of zero or more values, captured by repetitions.
+    Multiple(Vec<Match>),
+    /// A single token.
+    Token(Token),
+}
+impl Capture {
+    fn assign(&mut self, value: Match) {
+        match *self {
+            Capture::Single(_) => *self = Capture::Single(Box::new(value)),
+            Capture::Optional(None) => *self = Capture::Optional(Some(Box::new(value))),
+            Capture::Optional(Some(_)) => panic!("Optional value assigned twice!"),
+            Capture::Multiple(ref mut values) => values.push(value),
+            Capture::Token(_) => unreachable!(),
+        }
+    }
+}
+
+/// Describes a matched rule, including the values that were captured by it.
+#[derive(Debug, Clone)]
+pub struct Match {
+    /// What rule was matched.
+    pub rule: &'static str,
+    /// The values that were captured.
+    pub captures: Vec<Capture>,
+    /// The starting byte index of this match in the source text.
+    pub start: usize,
+    /// The ending (excl) byte index of this match in the source text.
+    pub end: usize,
+    /// The operator token, if this is a binary operation. The operands are
+    /// then captured as the single values 0 and 1.
+    pub operator: Option<Token>,
+}
+impl Match {
+    fn new(rule: &'static str, captures: Vec<Capture>) -> Match {
+        Match { rule, captures, start: 0, end: 0, operator: None }
+    }
+
+    fn missing() -> Match {
+        Match::new("", Vec::new())
+    }
+
+    fn from_token(token: Token) -> Match {
+        Match { 
+            rule: token.name(), start: token.start, end: token.end, 
+            captures: vec![Capture::Token(token)], operator: None,
+        }
+    }
+
+    /// Accesses a 'single' capture value of this match at the given capture index.
+    pub fn single(&self, index: usize) -> Option<&Match> {
+        match self.captures.get(index) {
+            Some(&Capture::Single(ref value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Accesses an 'optional' capture value of this match at the given capture index.
+    pub fn optional(&self, index: usize) -> Option<Option<&Match>> {
+        match self.captures.get(index) {
+            Some(&Capture::Optional(ref value)) => Some(value.as_ref().map(|b| &**b)),
+            _ => None,
+        }
+    }
+
+    /// Accesses a 'multiple' capture value of this match at the given capture index.
+    pub fn multiple(&self, index: usize) -> Option<&Vec<Match>> {
+        match self.captures.get(index) {
+            Some(&Capture::Multiple(ref values)) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Accesses the token of this match, if it is a token match.
+    pub fn token(&self) -> Option<&Token> {
+        match self.captures.get(0) {
+            Some(&Capture::Token(ref token)) if self.captures.len() == 1 => Some(token),
+            _ => None,
+        }
+    }
+
+    /// Accesses the token in the capture value of this match at the given capture index.
+    pub fn single_token(&self, index: usize) -> Option<&Token> {
+        self.single(index).and_then(|m| m.token())
+    }
+
+    /// Returns the slice of the source text that this match spans.
+    pub fn slice<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.start..self.end]
+    }
+}
+
+/// Describes why a text could not be lexed or parsed.
+#[derive(Debug, Clone)]
+pub enum ParseError {
+    /// No token definition matched the text at the given byte index.
+    Lex { pos: usize },
+    /// The line at the given byte index is indented less than the line 
+    /// before it, but not as much as any enclosing line.
+    Dedent { pos: usize },
+    /// A token was found where the grammar doesn't allow it.
+    UnexpectedToken { found: Token, scope: Vec<&'static str>, expected: Vec<TokenKind> },
+    /// The token stream ended while the parse still expected tokens.
+    UnexpectedEof { scope: Vec<&'static str>, expected: Vec<TokenKind> },
+    /// A loop ('%') pattern reached the end of the tokens without being broken.
+    UnclosedLoop { pos: usize, scope: Vec<&'static str> },
+}
+
+/// Changes the stack of lexer modes after a token of the given kind.
+fn change_mode(modes: &mut Vec<usize>, kind: TokenKind) {
+    if let Some(&(_, mode)) = PUSHES.iter().find(|&&(push, _)| push == kind) {
+        modes.push(mode);
+    } else if POPS.contains(&kind) && modes.len() > 1 {
+        modes.pop();
+    }
+}
+
+/// An automaton that finds the longest token of a lexer mode.
+struct ModeLexer {
+    /// The first char of each range of chars that are read alike.
+    range_starts: &'static [u32],
+    /// The column of each range of chars in the transition table.
+    range_classes: &'static [u32],
+    /// The number of columns in the transition table.
+    nof_classes: usize,
+    /// The next state for each state and column. Lexing starts in state 1,
+    /// and state 0 reads no more chars.
+    transitions: &'static [u32],
+    /// The tokens that each state lexes, best first, with whether they can
+    /// only be followed by a non-letter.
+    accepts: &'static [&'static [(TokenKind, bool)]],
+}
+impl ModeLexer {
+    /// Returns the longest token at the start of the text, and its length.
+    fn longest_match(&self, text: &str) -> Option<(TokenKind, usize)> {
+        let mut state = 1;
+        let mut found = None;
+        for (index, ch) in text.char_indices() {
+            let range = match self.range_starts.binary_search(&(ch as u32)) {
+                Ok(range) => range,
+                Err(range) => range - 1,
+            };
+            state = self.transitions[state * self.nof_classes + self.range_classes[range] as usize] as usize;
+            if state == 0 {
+                break;
+            }
+            let end = index + ch.len_utf8();
+            let letter_follows = text[end..].chars().next().map_or(false, |ch| ch.is_alphabetic());
+            if let Some(&(kind, _)) = self.accepts[state].iter().find(|&&(_, alpha)| ! (alpha && letter_follows)) {
+                found = Some((kind, end));
+            }
+        }
+        found
+    }
+}
+
+/// Splits the given text into tokens, lexing the longest token at each 
+/// position.
+pub fn lex(text: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut modes = vec![0];
+    let mut start = 0;
+    while start < text.len() {
+        let kind = match MODES[*modes.last().unwrap()].longest_match(&text[start..]) {
+            Some((kind, len)) => {
+                if ! kind.is_skipped() {
+                    tokens.push(Token { kind, start, end: start + len });
+                }
+                start += len;
+                kind
+            }
+            None => return Err(ParseError::Lex { pos: start }),
+        };
+        change_mode(&mut modes, kind);
+    }
+    add_layout_tokens(tokens, text)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    last_end: usize,
+    scope: Vec<&'static str>,
+    /// The tokens expected by the optional patterns that were skipped since
+    /// the last token was read, which are expected by errors as well.
+    skipped: Vec<&'static [TokenKind]>,
+}
+impl Parser {
+    fn new(mut tokens: Vec<Token>, text: &str) -> Parser {
+        tokens.push(Token { kind: TokenKind::EOF, start: text.len(), end: text.len() });
+        Parser { tokens, pos: 0, last_end: 0, scope: Vec::new(), skipped: Vec::new() }
+    }
+
+    fn peek_kind(&self) -> Option<TokenKind> {
+        self.tokens.get(self.pos).map(|token| token.kind)
+    }
+
+    fn peeks(&self, kinds: &[TokenKind]) -> bool {
+        self.peek_kind().map_or(false, |kind| kinds.contains(&kind))
+    }
+
+    fn can_consume(&mut self, kinds: &[TokenKind], expected: &'static [TokenKind]) -> bool {
+        let consumes = self.peeks(kinds);
+        if ! consumes {
+            self.skipped.push(expected);
+        }
+        consumes
+    }
+
+    fn start(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.last_end, |token| token.start)
+    }
+
+    fn end(&self, start: usize) -> usize {
+        if self.last_end > start { self.last_end } else { start }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        if let Some(ref token) = token {
+            self.pos += 1;
+            self.last_end = token.end;
+            self.skipped.clear();
+        }
+        token
+    }
+
+    fn expect(&mut self, kind: TokenKind, expected: &[TokenKind]) -> Result<Token, ParseError> {
+        match self.peek_kind() {
+            Some(found) if found != kind => Err(self.unexpected(expected)),
+            Some(_) => Ok(self.next().unwrap()),
+            None => Err(self.eof(expected)),
+        }
+    }
+
+    fn expected(&self, expected: &[TokenKind]) -> Vec<TokenKind> {
+        let mut kinds = Vec::new();
+        for &kind in self.skipped.iter().flat_map(|skipped| skipped.iter()).chain(expected) {
+            if ! kinds.contains(&kind) {
+                kinds.push(kind);
+            }
+        }
+        kinds
+    }
+
+    fn unexpected(&self, expected: &[TokenKind]) -> ParseError {
+        ParseError::UnexpectedToken {
+            found: self.tokens[self.pos].clone(),
+            scope: self.scope.clone(),
+            expected: self.expected(expected),
+        }
+    }
+
+    fn eof(&self, expected: &[TokenKind]) -> ParseError {
+        ParseError::UnexpectedEof { scope: self.scope.clone(), expected: self.expected(expected) }
+    }
+
+    fn unclosed_loop(&self, pos: usize) -> ParseError {
+        ParseError::UnclosedLoop { pos, scope: self.scope.clone() }
+    }
+}
+
+/// Lexes and parses the text, starting with the rule 'array'.
+pub fn parse_array(text: &str) -> Result<Match, ParseError> {
+    let mut p = Parser::new(lex(text)?, text);
+    rule_array(&mut p)
+}
+
+/// Lexes and parses the text, starting with the rule 'lists'.
+pub fn parse_lists(text: &str) -> Result<Match, ParseError> {
+    let mut p = Parser::new(lex(text)?, text);
+    rule_lists(&mut p)
+}
+
+/// Lexes and parses the text, starting with the rule 'set'.
+pub fn parse_set(text: &str) -> Result<Match, ParseError> {
+    let mut p = Parser::new(lex(text)?, text);
+    rule_set(&mut p)
+}
+
+/// Lexes and parses the text, starting with the rule 'tuple'.
+pub fn parse_tuple(text: &str) -> Result<Match, ParseError> {
+    let mut p = Parser::new(lex(text)?, text);
+    rule_tuple(&mut p)
+}
+
+fn rule_array(p: &mut Parser) -> Result<Match, ParseError> {
+    let mut mtc = Match::new("array", vec![Capture::Multiple(Vec::new())]);
+    mtc.start = p.start();
+    p.scope.push("array");
+    pat_array(p, &mut mtc.captures)?;
+    p.scope.pop();
+    mtc.end = p.end(mtc.start);
+    Ok(mtc)
+}
+
+fn pat_array(p: &mut Parser, caps: &mut Vec<Capture>) -> Result<bool, ParseError> {
+    p.expect(TokenKind::Unnamed6, &[TokenKind::Unnamed6])?;
+    loop {
+        caps[0].assign(Match::from_token(p.expect(TokenKind::NUM, &[TokenKind::NUM])?));
+        if ! p.can_consume(&[TokenKind::Unnamed4], &[TokenKind::Unnamed4]) {
+            break;
+        }
+        p.expect(TokenKind::Unnamed4, &[TokenKind::Unnamed4])?;
+        if ! p.can_consume(&[TokenKind::NUM], &[TokenKind::NUM]) {
+            break;
+        }
+    }
+    p.expect(TokenKind::Unnamed7, &[TokenKind::Unnamed7])?;
+    Ok(false)
+}
+
+fn rule_lists(p: &mut Parser) -> Result<Match, ParseError> {
+    let mut mtc = Match::new("lists", vec![Capture::Multiple(Vec::new())]);
+    mtc.start = p.start();
+    p.scope.push("lists");
+    pat_lists(p, &mut mtc.captures)?;
+    p.scope.pop();
+    mtc.end = p.end(mtc.start);
+    Ok(mtc)
+}
+
+fn pat_lists(p: &mut Parser, caps: &mut Vec<Capture>) -> Result<bool, ParseError> {
+    while p.can_consume(&[TokenKind::Unnamed3, TokenKind::Unnamed6, TokenKind::Unnamed8], &[TokenKind::Unnamed3, TokenKind::Unnamed6, TokenKind::Unnamed8]) {
+        match p.peek_kind() {
+            None => return Err(p.eof(&[TokenKind::Unnamed3, TokenKind::Unnamed6, TokenKind::Unnamed8])),
+            Some(TokenKind::Unnamed3) => {
+                caps[0].assign(rule_tuple(p)?);
+            }
+            Some(TokenKind::Unnamed6) => {
+                caps[0].assign(rule_array(p)?);
+            }
+            Some(TokenKind::Unnamed8) => {
+                caps[0].assign(rule_set(p)?);
+            }
+            Some(_) => return Err(p.unexpected(&[TokenKind::Unnamed3, TokenKind::Unnamed6, TokenKind::Unnamed8])),
+        }
+    }
+    p.expect(TokenKind::EOF, &[TokenKind::EOF])?;
+    Ok(false)
+}
+
+fn rule_set(p: &mut Parser) -> Result<Match, ParseError> {
+    let mut mtc = Match::new("set", vec![Capture::Multiple(Vec::new())]);
+    mtc.start = p.start();
+    p.scope.push("set");
+    pat_set(p, &mut mtc.captures)?;
+    p.scope.pop();
+    mtc.end = p.end(mtc.start);
+    Ok(mtc)
+}
+
+fn pat_set(p: &mut Parser, caps: &mut Vec<Capture>) -> Result<bool, ParseError> {
+    p.expect(TokenKind::Unnamed8, &[TokenKind::Unnamed8])?;
+    if p.can_consume(&[TokenKind::NUM], &[TokenKind::NUM]) {
+        loop {
+            caps[0].assign(Match::from_token(p.expect(TokenKind::NUM, &[TokenKind::NUM])?));
+            if ! p.can_consume(&[TokenKind::Unnamed4], &[TokenKind::Unnamed4]) {
+                break;
+            }
+            p.expect(TokenKind::Unnamed4, &[TokenKind::Unnamed4])?;
+            if ! p.can_consume(&[TokenKind::NUM], &[TokenKind::NUM]) {
+                break;
+            }
+        }
+    }
+    p.expect(TokenKind::Unnamed9, &[TokenKind::Unnamed9])?;
+    Ok(false)
+}
+
+fn rule_tuple(p: &mut Parser) -> Result<Match, ParseError> {
+    let mut mtc = Match::new("tuple", vec![Capture::Multiple(Vec::new())]);
+    mtc.start = p.start();
+    p.scope.push("tuple");
+    pat_tuple(p, &mut mtc.captures)?;
+    p.scope.pop();
+    mtc.end = p.end(mtc.start);
+    Ok(mtc)
+}
+
+fn pat_tuple(p: &mut Parser, caps: &mut Vec<Capture>) -> Result<bool, ParseError> {
+    p.expect(TokenKind::Unnamed3, &[TokenKind::Unnamed3])?;
+    loop {
+        caps[0].assign(Match::from_token(p.expect(TokenKind::NUM, &[TokenKind::NUM])?));
+        if ! p.can_consume(&[TokenKind::Unnamed4], &[TokenKind::Unnamed4]) {
+            break;
+        }
+        p.expect(TokenKind::Unnamed4, &[TokenKind::Unnamed4])?;
+    }
+    p.expect(TokenKind::Unnamed5, &[TokenKind::Unnamed5])?;
+    Ok(false)
+}
@@ -0,0 +1,165 @@
+//! Typed syntax trees generated by heck. Do not edit by hand.
+#![allow(dead_code, unused_imports, unused_variables)]
+
+use heck::{Match, Token};
+
+/// The 'aot_scope' rule.
+#[derive(Debug, Clone)]
+pub struct AotScope {
+    pub key: Vec<Key>,
+}
+impl<'a> From<&'a Match> for AotScope {
+    fn from(m: &'a Match) -> AotScope {
+        AotScope {
+            key: m.multiple(0).unwrap().iter().map(|m| Key::from(m)).collect(),
+        }
+    }
+}
+
+/// The 'array' rule.
+#[derive(Debug, Clone)]
+pub struct Array {
+    pub expr: Vec<Expr>,
+}
+impl<'a> From<&'a Match> for Array {
+    fn from(m: &'a Match) -> Array {
+        Array {
+            expr: m.multiple(0).unwrap().iter().map(|m| Expr::from(m)).collect(),
+        }
+    }
+}
+
+/// A value captured by 'items' in the 'document' rule.
+#[derive(Debug, Clone)]
+pub enum DocumentItems {
+    Entry(Box<Entry>),
+    AotScope(Box<AotScope>),
+    TableScope(Box<TableScope>),
+}
+impl<'a> From<&'a Match> for DocumentItems {
+    fn from(m: &'a Match) -> DocumentItems {
+        match &m.rule[..] {
+            "entry" => DocumentItems::Entry(Box::new(Entry::from(m))),
+            "aot_scope" => DocumentItems::AotScope(Box::new(AotScope::from(m))),
+            "table_scope" => DocumentItems::TableScope(Box::new(TableScope::from(m))),
+            rule => panic!("Unexpected match of {:?}", rule),
+        }
+    }
+}
+
+/// The 'document' rule.
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub items: Vec<DocumentItems>,
+}
+impl<'a> From<&'a Match> for Document {
+    fn from(m: &'a Match) -> Document {
+        Document {
+            items: m.multiple(0).unwrap().iter().map(|m| DocumentItems::from(m)).collect(),
+        }
+    }
+}
+
+/// The 'end' rule.
+#[derive(Debug, Clone)]
+pub struct End;
+impl<'a> From<&'a Match> for End {
+    fn from(m: &'a Match) -> End {
+        End
+    }
+}
+
+/// The 'endl' rule.
+#[derive(Debug, Clone)]
+pub struct Endl;
+impl<'a> From<&'a Match> for Endl {
+    fn from(m: &'a Match) -> Endl {
+        Endl
+    }
+}
+
+/// The 'entry' rule.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub key: Box<Key>,
+    pub expr: Box<Expr>,
+}
+impl<'a> From<&'a Match> for Entry {
+    fn from(m: &'a Match) -> Entry {
+        Entry {
+            key: Box::new(Key::from(m.single(0).unwrap())),
+            expr: Box::new(Expr::from(m.single(1).unwrap())),
+        }
+    }
+}
+
+/// The 'expr' rule.
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Int(Token),
+    Float(Token),
+    String(Token),
+    Array(Box<Array>),
+    InlineTable(Box<InlineTable>),
+    True(Token),
+    False(Token),
+}
+impl<'a> From<&'a Match> for Expr {
+    fn from(m: &'a Match) -> Expr {
+        let m = m.single(0).unwrap();
+        match &m.rule[..] {
+            "INT" => Expr::Int(m.token().unwrap().clone()),
+            "FLOAT" => Expr::Float(m.token().unwrap().clone()),
+            "STRING" => Expr::String(m.token().unwrap().clone()),
+            "array" => Expr::Array(Box::new(Array::from(m))),
+            "inline_table" => Expr::InlineTable(Box::new(InlineTable::from(m))),
+            "TRUE" => Expr::True(m.token().unwrap().clone()),
+            "FALSE" => Expr::False(m.token().unwrap().clone()),
+            rule => panic!("Unexpected match of {:?}", rule),
+        }
+    }
+}
+
+/// The 'inline_table' rule.
+#[derive(Debug, Clone)]
+pub struct InlineTable {
+    pub entry: Vec<Entry>,
+}
+impl<'a> From<&'a Match> for InlineTable {
+    fn from(m: &'a Match) -> InlineTable {
+        InlineTable {
+            entry: m.multiple(0).unwrap().iter().map(|m| Entry::from(m)).collect(),
+        }
+    }
+}
+
+/// The 'key' rule.
+#[derive(Debug, Clone)]
+pub enum Key {
+    Key(Token),
+    String(Token),
+}
+impl<'a> From<&'a Match> for Key {
+    fn from(m: &'a Match) -> Key {
+        let m = m.single(0).unwrap();
+        match &m.rule[..] {
+            "KEY" => Key::Key(m.token().unwrap().clone()),
+            "STRING" => Key::String(m.token().unwrap().clone()),
+            rule => panic!("Unexpected match of {:?}", rule),
+        }
+    }
+}
+
+/// The 'table_scope' rule.
+#[derive(Debug, Clone)]
+pub struct TableScope {
+    pub key: Vec<Key>,
+}
+impl<'a> From<&'a Match> for TableScope {
+    fn from(m: &'a Match) -> TableScope {
+        TableScope {
+            key: m.multiple(0).unwrap().iter().map(|m| Key::from(m)).collect(),
+        }
+    }
+}
+
@@ -0,0 +1,847 @@
+//! A lexer and parser generated by heck. Do not edit by hand.
+#![allow(dead_code, unused_mut, unused_variables, unreachable_code, 
+    unreachable_patterns, non_camel_case_types)]
+
+/// The kinds of tokens in the grammar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    /// "EOF"
+    EOF,
+    /// "_SPACE"
+    _SPACE,
+    /// "_TAB"
+    _TAB,
+    /// "_COMMENT"
+    _COMMENT,
+    /// "TRUE"
+    TRUE,
+    /// "FALSE"
+    FALSE,
+    /// "NEWLINE"
+    NEWLINE,
+    /// "STRING"
+    STRING,
+    /// "KEY"
+    KEY,
+    /// "FLOAT"
+    FLOAT,
+    /// "INT"
+    INT,
+    /// "["
+    Unnamed11,
+    /// "."
+    Unnamed12,
+    /// "]"
+    Unnamed13,
+    /// "[["
+    Unnamed14,
+    /// "]]"
+    Unnamed15,
+    /// ","
+    Unnamed16,
+    /// "="
+    Unnamed17,
+    /// "{"
+    Unnamed18,
+    /// "}"
+    Unnamed19,
+}
+impl TokenKind {
+    /// Returns the name of the token in the grammar.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            TokenKind::EOF => "EOF",
+            TokenKind::_SPACE => "_SPACE",
+            TokenKind::_TAB => "_TAB",
+            TokenKind::_COMMENT => "_COMMENT",
+            TokenKind::TRUE => "TRUE",
+            TokenKind::FALSE => "FALSE",
+            TokenKind::NEWLINE => "NEWLINE",
+            TokenKind::STRING => "STRING",
+            TokenKind::KEY => "KEY",
+            TokenKind::FLOAT => "FLOAT",
+            TokenKind::INT => "INT",
+            TokenKind::Unnamed11 => "[",
+            TokenKind::Unnamed12 => ".",
+            TokenKind::Unnamed13 => "]",
+            TokenKind::Unnamed14 => "[[",
+            TokenKind::Unnamed15 => "]]",
+            TokenKind::Unnamed16 => ",",
+            TokenKind::Unnamed17 => "=",
+            TokenKind::Unnamed18 => "{",
+            TokenKind::Unnamed19 => "}",
+        }
+    }
+
+    /// Returns whether tokens of this kind are left out by the lexer.
+    pub fn is_skipped(&self) -> bool {
+        match *self {
+            TokenKind::_SPACE => true,
+            TokenKind::_TAB => true,
+            TokenKind::_COMMENT => true,
+            _ => false,
+        }
+    }
+}
+
+/// The automaton of each lexer mode, starting with the default mode.
+const MODES: &'static [ModeLexer] = &[
+    // default
+    ModeLexer {
+        range_starts: &[0, 9, 10, 11, 13, 14, 32, 33, 34, 35, 36, 43, 44, 45, 46, 47, 48, 58, 61, 62, 65, 91, 92, 93, 94, 95, 96, 97, 98, 101, 102, 103, 108, 109, 114, 115, 116, 117, 118, 123, 124, 125, 126],
+        range_classes: &[0, 1, 2, 0, 3, 0, 4, 0, 5, 6, 0, 7, 8, 9, 10, 0, 11, 0, 12, 0, 13, 14, 0, 15, 0, 16, 0, 17, 13, 18, 19, 13, 20, 13, 21, 22, 23, 24, 13, 25, 0, 26, 0],
+        nof_classes: 27,
+        transitions: &[
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 0, 12, 13, 14, 15, 13, 13, 13, 16, 13, 13, 13, 17, 13, 18, 19,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            21, 21, 21, 21, 21, 22, 21, 21, 21, 21, 23, 21, 21, 21, 21, 21, 21, 21, 21, 21, 21, 21, 21, 21, 21, 21, 21,
+            24, 24, 0, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 25, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 25, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 26, 0, 26, 0, 26, 0, 0, 26, 26, 26, 26, 26, 26, 26, 26, 26, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 27, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 28, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 26, 0, 26, 0, 26, 0, 0, 26, 29, 26, 26, 26, 26, 26, 26, 26, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 26, 0, 26, 0, 26, 0, 0, 26, 26, 26, 26, 26, 30, 26, 26, 26, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            21, 21, 21, 21, 21, 22, 21, 21, 21, 21, 23, 21, 21, 21, 21, 21, 21, 21, 21, 21, 21, 21, 21, 21, 21, 21, 21,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            21, 21, 21, 21, 21, 22, 21, 21, 21, 21, 23, 21, 21, 21, 21, 21, 21, 21, 21, 21, 21, 21, 21, 21, 21, 21, 21,
+            24, 24, 0, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 31, 32, 0, 0, 0, 0, 33, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 26, 0, 26, 0, 26, 0, 0, 26, 26, 26, 26, 26, 26, 26, 26, 26, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 26, 0, 26, 0, 26, 0, 0, 26, 26, 26, 26, 34, 26, 26, 26, 26, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 26, 0, 26, 0, 26, 0, 0, 26, 26, 26, 26, 26, 26, 26, 26, 35, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 36, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 31, 32, 0, 0, 0, 0, 33, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 32, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 26, 0, 26, 0, 26, 0, 0, 26, 26, 26, 26, 26, 26, 37, 26, 26, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 26, 0, 26, 0, 26, 0, 0, 26, 26, 38, 26, 26, 26, 26, 26, 26, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 39, 0, 0, 0, 0, 40, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 26, 0, 26, 0, 26, 0, 0, 26, 26, 41, 26, 26, 26, 26, 26, 26, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 26, 0, 26, 0, 26, 0, 0, 26, 26, 26, 26, 26, 26, 26, 26, 26, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 39, 0, 0, 0, 0, 40, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 39, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 26, 0, 26, 0, 26, 0, 0, 26, 26, 26, 26, 26, 26, 26, 26, 26, 0, 0,
+        ],
+        accepts: &[
+            &[],
+            &[],
+            &[(TokenKind::_TAB, false)],
+            &[(TokenKind::NEWLINE, false)],
+            &[],
+            &[(TokenKind::_SPACE, false)],
+            &[],
+            &[(TokenKind::_COMMENT, false)],
+            &[],
+            &[(TokenKind::Unnamed16, false)],
+            &[],
+            &[(TokenKind::Unnamed12, false)],
+            &[(TokenKind::Unnamed17, false)],
+            &[(TokenKind::KEY, false)],
+            &[(TokenKind::Unnamed11, false)],
+            &[(TokenKind::Unnamed13, false)],
+            &[(TokenKind::KEY, false)],
+            &[(TokenKind::KEY, false)],
+            &[(TokenKind::Unnamed18, false)],
+            &[(TokenKind::Unnamed19, false)],
+            &[(TokenKind::NEWLINE, false)],
+            &[],
+            &[(TokenKind::STRING, false)],
+            &[],
+            &[(TokenKind::_COMMENT, false)],
+            &[(TokenKind::INT, false)],
+            &[(TokenKind::KEY, false)],
+            &[(TokenKind::Unnamed14, false)],
+            &[(TokenKind::Unnamed15, false)],
+            &[(TokenKind::KEY, false)],
+            &[(TokenKind::KEY, false)],
+            &[(TokenKind::FLOAT, false)],
+            &[(TokenKind::INT, false)],
+            &[],
+            &[(TokenKind::KEY, false)],
+            &[(TokenKind::KEY, false)],
+            &[(TokenKind::FLOAT, false)],
+            &[(TokenKind::KEY, false)],
+            &[(TokenKind::TRUE, true), (TokenKind::KEY, false)],
+            &[(TokenKind::FLOAT, false)],
+            &[],
+            &[(TokenKind::FALSE, true), (TokenKind::KEY, false)],
+        ],
+    },
+];
+
+/// The tokens that enter a lexer mode, with the index of the mode.
+const PUSHES: &'static [(TokenKind, usize)] = &[];
+/// The tokens that return to the lexer mode entered before the current one.
+const POPS: &'static [TokenKind] = &[];
+
+/// Returns the tokens, since the grammar doesn't track indentation.
+fn add_layout_tokens(tokens: Vec<Token>, text: &str) -> Result<Vec<Token>, ParseError> {
+    Ok(tokens)
+}
+
+/// A description of a small part of a source text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    /// The 'type' of this token; what kind of function this text part serves.
+    pub kind: TokenKind,
+    /// The starting byte index of this token in the source text.
+    pub start: usize,
+    /// The ending (excl) byte index of this token in the source text.
+    pub end: usize,
+}
+impl Token {
+    /// Returns the name of this token in the grammar.
+    pub fn name(&self) -> &'static str {
+        self.kind.name()
+    }
+
+    /// Returns the slice of the source text that this token represents.
+    pub fn slice<'a>(&self, text: &'a str) -> &'a str {
+        &text[self.start..self.end]
+    }
+}
+
+/// Describes the value of a Rule or Token matched and captured by a '$' capture pattern.
+#[derive(Debug, Clone)]
+pub enum Capture {
+    /// A single value that is always present.
+    Single(Box<Match>),
+    /// A value that may or may not be assigned when parsing.
+    Optional(Option<Box<Match>>),
+    /// A set of zero or more values, captured by repetitions.
+    Multiple(Vec<Match>),
+    /// A single token.
+    Token(Token),
+}
+impl Capture {
+    fn assign(&mut self, value: Match) {
+        match *self {
+            Capture::Single(_) => *self = Capture::Single(Box::new(value)),
+            Capture::Optional(None) => *self = Capture::Optional(Some(Box::new(value))),
+            Capture::Optional(Some(_)) => panic!("Optional value assigned twice!"),
+            Capture::Multiple(ref mut values) => values.push(value),
+            Capture::Token(_) => unreachable!(),
+        }
+    }
+}
+
+/// Describes a matched rule, including the values that were captured by it.
+#[derive(Debug, Clone)]
+pub struct Match {
+    /// What rule was matched.
+    pub rule: &'static str,
+    /// The values that were captured.
+    pub captures: Vec<Capture>,
+    /// The starting byte index of this match in the source text.
+    pub start: usize,
+    /// The ending (excl) byte index of this match in the source text.
+    pub end: usize,
+    /// The operator token, if this is a binary operation. The operands are
+    /// then captured as the single values 0 and 1.
+    pub operator: Option<Token>,
+}
+impl Match {
+    fn new(rule: &'static str, captures: Vec<Capture>) -> Match {
+        Match { rule, captures, start: 0, end: 0, operator: None }
+    }
+
+    fn missing() -> Match {
+        Match::new("", Vec::new())
+    }
+
+    fn from_token(token: Token) -> Match {
+        Match { 
+            rule: token.name(), start: token.start, end: token.end, 
+            captures: vec![Capture::Token(token)], operator: None,
+        }
+    }
+
+    /// Accesses a 'single' capture value of this match at the given capture index.
+    pub fn single(&self, index: usize) -> Option<&Match> {
+        match self.captures.get(index) {
+            Some(&Capture::Single(ref value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Accesses an 'optional' capture value of this match at the given capture index.
+    pub fn optional(&self, index: usize) -> Option<Option<&Match>> {
+        match self.captures.get(index) {
+            Some(&Capture::Optional(ref value)) => Some(value.as_ref().map(|b| &**b)),
+            _ => None,
+        }
+    }
+
+    /// Accesses a 'multiple' capture value of this match at the given capture index.
+    pub fn multiple(&self, index: usize) -> Option<&Vec<Match>> {
+        match self.captures.get(index) {
+            Some(&Capture::Multiple(ref values)) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Accesses the token of this match, if it is a token match.
+    pub fn token(&self) -> Option<&Token> {
+        match self.captures.get(0) {
+            Some(&Capture::Token(ref token)) if self.captures.len() == 1 => Some(token),
+            _ => None,
+        }
+    }
+
+    /// Accesses the token in the capture value of this match at the given capture index.
+    pub fn single_token(&self, index: usize) -> Option<&Token> {
+        self.single(index).and_then(|m| m.token())
+    }
+
+    /// Returns the slice of the source text that this match spans.
+    pub fn slice<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.start..self.end]
+    }
+}
+
+/// Describes why a text could not be lexed or parsed.
+#[derive(Debug, Clone)]
+pub enum ParseError {
+    /// No token definition matched the text at the given byte index.
+    Lex { pos: usize },
+    /// The line at the given byte index is indented less than the line 
+    /// before it, but not as much as any enclosing line.
+    Dedent { pos: usize },
+    /// A token was found where the grammar doesn't allow it.
+    UnexpectedToken { found: Token, scope: Vec<&'static str>, expected: Vec<TokenKind> },
+    /// The token stream ended while the parse still expected tokens.
+    UnexpectedEof { scope: Vec<&'static str>, expected: Vec<TokenKind> },
+    /// A loop ('%') pattern reached the end of the tokens without being broken.
+    UnclosedLoop { pos: usize, scope: Vec<&'static str> },
+}
+
+/// Changes the stack of lexer modes after a token of the given kind.
+fn change_mode(modes: &mut Vec<usize>, kind: TokenKind) {
+    if let Some(&(_, mode)) = PUSHES.iter().find(|&&(push, _)| push == kind) {
+        modes.push(mode);
+    } else if POPS.contains(&kind) && modes.len() > 1 {
+        modes.pop();
+    }
+}
+
+/// An automaton that finds the longest token of a lexer mode.
+struct ModeLexer {
+    /// The first char of each range of chars that are read alike.
+    range_starts: &'static [u32],
+    /// The column of each range of chars in the transition table.
+    range_classes: &'static [u32],
+    /// The number of columns in the transition table.
+    nof_classes: usize,
+    /// The next state for each state and column. Lexing starts in state 1,
+    /// and state 0 reads no more chars.
+    transitions: &'static [u32],
+    /// The tokens that each state lexes, best first, with whether they can
+    /// only be followed by a non-letter.
+    accepts: &'static [&'static [(TokenKind, bool)]],
+}
+impl ModeLexer {
+    /// Returns the longest token at the start of the text, and its length.
+    fn longest_match(&self, text: &str) -> Option<(TokenKind, usize)> {
+        let mut state = 1;
+        let mut found = None;
+        for (index, ch) in text.char_indices() {
+            let range = match self.range_starts.binary_search(&(ch as u32)) {
+                Ok(range) => range,
+                Err(range) => range - 1,
+            };
+            state = self.transitions[state * self.nof_classes + self.range_classes[range] as usize] as usize;
+            if state == 0 {
+                break;
+            }
+            let end = index + ch.len_utf8();
+            let letter_follows = text[end..].chars().next().map_or(false, |ch| ch.is_alphabetic());
+            if let Some(&(kind, _)) = self.accepts[state].iter().find(|&&(_, alpha)| ! (alpha && letter_follows)) {
+                found = Some((kind, end));
+            }
+        }
+        found
+    }
+}
+
+/// Splits the given text into tokens, lexing the longest token at each 
+/// position.
+pub fn lex(text: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut modes = vec![0];
+    let mut start = 0;
+    while start < text.len() {
+        let kind = match MODES[*modes.last().unwrap()].longest_match(&text[start..]) {
+            Some((kind, len)) => {
+                if ! kind.is_skipped() {
+                    tokens.push(Token { kind, start, end: start + len });
+                }
+                start += len;
+                kind
+            }
+            None => return Err(ParseError::Lex { pos: start }),
+        };
+        change_mode(&mut modes, kind);
+    }
+    add_layout_tokens(tokens, text)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    last_end: usize,
+    scope: Vec<&'static str>,
+    /// The tokens expected by the optional patterns that were skipped since
+    /// the last token was read, which are expected by errors as well.
+    skipped: Vec<&'static [TokenKind]>,
+}
+impl Parser {
+    fn new(mut tokens: Vec<Token>, text: &str) -> Parser {
+        tokens.push(Token { kind: TokenKind::EOF, start: text.len(), end: text.len() });
+        Parser { tokens, pos: 0, last_end: 0, scope: Vec::new(), skipped: Vec::new() }
+    }
+
+    fn peek_kind(&self) -> Option<TokenKind> {
+        self.tokens.get(self.pos).map(|token| token.kind)
+    }
+
+    fn peeks(&self, kinds: &[TokenKind]) -> bool {
+        self.peek_kind().map_or(false, |kind| kinds.contains(&kind))
+    }
+
+    fn can_consume(&mut self, kinds: &[TokenKind], expected: &'static [TokenKind]) -> bool {
+        let consumes = self.peeks(kinds);
+        if ! consumes {
+            self.skipped.push(expected);
+        }
+        consumes
+    }
+
+    fn start(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.last_end, |token| token.start)
+    }
+
+    fn end(&self, start: usize) -> usize {
+        if self.last_end > start { self.last_end } else { start }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        if let Some(ref token) = token {
+            self.pos += 1;
+            self.last_end = token.end;
+            self.skipped.clear();
+        }
+        token
+    }
+
+    fn expect(&mut self, kind: TokenKind, expected: &[TokenKind]) -> Result<Token, ParseError> {
+        match self.peek_kind() {
+            Some(found) if found != kind => Err(self.unexpected(expected)),
+            Some(_) => Ok(self.next().unwrap()),
+            None => Err(self.eof(expected)),
+        }
+    }
+
+    fn expected(&self, expected: &[TokenKind]) -> Vec<TokenKind> {
+        let mut kinds = Vec::new();
+        for &kind in self.skipped.iter().flat_map(|skipped| skipped.iter()).chain(expected) {
+            if ! kinds.contains(&kind) {
+                kinds.push(kind);
+            }
+        }
+        kinds
+    }
+
+    fn unexpected(&self, expected: &[TokenKind]) -> ParseError {
+        ParseError::UnexpectedToken {
+            found: self.tokens[self.pos].clone(),
+            scope: self.scope.clone(),
+            expected: self.expected(expected),
+        }
+    }
+
+    fn eof(&self, expected: &[TokenKind]) -> ParseError {
+        ParseError::UnexpectedEof { scope: self.scope.clone(), expected: self.expected(expected) }
+    }
+
+    fn unclosed_loop(&self, pos: usize) -> ParseError {
+        ParseError::UnclosedLoop { pos, scope: self.scope.clone() }
+    }
+}
+
+/// Lexes and parses the text, starting with the rule 'aot_scope'.
+pub fn parse_aot_scope(text: &str) -> Result<Match, ParseError> {
+    let mut p = Parser::new(lex(text)?, text);
+    rule_aot_scope(&mut p)
+}
+
+/// Lexes and parses the text, starting with the rule 'array'.
+pub fn parse_array(text: &str) -> Result<Match, ParseError> {
+    let mut p = Parser::new(lex(text)?, text);
+    rule_array(&mut p)
+}
+
+/// Lexes and parses the text, starting with the rule 'document'.
+pub fn parse_document(text: &str) -> Result<Match, ParseError> {
+    let mut p = Parser::new(lex(text)?, text);
+    rule_document(&mut p)
+}
+
+/// Lexes and parses the text, starting with the rule 'end'.
+pub fn parse_end(text: &str) -> Result<Match, ParseError> {
+    let mut p = Parser::new(lex(text)?, text);
+    rule_end(&mut p)
+}
+
+/// Lexes and parses the text, starting with the rule 'endl'.
+pub fn parse_endl(text: &str) -> Result<Match, ParseError> {
+    let mut p = Parser::new(lex(text)?, text);
+    rule_endl(&mut p)
+}
+
+/// Lexes and parses the text, starting with the rule 'entry'.
+pub fn parse_entry(text: &str) -> Result<Match, ParseError> {
+    let mut p = Parser::new(lex(text)?, text);
+    rule_entry(&mut p)
+}
+
+/// Lexes and parses the text, starting with the rule 'expr'.
+pub fn parse_expr(text: &str) -> Result<Match, ParseError> {
+    let mut p = Parser::new(lex(text)?, text);
+    rule_expr(&mut p)
+}
+
+/// Lexes and parses the text, starting with the rule 'inline_table'.
+pub fn parse_inline_table(text: &str) -> Result<Match, ParseError> {
+    let mut p = Parser::new(lex(text)?, text);
+    rule_inline_table(&mut p)
+}
+
+/// Lexes and parses the text, starting with the rule 'key'.
+pub fn parse_key(text: &str) -> Result<Match, ParseError> {
+    let mut p = Parser::new(lex(text)?, text);
+    rule_key(&mut p)
+}
+
+/// Lexes and parses the text, starting with the rule 'table_scope'.
+pub fn parse_table_scope(text: &str) -> Result<Match, ParseError> {
+    let mut p = Parser::new(lex(text)?, text);
+    rule_table_scope(&mut p)
+}
+
+fn rule_aot_scope(p: &mut Parser) -> Result<Match, ParseError> {
+    let mut mtc = Match::new("aot_scope", vec![Capture::Multiple(Vec::new())]);
+    mtc.start = p.start();
+    p.scope.push("aot_scope");
+    pat_aot_scope(p, &mut mtc.captures)?;
+    p.scope.pop();
+    mtc.end = p.end(mtc.start);
+    Ok(mtc)
+}
+
+fn pat_aot_scope(p: &mut Parser, caps: &mut Vec<Capture>) -> Result<bool, ParseError> {
+    p.expect(TokenKind::Unnamed14, &[TokenKind::Unnamed14])?;
+    caps[0].assign(rule_key(p)?);
+    while p.can_consume(&[TokenKind::Unnamed12], &[TokenKind::Unnamed12]) {
+        p.expect(TokenKind::Unnamed12, &[TokenKind::Unnamed12])?;
+        caps[0].assign(rule_key(p)?);
+    }
+    p.expect(TokenKind::Unnamed15, &[TokenKind::Unnamed15])?;
+    Ok(false)
+}
+
+fn rule_array(p: &mut Parser) -> Result<Match, ParseError> {
+    let mut mtc = Match::new("array", vec![Capture::Multiple(Vec::new())]);
+    mtc.start = p.start();
+    p.scope.push("array");
+    pat_array(p, &mut mtc.captures)?;
+    p.scope.pop();
+    mtc.end = p.end(mtc.start);
+    Ok(mtc)
+}
+
+fn pat_array(p: &mut Parser, caps: &mut Vec<Capture>) -> Result<bool, ParseError> {
+    p.expect(TokenKind::Unnamed11, &[TokenKind::Unnamed11])?;
+    while p.can_consume(&[TokenKind::NEWLINE], &[TokenKind::NEWLINE]) {
+        rule_endl(p)?;
+    }
+    if p.peeks(&[TokenKind::Unnamed13]) {
+        p.next();
+        return Ok(true);
+    }
+    p.skipped.push(&[TokenKind::Unnamed13]);
+    caps[0].assign(rule_expr(p)?);
+    if p.peeks(&[TokenKind::Unnamed13]) {
+        p.next();
+        return Ok(true);
+    }
+    p.skipped.push(&[TokenKind::Unnamed13]);
+    let start = match p.peek_kind() {
+        Some(_) => p.start(),
+        None => return Err(p.eof(&[TokenKind::Unnamed16])),
+    };
+    while p.peek_kind().is_some() {
+        p.expect(TokenKind::Unnamed16, &[TokenKind::Unnamed16])?;
+        while p.can_consume(&[TokenKind::NEWLINE], &[TokenKind::NEWLINE]) {
+            rule_endl(p)?;
+        }
+        if p.peeks(&[TokenKind::Unnamed13]) {
+            p.next();
+            return Ok(true);
+        }
+        p.skipped.push(&[TokenKind::Unnamed13]);
+        caps[0].assign(rule_expr(p)?);
+        while p.can_consume(&[TokenKind::NEWLINE], &[TokenKind::NEWLINE]) {
+            rule_endl(p)?;
+        }
+        if p.peeks(&[TokenKind::Unnamed13]) {
+            p.next();
+            return Ok(true);
+        }
+        p.skipped.push(&[TokenKind::Unnamed13]);
+    }
+    return Err(p.unclosed_loop(start));
+    Ok(false)
+}
+
+fn rule_document(p: &mut Parser) -> Result<Match, ParseError> {
+    let mut mtc = Match::new("document", vec![Capture::Multiple(Vec::new())]);
+    mtc.start = p.start();
+    p.scope.push("document");
+    pat_document(p, &mut mtc.captures)?;
+    p.scope.pop();
+    mtc.end = p.end(mtc.start);
+    Ok(mtc)
+}
+
+fn pat_document(p: &mut Parser, caps: &mut Vec<Capture>) -> Result<bool, ParseError> {
+    loop {
+        if p.can_consume(&[TokenKind::STRING, TokenKind::KEY, TokenKind::Unnamed11, TokenKind::Unnamed14], &[TokenKind::KEY, TokenKind::STRING, TokenKind::Unnamed14, TokenKind::Unnamed11]) {
+            match p.peek_kind() {
+                None => return Err(p.eof(&[TokenKind::KEY, TokenKind::STRING, TokenKind::Unnamed14, TokenKind::Unnamed11])),
+                Some(TokenKind::STRING) | Some(TokenKind::KEY) => {
+                    caps[0].assign(rule_entry(p)?);
+                }
+                Some(TokenKind::Unnamed14) => {
+                    caps[0].assign(rule_aot_scope(p)?);
+                }
+                Some(TokenKind::Unnamed11) => {
+                    caps[0].assign(rule_table_scope(p)?);
+                }
+                Some(_) => return Err(p.unexpected(&[TokenKind::KEY, TokenKind::STRING, TokenKind::Unnamed14, TokenKind::Unnamed11])),
+            }
+        }
+        rule_end(p)?;
+        if ! p.can_consume(&[TokenKind::EOF, TokenKind::NEWLINE, TokenKind::STRING, TokenKind::KEY, TokenKind::Unnamed11, TokenKind::Unnamed14], &[TokenKind::KEY, TokenKind::STRING, TokenKind::Unnamed14, TokenKind::Unnamed11, TokenKind::EOF, TokenKind::NEWLINE]) {
+            break;
+        }
+    }
+    Ok(false)
+}
+
+fn rule_end(p: &mut Parser) -> Result<Match, ParseError> {
+    let mut mtc = Match::new("end", vec![]);
+    mtc.start = p.start();
+    p.scope.push("end");
+    pat_end(p, &mut mtc.captures)?;
+    p.scope.pop();
+    mtc.end = p.end(mtc.start);
+    Ok(mtc)
+}
+
+fn pat_end(p: &mut Parser, caps: &mut Vec<Capture>) -> Result<bool, ParseError> {
+    match p.peek_kind() {
+        None => return Err(p.eof(&[TokenKind::EOF, TokenKind::NEWLINE])),
+        Some(TokenKind::EOF) => {
+            p.expect(TokenKind::EOF, &[TokenKind::EOF])?;
+        }
+        Some(TokenKind::NEWLINE) => {
+            p.expect(TokenKind::NEWLINE, &[TokenKind::NEWLINE])?;
+        }
+        Some(_) => return Err(p.unexpected(&[TokenKind::EOF, TokenKind::NEWLINE])),
+    }
+    Ok(false)
+}
+
+fn rule_endl(p: &mut Parser) -> Result<Match, ParseError> {
+    let mut mtc = Match::new("endl", vec![]);
+    mtc.start = p.start();
+    p.scope.push("endl");
+    pat_endl(p, &mut mtc.captures)?;
+    p.scope.pop();
+    mtc.end = p.end(mtc.start);
+    Ok(mtc)
+}
+
+fn pat_endl(p: &mut Parser, caps: &mut Vec<Capture>) -> Result<bool, ParseError> {
+    p.expect(TokenKind::NEWLINE, &[TokenKind::NEWLINE])?;
+    Ok(false)
+}
+
+fn rule_entry(p: &mut Parser) -> Result<Match, ParseError> {
+    let mut mtc = Match::new("entry", vec![Capture::Single(Box::new(Match::missing())), Capture::Single(Box::new(Match::missing()))]);
+    mtc.start = p.start();
+    p.scope.push("entry");
+    pat_entry(p, &mut mtc.captures)?;
+    p.scope.pop();
+    mtc.end = p.end(mtc.start);
+    Ok(mtc)
+}
+
+fn pat_entry(p: &mut Parser, caps: &mut Vec<Capture>) -> Result<bool, ParseError> {
+    caps[0].assign(rule_key(p)?);
+    p.expect(TokenKind::Unnamed17, &[TokenKind::Unnamed17])?;
+    caps[1].assign(rule_expr(p)?);
+    Ok(false)
+}
+
+fn rule_expr(p: &mut Parser) -> Result<Match, ParseError> {
+    let mut mtc = Match::new("expr", vec![Capture::Single(Box::new(Match::missing()))]);
+    mtc.start = p.start();
+    p.scope.push("expr");
+    pat_expr(p, &mut mtc.captures)?;
+    p.scope.pop();
+    mtc.end = p.end(mtc.start);
+    Ok(mtc)
+}
+
+fn pat_expr(p: &mut Parser, caps: &mut Vec<Capture>) -> Result<bool, ParseError> {
+    match p.peek_kind() {
+        None => return Err(p.eof(&[TokenKind::INT, TokenKind::FLOAT, TokenKind::STRING, TokenKind::Unnamed11, TokenKind::Unnamed18, TokenKind::TRUE, TokenKind::FALSE])),
+        Some(TokenKind::INT) => {
+            caps[0].assign(Match::from_token(p.expect(TokenKind::INT, &[TokenKind::INT])?));
+        }
+        Some(TokenKind::FLOAT) => {
+            caps[0].assign(Match::from_token(p.expect(TokenKind::FLOAT, &[TokenKind::FLOAT])?));
+        }
+        Some(TokenKind::STRING) => {
+            caps[0].assign(Match::from_token(p.expect(TokenKind::STRING, &[TokenKind::STRING])?));
+        }
+        Some(TokenKind::Unnamed11) => {
+            caps[0].assign(rule_array(p)?);
+        }
+        Some(TokenKind::Unnamed18) => {
+            caps[0].assign(rule_inline_table(p)?);
+        }
+        Some(TokenKind::TRUE) => {
+            caps[0].assign(Match::from_token(p.expect(TokenKind::TRUE, &[TokenKind::TRUE])?));
+        }
+        Some(TokenKind::FALSE) => {
+            caps[0].assign(Match::from_token(p.expect(TokenKind::FALSE, &[TokenKind::FALSE])?));
+        }
+        Some(_) => return Err(p.unexpected(&[TokenKind::INT, TokenKind::FLOAT, TokenKind::STRING, TokenKind::Unnamed11, TokenKind::Unnamed18, TokenKind::TRUE, TokenKind::FALSE])),
+    }
+    Ok(false)
+}
+
+fn rule_inline_table(p: &mut Parser) -> Result<Match, ParseError> {
+    let mut mtc = Match::new("inline_table", vec![Capture::Multiple(Vec::new())]);
+    mtc.start = p.start();
+    p.scope.push("inline_table");
+    pat_inline_table(p, &mut mtc.captures)?;
+    p.scope.pop();
+    mtc.end = p.end(mtc.start);
+    Ok(mtc)
+}
+
+fn pat_inline_table(p: &mut Parser, caps: &mut Vec<Capture>) -> Result<bool, ParseError> {
+    p.expect(TokenKind::Unnamed18, &[TokenKind::Unnamed18])?;
+    while p.can_consume(&[TokenKind::NEWLINE], &[TokenKind::NEWLINE]) {
+        rule_endl(p)?;
+    }
+    if p.peeks(&[TokenKind::Unnamed19]) {
+        p.next();
+        return Ok(true);
+    }
+    p.skipped.push(&[TokenKind::Unnamed19]);
+    caps[0].assign(rule_entry(p)?);
+    if p.peeks(&[TokenKind::Unnamed19]) {
+        p.next();
+        return Ok(true);
+    }
+    p.skipped.push(&[TokenKind::Unnamed19]);
+    let start = match p.peek_kind() {
+        Some(_) => p.start(),
+        None => return Err(p.eof(&[TokenKind::Unnamed16])),
+    };
+    while p.peek_kind().is_some() {
+        p.expect(TokenKind::Unnamed16, &[TokenKind::Unnamed16])?;
+        while p.can_consume(&[TokenKind::NEWLINE], &[TokenKind::NEWLINE]) {
+            rule_endl(p)?;
+        }
+        if p.peeks(&[TokenKind::Unnamed19]) {
+            p.next();
+            return Ok(true);
+        }
+        p.skipped.push(&[TokenKind::Unnamed19]);
+        caps[0].assign(rule_entry(p)?);
+        while p.can_consume(&[TokenKind::NEWLINE], &[TokenKind::NEWLINE]) {
+            rule_endl(p)?;
+        }
+        if p.peeks(&[TokenKind::Unnamed19]) {
+            p.next();
+            return Ok(true);
+        }
+        p.skipped.push(&[TokenKind::Unnamed19]);
+    }
+    return Err(p.unclosed_loop(start));
+    Ok(false)
+}
+
+fn rule_key(p: &mut Parser) -> Result<Match, ParseError> {
+    let mut mtc = Match::new("key", vec![Capture::Single(Box::new(Match::missing()))]);
+    mtc.start = p.start();
+    p.scope.push("key");
+    pat_key(p, &mut mtc.captures)?;
+    p.scope.pop();
+    mtc.end = p.end(mtc.start);
+    Ok(mtc)
+}
+
+fn pat_key(p: &mut Parser, caps: &mut Vec<Capture>) -> Result<bool, ParseError> {
+    match p.peek_kind() {
+        None => return Err(p.eof(&[TokenKind::KEY, TokenKind::STRING])),
+        Some(TokenKind::KEY) => {
+            caps[0].assign(Match::from_token(p.expect(TokenKind::KEY, &[TokenKind::KEY])?));
+        }
+        Some(TokenKind::STRING) => {
+            caps[0].assign(Match::from_token(p.expect(TokenKind::STRING, &[TokenKind::STRING])?));
+        }
+        Some(_) => return Err(p.unexpected(&[TokenKind::KEY, TokenKind::STRING])),
+    }
+    Ok(false)
+}
+
+fn rule_table_scope(p: &mut Parser) -> Result<Match, ParseError> {
+    let mut mtc = Match::new("table_scope", vec![Capture::Multiple(Vec::new())]);
+    mtc.start = p.start();
+    p.scope.push("table_scope");
+    pat_table_scope(p, &mut mtc.captures)?;
+    p.scope.pop();
+    mtc.end = p.end(mtc.start);
+    Ok(mtc)
+}
+
+fn pat_table_scope(p: &mut Parser, caps: &mut Vec<Capture>) -> Result<bool, ParseError> {
+    p.expect(TokenKind::Unnamed11, &[TokenKind::Unnamed11])?;
+    caps[0].assign(rule_key(p)?);
+    while p.can_consume(&[TokenKind::Unnamed12], &[TokenKind::Unnamed12]) {
+        p.expect(TokenKind::Unnamed12, &[TokenKind::Unnamed12])?;
+        caps[0].assign(rule_key(p)?);
+    }
+    p.expect(TokenKind::Unnamed13, &[TokenKind::Unnamed13])?;
+    Ok(false)
+}
@@ -1,44 +1,14 @@
 extern crate heck;
 
-use heck::{Match, lex_and_parse_with_grammar};
+// Generated from 'toml.heck' with 'dero examples/toml.heck --generate-ast'.
+#[path = "generated/toml_ast.rs"]
+mod toml_ast;
+
+use heck::{Token, lex_and_parse_with_grammar};
+use toml_ast::{Document, DocumentItems, Key, Expr, Entry, Array, InlineTable};
 use std::collections::HashMap;
 
-const TOML_GRAMMAR: &str = r##"
-_SPACE:        " "
-_TAB:          "\t"
-_COMMENT:    r#"#.*?"#
-TRUE:         "true"
-FALSE:        "false"
-NEWLINE:      "\r\n" | "\n"
-STRING:     r#""(?:\.|[^"])*""#
-KEY:        r#"[a-zA-Z_][a-zA-Z_0-9\-]*"#
-FLOAT:      r#"(?:\+|-)[0-9](?:_?[0-9])*\.(?:[0-9](?:_?[0-9])*)?"#
-INT:        r#"(?:\+|-)[0-9](_?[0-9])*"#
-
-key:
-    $(KEY | STRING)
-endl:
-    NEWLINE
-end:
-    (EOF | NEWLINE)
-table_scope:
-    "[" $$key ( "." $$key )* "]"
-aot_scope:
-    "[[" $$key ( "." $$key )* "]]"
-array:
-    "[" endl* "]"! $$expr "]"! ("," endl* "]"! $$expr endl* "]"!)%
-expr:
-    $(INT | FLOAT | STRING | array | inline_table | TRUE | FALSE)
-entry:
-    $key "=" $expr
-inline_table:
-    "{" endl* "}"! $$entry "}"! ("," endl* "}"! $$entry endl* "}"!)%
-
-
-document:
-    (($$entry | $$aot_scope | $$table_scope)? end)+
-
-"##;
+const TOML_GRAMMAR: &str = include_str!("toml.heck");
 
 const TOML_FILE: &str = include_str!("../Cargo.toml");
 
@@ -56,6 +26,12 @@ pub enum TomlValue {
 
 type TomlResult<T> = Result<T, String>;
 
+/// Returns an error positioned at the start of the given token.
+fn error_at<T>(token: &Token, source: &str, message: &str) -> TomlResult<T> {
+    let (line, col) = token.start_position(source);
+    Err(format!("{}:{}: {}: {:?}", line, col, message, token.slice(source)))
+}
+
 fn with_scope<'a, F: FnOnce(&'a mut TomlTable), I: Iterator<Item=S>, S: ToString> (table: &'a mut TomlTable, mut path: I, f: F) {
     match path.next() {
         None => {
@@ -91,18 +67,16 @@ fn clean_string(s: &str) -> String {
     s.to_string()
 }
 
-fn reduce_key(m: &Match, source: &str) -> String {
-    let m = m.single(0).unwrap();
-    match m.rule.as_str() {
-        "STRING" => clean_string(m.token().unwrap().slice(source)),
-        "KEY" => m.token().unwrap().slice(source).to_string(),
-        _ => unreachable!(),
+fn reduce_key(key: &Key, source: &str) -> String {
+    match *key {
+        Key::String(ref token) => clean_string(token.slice(source)),
+        Key::Key(ref token) => token.slice(source).to_string(),
     }
 }
 
-fn reduce_inline_table(m: &Match, source: &str) -> TomlResult<TomlValue> {
+fn reduce_inline_table(inline_table: &InlineTable, source: &str) -> TomlResult<TomlValue> {
     let mut table = TomlTable::new();
-    for res in m.multiple(0).unwrap().into_iter().map(|m| reduce_entry(m, source)) {
+    for res in inline_table.entry.iter().map(|entry| reduce_entry(entry, source)) {
         let (k, v) = res?;
         table.insert(k, v);
         // TODO: ensure keys only added once.
@@ -110,54 +84,58 @@ fn reduce_inline_table(m: &Match, source: &str) -> TomlResult<TomlValue> {
     Ok(TomlValue::Table(table))
 }
 
-fn reduce_array(m: &Match, source: &str) -> TomlResult<TomlValue> {
+fn reduce_array(array: &Array, source: &str) -> TomlResult<TomlValue> {
     let mut arr = Vec::new();
-    for res in m.multiple(0).unwrap().into_iter().map(|m| reduce_expr(m, source)) {
+    for res in array.expr.iter().map(|expr| reduce_expr(expr, source)) {
         arr.push(res?);
     }
     Ok(TomlValue::Array(arr))
 }
 
-fn reduce_expr(m: &Match, source: &str) -> TomlResult<TomlValue> {
-    let m = m.single(0).unwrap();
-    Ok(match m.rule.as_str() {
-        "INT" => {
-            TomlValue::Int(m.token().unwrap().slice(source).parse().expect("Invalid int :c"))
+fn reduce_expr(expr: &Expr, source: &str) -> TomlResult<TomlValue> {
+    Ok(match *expr {
+        Expr::Int(ref token) => {
+            match token.slice(source).parse() {
+                Ok(value) => TomlValue::Int(value),
+                Err(_) => return error_at(token, source, "Invalid integer"),
+            }
         }
-        "FLOAT" => {
-            TomlValue::Float(m.token().unwrap().slice(source).parse().expect("Invalid float :c"))
+        Expr::Float(ref token) => {
+            match token.slice(source).parse() {
+                Ok(value) => TomlValue::Float(value),
+                Err(_) => return error_at(token, source, "Invalid float"),
+            }
         }
-        "STRING" => {
-            TomlValue::Str(clean_string(m.token().unwrap().slice(source)))
+        Expr::String(ref token) => {
+            TomlValue::Str(clean_string(token.slice(source)))
         }
-        "array" => reduce_array(m, source)?,
-        "inline_table" => reduce_inline_table(m, source)?,
-        "TRUE" => TomlValue::Bool(true),
-        "FALSE" => TomlValue::Bool(false),
-        _ => unreachable!(),
+        Expr::Array(ref array) => reduce_array(array, source)?,
+        Expr::InlineTable(ref inline_table) => reduce_inline_table(inline_table, source)?,
+        Expr::True(_) => TomlValue::Bool(true),
+        Expr::False(_) => TomlValue::Bool(false),
     })
 }
 
-fn reduce_entry(m: &Match, source: &str) -> TomlResult<(String, TomlValue)> {
-    let key = reduce_key(m.single(0).unwrap(), source);
-    let value = reduce_expr(m.single(1).unwrap(), source)?;
+fn reduce_entry(entry: &Entry, source: &str) -> TomlResult<(String, TomlValue)> {
+    let key = reduce_key(&entry.key, source);
+    let value = reduce_expr(&entry.expr, source)?;
     Ok((key, value))
 }
 
-fn reduce_scope(m: &Match, source: &str) -> Vec<String> {
-    m.multiple(0).unwrap().into_iter().map(|m| reduce_key(m, source)).collect()
+fn reduce_scope(keys: &[Key], source: &str) -> Vec<String> {
+    keys.iter().map(|key| reduce_key(key, source)).collect()
 }
 
-fn reduce_document(m: &Match, source: &str) -> TomlResult<TomlTable> {
+fn reduce_document(document: &Document, source: &str) -> TomlResult<TomlTable> {
     let mut table = HashMap::new();
     let mut scope = Vec::new();
-    for tlitem in m.multiple(0).unwrap() {
-        match tlitem.rule.as_str() {
-            "table_scope" => {
-                scope = reduce_scope(tlitem, source);
+    for tlitem in &document.items {
+        match *tlitem {
+            DocumentItems::TableScope(ref table_scope) => {
+                scope = reduce_scope(&table_scope.key, source);
             }
-            "aot_scope" => {
-                scope = reduce_scope(tlitem, source);
+            DocumentItems::AotScope(ref aot_scope) => {
+                scope = reduce_scope(&aot_scope.key, source);
                 let (last, pre) = scope.split_last().unwrap();
                 with_scope(&mut table, pre.iter(), |table| {
                     let val = table.entry(last.to_string()).or_insert_with(
@@ -170,13 +148,12 @@ fn reduce_document(m: &Match, source: &str) -> TomlResult<TomlTable> {
                     }
                 });
             }
-            "entry" => {
-                let (key, val) = reduce_entry(tlitem, source)?;
+            DocumentItems::Entry(ref entry) => {
+                let (key, val) = reduce_entry(entry, source)?;
                 with_scope(&mut table, scope.iter(), |table| {
                     table.insert(key, val);
                 });
             }
-            _ => unreachable!(),
         }
     }
     Ok(table)
@@ -185,6 +162,6 @@ fn reduce_document(m: &Match, source: &str) -> TomlResult<TomlTable> {
 fn main() {
     let mtc = lex_and_parse_with_grammar(TOML_FILE, TOML_GRAMMAR, "document")
         .expect("Could not parse TOML document");
-    let document = reduce_document(&mtc, TOML_FILE).expect("Invalid document");
+    let document = reduce_document(&Document::from(&mtc), TOML_FILE).expect("Invalid document");
     println!("Document: {:#?}", document);
 }
@@ -0,0 +1,680 @@
+//! Analyses of the patterns of parser rules, shared by the parser and the
+//! validators.
+
+use parser::ParserRules;
+use grammar::{Pat, GrammarToken};
+use symbols::{Symbols, TokenId};
+use std::collections::HashSet;
+use std::rc::Rc;
+
+/// Finds the names of the rules that can be parsed without reading a token.
+pub fn find_nullable_rules(parser_rules: &ParserRules) -> HashSet<String> {
+    let mut nullable = HashSet::new();
+    // Iterate until a fixed point is found, since rules can refer to rules
+    // that haven't been found to be nullable yet.
+    loop {
+        let mut changed = false;
+        for (name, rule) in parser_rules {
+            if ! nullable.contains(name) && is_nullable(&rule.pat, &nullable) {
+                nullable.insert(name.clone());
+                changed = true;
+            }
+        }
+        if ! changed {
+            return nullable;
+        }
+    }
+}
+
+/// Returns whether the pattern can be parsed without reading a token, given
+/// the set of rules that can.
+pub fn is_nullable(pat: &Pat, nullable_rules: &HashSet<String>) -> bool {
+    use grammar::Pat::*;
+    match *pat {
+        Token(_) => false,
+        BreakOnToken(_) => true,
+        Rule(ref name) => nullable_rules.contains(name),
+        Seq(ref pats) => pats.iter().all(|pat| is_nullable(pat, nullable_rules)),
+        AnyOf(ref pats) => pats.iter().any(|pat| is_nullable(pat, nullable_rules)),
+        Opt(_) | ZeroPlus(_) => true,
+        OnePlus(ref pat) | Cap(_, ref pat) | Loop(ref pat) => is_nullable(pat, nullable_rules),
+        SepBy(ref item, _, flags) => flags.empty || is_nullable(item, nullable_rules),
+        Call(..) => unreachable!(),
+    }
+}
+
+/// Adds the rules that the pattern can start parsing before reading a token
+/// to 'left_rules', and returns whether the pattern is nullable.
+pub fn find_left_rules(pat: &Pat, nullable_rules: &HashSet<String>,
+    left_rules: &mut Vec<String>) -> bool
+{
+    use grammar::Pat::*;
+    match *pat {
+        Token(_) => false,
+        BreakOnToken(_) => true,
+        Rule(ref name) => {
+            if ! left_rules.contains(name) {
+                left_rules.push(name.clone());
+            }
+            nullable_rules.contains(name)
+        }
+        Seq(ref pats) => {
+            for pat in pats {
+                if ! find_left_rules(pat, nullable_rules, left_rules) {
+                    return false;
+                }
+            }
+            true
+        }
+        AnyOf(ref pats) => {
+            let mut nullable = false;
+            for pat in pats {
+                if find_left_rules(pat, nullable_rules, left_rules) {
+                    nullable = true;
+                }
+            }
+            nullable
+        }
+        Opt(ref pat) | ZeroPlus(ref pat) => {
+            find_left_rules(pat, nullable_rules, left_rules);
+            true
+        }
+        OnePlus(ref pat) | Cap(_, ref pat) | Loop(ref pat) => {
+            find_left_rules(pat, nullable_rules, left_rules)
+        }
+        SepBy(ref item, ref sep, flags) => {
+            // The separator is read first when the item is parsed as nothing.
+            let nullable = find_left_rules(item, nullable_rules, left_rules);
+            if nullable {
+                find_left_rules(sep, nullable_rules, left_rules);
+            }
+            flags.empty || nullable
+        }
+        Call(..) => unreachable!(),
+    }
+}
+
+/// A set of tokens of a grammar, with a bit for every token id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenSet {
+    bits: Vec<u64>,
+}
+impl TokenSet {
+    /// Creates an empty set for a grammar with the given number of tokens.
+    pub(crate) fn empty(count: usize) -> TokenSet {
+        TokenSet { bits: vec![0; count.div_ceil(64)] }
+    }
+    
+    /// Creates a set of every token of a grammar with the given number of tokens.
+    pub(crate) fn all(count: usize) -> TokenSet {
+        let mut set = TokenSet::empty(count);
+        for index in 0..count {
+            set.insert(TokenId::from_index(index));
+        }
+        set
+    }
+    
+    /// Returns whether the token is in this set.
+    pub fn contains(&self, id: TokenId) -> bool {
+        self.bits[id.index() / 64] & (1 << (id.index() % 64)) != 0
+    }
+    
+    /// Adds the token to this set, and returns whether it was added.
+    pub(crate) fn insert(&mut self, id: TokenId) -> bool {
+        let contained = self.contains(id);
+        self.bits[id.index() / 64] |= 1 << (id.index() % 64);
+        ! contained
+    }
+    
+    /// Adds the tokens of the other set to this set, and returns whether 
+    /// any were added.
+    pub(crate) fn union_with(&mut self, other: &TokenSet) -> bool {
+        let mut changed = false;
+        for (bits, &other_bits) in self.bits.iter_mut().zip(&other.bits) {
+            changed |= other_bits & ! *bits != 0;
+            *bits |= other_bits;
+        }
+        changed
+    }
+    
+    /// Returns the tokens that are in both this set and the other one.
+    pub fn intersection(&self, other: &TokenSet) -> TokenSet {
+        let bits = self.bits.iter().zip(&other.bits).map(|(a, b)| a & b).collect();
+        TokenSet { bits }
+    }
+    
+    /// Returns the tokens of this set that aren't in the other one.
+    pub fn difference(&self, other: &TokenSet) -> TokenSet {
+        let bits = self.bits.iter().zip(&other.bits).map(|(a, b)| a & ! b).collect();
+        TokenSet { bits }
+    }
+    
+    /// Returns whether the set has no tokens.
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|&bits| bits == 0)
+    }
+    
+    /// Returns the tokens of this set, ordered by id.
+    pub fn ids(&self) -> Vec<TokenId> {
+        let mut ids = Vec::new();
+        for (i, &bits) in self.bits.iter().enumerate() {
+            for bit in 0..64 {
+                if bits & (1 << bit) != 0 {
+                    ids.push(TokenId::from_index(i * 64 + bit));
+                }
+            }
+        }
+        ids
+    }
+    
+    /// Returns the names of the tokens of this set, ordered by id.
+    pub fn names(&self, symbols: &Symbols) -> Vec<String> {
+        self.ids().into_iter().map(|id| symbols.token_name(id).to_string()).collect()
+    }
+}
+
+/// The tokens that decide how a node of the pattern of a rule is parsed,
+/// found once when the parser rules are made, so that the parser only has
+/// to look them up.
+#[derive(Debug, Clone)]
+pub struct PatSets {
+    /// The number of nodes in the pattern of the node, including itself.
+    pub(crate) size: usize,
+    /// The tokens that the node is parsed with (FIRST).
+    pub(crate) first: TokenSet,
+    /// The tokens that aren't in 'first', but which the parser can pass the
+    /// node on, without reading them.
+    pub(crate) passes: TokenSet,
+    /// The tokens that can be read right after the node (FOLLOW).
+    pub(crate) follow: TokenSet,
+    /// Whether the node can be parsed without reading a token.
+    pub(crate) nullable: bool,
+    /// The branch of an 'AnyOf' node that each token is parsed with, by
+    /// token id (the LL(1) prediction table). Empty for other nodes.
+    pub(crate) choices: Vec<Option<usize>>,
+}
+impl PatSets {
+    fn new(count: usize) -> PatSets {
+        PatSets {
+            size: 1,
+            first: TokenSet::empty(count),
+            passes: TokenSet::empty(count),
+            follow: TokenSet::empty(count),
+            nullable: false,
+            choices: Vec::new(),
+        }
+    }
+    
+    /// Returns the tokens that the node is parsed with (FIRST).
+    pub fn first(&self) -> &TokenSet {
+        &self.first
+    }
+    
+    /// Returns the tokens that can be read right after the node (FOLLOW).
+    pub fn follow(&self) -> &TokenSet {
+        &self.follow
+    }
+    
+    /// Returns the tokens that aren't in 'first', but which the parser can
+    /// pass the node on, without reading them.
+    pub fn passes(&self) -> &TokenSet {
+        &self.passes
+    }
+    
+    /// Returns whether the node can be parsed without reading a token.
+    pub fn is_nullable(&self) -> bool {
+        self.nullable
+    }
+    
+    /// Returns which branch of an alternative the given token is parsed 
+    /// with, if any.
+    pub fn choice(&self, id: TokenId) -> Option<usize> {
+        self.choices.get(id.index()).and_then(|&choice| choice)
+    }
+}
+
+/// Returns the node indices of the children of the node at the given index.
+pub fn child_nodes(sets: &[PatSets], node: usize, nof_children: usize) -> Vec<usize> {
+    children(sets, node).take(nof_children).collect()
+}
+
+/// Iterates over the node indices of the children of the node at the given
+/// index, without allocating, for the parser. The caller stops after the 
+/// last child, ie: by zipping the children with their patterns.
+pub(crate) fn children<'s>(sets: &'s [PatSets], node: usize) -> Children<'s> {
+    Children { sets, next: node + 1 }
+}
+
+/// The node indices of the children of a node (see 'children').
+pub(crate) struct Children<'s> {
+    sets: &'s [PatSets],
+    next: usize,
+}
+impl<'s> Iterator for Children<'s> {
+    type Item = usize;
+    
+    fn next(&mut self) -> Option<usize> {
+        let child = self.next;
+        self.next += self.sets[child].size;
+        Some(child)
+    }
+}
+
+/// Combines the 'first' and 'passes' sets of the parts of a sequence, where
+/// a token is decided by the first part that doesn't pass it. Parts are only
+/// asked for until every token is decided.
+fn seq_sets<F>(nof_parts: usize, count: usize, mut part: F) -> (TokenSet, TokenSet) 
+    where F: FnMut(usize) -> (TokenSet, TokenSet)
+{
+    let mut first = TokenSet::empty(count);
+    let mut undecided = TokenSet::all(count);
+    for i in 0..nof_parts {
+        if undecided.is_empty() {
+            break;
+        }
+        let (part_first, part_passes) = part(i);
+        first.union_with(&undecided.intersection(&part_first));
+        undecided = undecided.intersection(&part_passes);
+    }
+    (first, undecided)
+}
+
+/// Finds the 'first' and 'passes' sets of whole rules, as they are needed.
+struct RuleSetFinder<'a> {
+    parser_rules: &'a ParserRules,
+    count: usize,
+    found: Vec<Option<(TokenSet, TokenSet)>>,
+    visiting: Vec<bool>,
+}
+impl<'a> RuleSetFinder<'a> {
+    fn rule_sets(&mut self, name: &str) -> (TokenSet, TokenSet) {
+        let id = match self.parser_rules.symbols.rule_id(name) {
+            Some(id) => id.index(),
+            None => return (TokenSet::empty(self.count), TokenSet::empty(self.count)),
+        };
+        if let Some(ref sets) = self.found[id] {
+            return sets.clone();
+        }
+        // Left recursion is reported by the validator; parse it as nothing.
+        if self.visiting[id] {
+            return (TokenSet::empty(self.count), TokenSet::empty(self.count));
+        }
+        self.visiting[id] = true;
+        let sets = self.pat_sets(&self.parser_rules.rules[id].pat);
+        self.visiting[id] = false;
+        self.found[id] = Some(sets.clone());
+        sets
+    }
+    
+    fn pat_sets(&mut self, pat: &'a Pat) -> (TokenSet, TokenSet) {
+        use grammar::Pat::*;
+        let count = self.count;
+        match *pat {
+            Token(GrammarToken::Named(id, _)) => {
+                let mut first = TokenSet::empty(count);
+                first.insert(id);
+                (first, TokenSet::empty(count))
+            }
+            BreakOnToken(GrammarToken::Named(id, _)) => {
+                let mut first = TokenSet::empty(count);
+                first.insert(id);
+                let passes = TokenSet::all(count).difference(&first);
+                (first, passes)
+            }
+            Token(_) | BreakOnToken(_) => {
+                panic!("Attempted analysis without assigning token names");
+            }
+            Rule(ref name) => self.rule_sets(name),
+            Seq(ref pats) => seq_sets(pats.len(), count, |i| self.pat_sets(&pats[i])),
+            AnyOf(ref pats) => {
+                let mut first = TokenSet::empty(count);
+                let mut passes = TokenSet::empty(count);
+                for pat in pats {
+                    let (pat_first, pat_passes) = self.pat_sets(pat);
+                    first.union_with(&pat_first);
+                    passes.union_with(&pat_passes);
+                }
+                let passes = passes.difference(&first);
+                (first, passes)
+            }
+            Opt(ref pat) | ZeroPlus(ref pat) => {
+                let (first, _) = self.pat_sets(pat);
+                let passes = TokenSet::all(count).difference(&first);
+                (first, passes)
+            }
+            OnePlus(ref pat) | Cap(_, ref pat) | Loop(ref pat) => self.pat_sets(pat),
+            SepBy(ref item, _, flags) => {
+                let (first, passes) = self.pat_sets(item);
+                if flags.empty {
+                    let passes = TokenSet::all(count).difference(&first);
+                    (first, passes)
+                } else {
+                    (first, passes)
+                }
+            }
+            Call(..) => unreachable!(),
+        }
+    }
+}
+
+/// Finds the sets of every node of the pattern, in preorder, given the sets
+/// of whole rules.
+fn find_node_sets<'a>(pat: &'a Pat, rules: &mut RuleSetFinder<'a>, 
+    nullable_rules: &HashSet<String>, nodes: &mut Vec<PatSets>) 
+{
+    use grammar::Pat::*;
+    let count = rules.count;
+    let node = nodes.len();
+    nodes.push(PatSets::new(count));
+    let children = match *pat {
+        Seq(ref pats) | AnyOf(ref pats) => {
+            let mut children = Vec::new();
+            for pat in pats {
+                children.push(nodes.len());
+                find_node_sets(pat, rules, nullable_rules, nodes);
+            }
+            children
+        }
+        Opt(ref pat) | ZeroPlus(ref pat) | OnePlus(ref pat) | Cap(_, ref pat) | Loop(ref pat) => {
+            find_node_sets(pat, rules, nullable_rules, nodes);
+            vec![node + 1]
+        }
+        SepBy(ref item, ref sep, _) => {
+            find_node_sets(item, rules, nullable_rules, nodes);
+            let sep_node = nodes.len();
+            find_node_sets(sep, rules, nullable_rules, nodes);
+            vec![node + 1, sep_node]
+        }
+        Token(_) | BreakOnToken(_) | Rule(_) => Vec::new(),
+        Call(..) => unreachable!(),
+    };
+    let (first, passes) = match *pat {
+        Seq(_) => {
+            seq_sets(children.len(), count, |i| {
+                (nodes[children[i]].first.clone(), nodes[children[i]].passes.clone())
+            })
+        }
+        AnyOf(_) => {
+            let mut first = TokenSet::empty(count);
+            let mut passes = TokenSet::empty(count);
+            let mut choices = vec![None; count];
+            for (branch, &child) in children.iter().enumerate() {
+                for id in nodes[child].first.ids() {
+                    if choices[id.index()].is_none() {
+                        choices[id.index()] = Some(branch);
+                    }
+                }
+                first.union_with(&nodes[child].first);
+                passes.union_with(&nodes[child].passes);
+            }
+            nodes[node].choices = choices;
+            let passes = passes.difference(&first);
+            (first, passes)
+        }
+        Opt(_) | ZeroPlus(_) => {
+            let first = nodes[node + 1].first.clone();
+            let passes = TokenSet::all(count).difference(&first);
+            (first, passes)
+        }
+        OnePlus(_) | Cap(_, _) | Loop(_) => {
+            (nodes[node + 1].first.clone(), nodes[node + 1].passes.clone())
+        }
+        SepBy(_, _, flags) => {
+            let first = nodes[node + 1].first.clone();
+            if flags.empty {
+                let passes = TokenSet::all(count).difference(&first);
+                (first, passes)
+            } else {
+                (first, nodes[node + 1].passes.clone())
+            }
+        }
+        Token(_) | BreakOnToken(_) | Rule(_) => rules.pat_sets(pat),
+        Call(..) => unreachable!(),
+    };
+    let nullable = match *pat {
+        Token(_) => false,
+        BreakOnToken(_) | Opt(_) | ZeroPlus(_) => true,
+        Rule(ref name) => nullable_rules.contains(name),
+        Seq(_) => children.iter().all(|&child| nodes[child].nullable),
+        AnyOf(_) => children.iter().any(|&child| nodes[child].nullable),
+        OnePlus(_) | Cap(_, _) | Loop(_) => nodes[node + 1].nullable,
+        SepBy(_, _, flags) => flags.empty || nodes[node + 1].nullable,
+        Call(..) => unreachable!(),
+    };
+    let size = nodes.len() - node;
+    let sets = &mut nodes[node];
+    sets.size = size;
+    sets.first = first;
+    sets.passes = passes;
+    sets.nullable = nullable;
+}
+
+/// Sets the follow set of every node of the pattern, given the follow set
+/// of the pattern, and adds the tokens that can follow the rules used in it
+/// to their follow sets. Returns whether any rule follow set changed.
+fn find_follow_sets(pat: &Pat, node: usize, follow: TokenSet, nodes: &mut [PatSets], 
+    symbols: &Symbols, rule_follows: &mut [TokenSet]) -> bool 
+{
+    use grammar::Pat::*;
+    let mut changed = false;
+    match *pat {
+        Rule(ref name) => {
+            if let Some(id) = symbols.rule_id(name) {
+                changed |= rule_follows[id.index()].union_with(&follow);
+            }
+        }
+        Seq(ref pats) => {
+            let children = child_nodes(nodes, node, pats.len());
+            // Work backwards, since each part is followed by the parts after it.
+            let mut rest = follow.clone();
+            for (pat, &child) in pats.iter().zip(&children).rev() {
+                changed |= find_follow_sets(pat, child, rest.clone(), nodes, symbols, rule_follows);
+                if nodes[child].nullable {
+                    rest.union_with(&nodes[child].first);
+                } else {
+                    rest = nodes[child].first.clone();
+                }
+            }
+        }
+        AnyOf(ref pats) => {
+            let children = child_nodes(nodes, node, pats.len());
+            for (pat, &child) in pats.iter().zip(&children) {
+                changed |= find_follow_sets(pat, child, follow.clone(), nodes, symbols, rule_follows);
+            }
+        }
+        Opt(ref pat) | Cap(_, ref pat) => {
+            changed |= find_follow_sets(pat, node + 1, follow.clone(), nodes, symbols, rule_follows);
+        }
+        ZeroPlus(ref pat) | OnePlus(ref pat) => {
+            // A repetition can be followed by another repetition.
+            let mut inner = follow.clone();
+            inner.union_with(&nodes[node + 1].first);
+            changed |= find_follow_sets(pat, node + 1, inner, nodes, symbols, rule_follows);
+        }
+        Loop(ref pat) => {
+            // A loop is only left by breaking out of the rule.
+            let inner = nodes[node + 1].first.clone();
+            changed |= find_follow_sets(pat, node + 1, inner, nodes, symbols, rule_follows);
+        }
+        SepBy(ref item, ref sep, flags) => {
+            let children = child_nodes(nodes, node, 2);
+            // An item is followed by a separator, or ends the list.
+            let mut item_follow = follow.clone();
+            item_follow.union_with(&nodes[children[1]].first);
+            // A separator is followed by an item, or ends the list if it can
+            // trail.
+            let mut sep_follow = nodes[children[0]].first.clone();
+            if flags.trailing {
+                sep_follow.union_with(&follow);
+            }
+            changed |= find_follow_sets(item, children[0], item_follow, nodes, symbols, rule_follows);
+            changed |= find_follow_sets(sep, children[1], sep_follow, nodes, symbols, rule_follows);
+        }
+        Token(_) | BreakOnToken(_) => {}
+        Call(..) => unreachable!(),
+    }
+    nodes[node].follow = follow;
+    changed
+}
+
+/// Finds the sets of every node of the pattern of every rule, indexed by 
+/// rule id, and then by the index of the node in preorder. The start rules
+/// are followed by EOF.
+pub fn find_pat_sets(parser_rules: &ParserRules) -> Vec<Vec<PatSets>> {
+    let count = parser_rules.symbols.token_count();
+    let nof_rules = parser_rules.rules.len();
+    let nullable_rules = find_nullable_rules(parser_rules);
+    let mut finder = RuleSetFinder {
+        parser_rules,
+        count,
+        found: vec![None; nof_rules],
+        visiting: vec![false; nof_rules],
+    };
+    let mut rule_sets = Vec::with_capacity(nof_rules);
+    for rule in &parser_rules.rules {
+        let mut nodes = Vec::new();
+        find_node_sets(&rule.pat, &mut finder, &nullable_rules, &mut nodes);
+        rule_sets.push(nodes);
+    }
+    
+    let mut rule_follows = vec![TokenSet::empty(count); nof_rules];
+    for name in &parser_rules.start_rules {
+        if let Some(id) = parser_rules.symbols.rule_id(name) {
+            rule_follows[id.index()].insert(TokenId::EOF);
+        }
+    }
+    // Iterate until a fixed point is found, since a rule can be used by 
+    // rules whose follow sets haven't been found yet.
+    loop {
+        let mut changed = false;
+        for (rule, nodes) in parser_rules.rules.iter().zip(rule_sets.iter_mut()) {
+            let id = parser_rules.symbols.rule_id(&rule.name).unwrap();
+            let follow = rule_follows[id.index()].clone();
+            changed |= find_follow_sets(&rule.pat, 0, follow, nodes, 
+                &parser_rules.symbols, &mut rule_follows);
+        }
+        if ! changed {
+            return rule_sets;
+        }
+    }
+}
+
+/// Finds the rules that no other rule uses.
+pub fn find_unused_rules(parser_rules: &ParserRules) -> Vec<Rc<String>> {
+    let mut used = HashSet::new();
+    for (name, rule) in parser_rules {
+        let mut used_rules = Vec::new();
+        find_used_rules(&rule.pat, &mut used_rules);
+        used.extend(used_rules.into_iter().filter(|used| used != name));
+    }
+    parser_rules.iter().filter(|&(name, _)| ! used.contains(name))
+        .map(|(_, rule)| rule.name.clone()).collect()
+}
+
+/// Adds the names of every rule that the pattern uses to 'used_rules'.
+pub fn find_used_rules(pat: &Pat, used_rules: &mut Vec<String>) {
+    use grammar::Pat::*;
+    match *pat {
+        Rule(ref name) => {
+            if ! used_rules.contains(name) {
+                used_rules.push(name.clone());
+            }
+        }
+        Seq(ref pats) | AnyOf(ref pats) => {
+            for pat in pats {
+                find_used_rules(pat, used_rules);
+            }
+        }
+        Opt(ref pat) | ZeroPlus(ref pat) | OnePlus(ref pat) | Cap(_, ref pat) | Loop(ref pat) => {
+            find_used_rules(pat, used_rules);
+        }
+        SepBy(ref item, ref sep, _) => {
+            find_used_rules(item, used_rules);
+            find_used_rules(sep, used_rules);
+        }
+        Token(_) | BreakOnToken(_) => {}
+        Call(..) => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grammar::parse_raw_rules;
+    use parser::find_parser_rules;
+
+    const GRAMMAR: &str = r##"
+        program: $$stmt* EOF
+        stmt: $ID ("=" $expr)? ";" | "print" $expr ";"
+        expr: $NUM | $ID | "(" $expr ")"
+        args: {$$expr, ","}*
+        ID: r#"[a-z]+"#
+        NUM: r#"[0-9]+"#
+    "##;
+
+    #[test]
+    fn finds_the_first_and_follow_sets_of_rules() {
+        let parser_rules = find_parser_rules(&parse_raw_rules(GRAMMAR).unwrap());
+        let symbols = parser_rules.symbols();
+        let stmt = parser_rules.get("stmt").unwrap();
+        assert_eq!(stmt.first().names(symbols), vec!["ID", "print"]);
+        assert_eq!(stmt.follow().names(symbols), vec!["EOF", "ID", "print"]);
+        let expr = parser_rules.get("expr").unwrap();
+        assert_eq!(expr.first().names(symbols), vec!["ID", "NUM", "("]);
+        // 'args' is a start rule, since no rule uses it, so it can end a text.
+        assert_eq!(expr.follow().names(symbols), vec!["EOF", ";", ")", ","]);
+        assert!(! stmt.is_nullable());
+        assert!(parser_rules.get("args").unwrap().is_nullable());
+        assert!(! parser_rules.get("program").unwrap().is_nullable());
+    }
+
+    #[test]
+    fn finds_the_sets_of_every_node() {
+        let parser_rules = find_parser_rules(&parse_raw_rules(GRAMMAR).unwrap());
+        let symbols = parser_rules.symbols();
+        let sets = parser_rules.get("stmt").unwrap().sets();
+        assert_eq!(sets[0].size, sets.len());
+        let branches = child_nodes(sets, 0, 2);
+        let parts = child_nodes(sets, branches[0], 3);
+        // The optional assignment.
+        let opt = &sets[parts[1]];
+        assert!(opt.is_nullable());
+        assert_eq!(opt.first().names(symbols), vec!["="]);
+        assert_eq!(opt.follow().names(symbols), vec![";"]);
+        assert!(opt.passes().contains(symbols.token_id(";").unwrap()));
+        assert!(! opt.passes().contains(symbols.token_id("=").unwrap()));
+        assert_eq!(sets[parts[2]].first().names(symbols), vec![";"]);
+    }
+
+    #[test]
+    fn predicts_the_branch_of_each_token() {
+        let parser_rules = find_parser_rules(&parse_raw_rules(GRAMMAR).unwrap());
+        let symbols = parser_rules.symbols();
+        let choice = |rule: &str, token: &str| {
+            parser_rules.get(rule).unwrap().sets()[0].choice(symbols.token_id(token).unwrap())
+        };
+        assert_eq!(choice("stmt", "ID"), Some(0));
+        assert_eq!(choice("stmt", "print"), Some(1));
+        assert_eq!(choice("stmt", "NUM"), None);
+        assert_eq!(choice("expr", "NUM"), Some(0));
+        assert_eq!(choice("expr", "ID"), Some(1));
+        assert_eq!(choice("expr", "("), Some(2));
+        assert_eq!(choice("expr", ";"), None);
+        // Only alternatives have a prediction table.
+        assert_eq!(choice("program", "ID"), None);
+    }
+
+    #[test]
+    fn token_sets_hold_any_number_of_tokens() {
+        let mut set = TokenSet::empty(130);
+        assert!(set.is_empty());
+        for &index in &[0, 63, 64, 129] {
+            assert!(set.insert(TokenId::from_index(index)));
+        }
+        assert!(! set.insert(TokenId::from_index(64)));
+        assert_eq!(set.ids().into_iter().map(TokenId::index).collect::<Vec<_>>(), vec![0, 63, 64, 129]);
+        let all = TokenSet::all(130);
+        assert_eq!(all.difference(&set).ids().len(), 126);
+        assert_eq!(all.intersection(&set), set);
+    }
+}
@@ -0,0 +1,216 @@
+//! Functions to generate code from grammars in build scripts, so that they
+//! can be kept in '.heck' files instead of string constants.
+//!
+//! ```ignore
+//! // build.rs
+//! extern crate heck;
+//! use heck::build::{generate, Output};
+//!
+//! fn main() {
+//!     generate("src/toml.heck", Output::Parser, "toml_parser.rs");
+//! }
+//!
+//! // src/main.rs: Defines the 'toml_parser' module.
+//! include!(concat!(env!("OUT_DIR"), "/toml_parser.rs"));
+//! ```
+
+use std::env;
+use std::fmt;
+use std::io::{self, Write};
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+use error::GrammarError;
+use grammar::RawRules;
+use loader::{FileResolver, load_grammar};
+use lexer::find_lexer_rules;
+use parser::find_parser_rules;
+use validate::validate_rules;
+use codegen::{generate_parser, generate_ast, generate_reducer_signatures, snake_case};
+
+/// The code that can be generated from a grammar.
+#[derive(Debug, Clone)]
+pub enum Output {
+    /// A compiled lexer and parser (see 'generate_parser').
+    Parser,
+    /// Typed syntax trees, using the 'Match' and 'Token' types at the given
+    /// path (see 'generate_ast').
+    Ast(String),
+    /// The signatures of reducer functions, as text to copy from.
+    ReducerSignatures,
+}
+
+/// Describes why code could not be generated from a grammar.
+#[derive(Debug)]
+pub enum BuildError {
+    /// The code could not be written.
+    Io(io::Error),
+    /// The grammar could not be read or parsed, or is not logically sound.
+    Grammar(Vec<GrammarError>),
+}
+
+impl From<io::Error> for BuildError {
+    fn from(err: io::Error) -> BuildError {
+        BuildError::Io(err)
+    }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BuildError::Io(ref err) => write!(f, "{}", err),
+            BuildError::Grammar(ref errors) => {
+                write!(f, "Grammar errors:")?;
+                for err in errors {
+                    write!(f, "\n  {}", err)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for BuildError {
+    fn description(&self) -> &str {
+        match *self {
+            BuildError::Io(_) => "could not write code",
+            BuildError::Grammar(_) => "invalid grammar",
+        }
+    }
+}
+
+/// Reads and validates the grammar at the given path, along with the files
+/// it imports, and writes the generated code to the given path. Parsers and
+/// syntax trees are written inside a module named after the output file, so
+/// that the file can be included with 'include!'. The name is made a valid
+/// identifier, so 'my-parser.rs' defines the 'my_parser' module.
+pub fn generate_to(grammar_path: &Path, output: Output, out_path: &Path) -> Result<(), BuildError> {
+    let raw_rules = load_grammar(&grammar_path.to_string_lossy(), &mut FileResolver)
+        .map_err(|err| BuildError::Grammar(vec![err]))?;
+    generate_from(&raw_rules, output, out_path)
+}
+
+/// Validates the given rules, and writes the generated code to the given
+/// path.
+fn generate_from(raw_rules: &RawRules, output: Output, out_path: &Path) -> Result<(), BuildError> {
+    let lexer_rules = find_lexer_rules(raw_rules);
+    let parser_rules = find_parser_rules(raw_rules);
+    let errors = validate_rules(raw_rules, &lexer_rules, &parser_rules);
+    if ! errors.is_empty() {
+        return Err(BuildError::Grammar(errors));
+    }
+
+    let code = match output {
+        Output::Parser => generate_parser(&parser_rules, &lexer_rules),
+        Output::Ast(ref types_path) => generate_ast(&parser_rules, types_path),
+        Output::ReducerSignatures => generate_reducer_signatures(&parser_rules).join("\n\n"),
+    };
+    let mut file = File::create(out_path)?;
+    match output {
+        Output::Parser | Output::Ast(_) => {
+            // The generated modules start with inner attributes, which
+            // 'include!' only allows inside a module.
+            let name = out_path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("grammar");
+            write!(file, "pub mod {} {{\n{}}}\n", snake_case(name), code)?;
+        }
+        Output::ReducerSignatures => {
+            writeln!(file, "{}", code)?;
+        }
+    }
+    Ok(())
+}
+
+/// Generates code from the grammar at the given path into the file with the
+/// given name in 'OUT_DIR', and tells cargo to rerun the build script when
+/// the grammar or one of its imports changes. This is meant to be called 
+/// from a build script, and fails the build with the location of every 
+/// problem found in the grammar.
+pub fn generate(grammar_path: &str, output: Output, out_name: &str) {
+    println!("cargo:rerun-if-changed={}", grammar_path);
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR is not set; is this a build script?");
+    let out_path = Path::new(&out_dir).join(out_name);
+    let result = load_grammar(grammar_path, &mut FileResolver)
+        .map_err(|err| BuildError::Grammar(vec![err]))
+        .and_then(|raw_rules| {
+            for file in &raw_rules.files()[1..] {
+                println!("cargo:rerun-if-changed={}", file);
+            }
+            generate_from(&raw_rules, output, &out_path)
+        });
+    match result {
+        Ok(()) => {}
+        Err(BuildError::Grammar(errors)) => {
+            for err in &errors {
+                eprintln!("{}", err);
+            }
+            panic!("The grammar '{}' has {} error(s)", grammar_path, errors.len());
+        }
+        Err(BuildError::Io(err)) => {
+            panic!("Could not generate code from '{}': {}", grammar_path, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Read;
+    use std::path::PathBuf;
+
+    /// Returns an empty directory for the files of a test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("heck-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read(path: &Path) -> String {
+        let mut text = String::new();
+        File::open(path).unwrap().read_to_string(&mut text).unwrap();
+        text
+    }
+
+    const GRAMMAR: &str = "program: $NUM EOF\nNUM: r#\"[0-9]+\"#\n";
+
+    #[test]
+    fn generates_a_module_named_after_the_output_file() {
+        let dir = test_dir("module");
+        let grammar_path = dir.join("grammar.heck");
+        fs::write(&grammar_path, GRAMMAR).unwrap();
+        let out_path = dir.join("my-parser.rs");
+        generate_to(&grammar_path, Output::Parser, &out_path).unwrap();
+        let code = read(&out_path);
+        assert!(code.starts_with("pub mod my_parser {\n//! A lexer and parser generated by heck."));
+        assert!(code.ends_with("}\n"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn generates_reducer_signatures_without_a_module() {
+        let dir = test_dir("signatures");
+        let grammar_path = dir.join("grammar.heck");
+        fs::write(&grammar_path, GRAMMAR).unwrap();
+        let out_path = dir.join("signatures.rs");
+        generate_to(&grammar_path, Output::ReducerSignatures, &out_path).unwrap();
+        let code = read(&out_path);
+        assert!(code.starts_with("fn reduce_program(m: &Match, source: &str) -> T {"));
+        assert!(code.ends_with("}\n"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_grammar_errors_with_their_files() {
+        let dir = test_dir("errors");
+        let grammar_path = dir.join("grammar.heck");
+        fs::write(&grammar_path, "program: $NUM value EOF\nNUM: r#\"[0-9]+\"#\n").unwrap();
+        let out_path = dir.join("parser.rs");
+        let err = generate_to(&grammar_path, Output::Parser, &out_path).unwrap_err();
+        assert_eq!(err.to_string(), format!(
+            "Grammar errors:\n  {}:1:15: program: Unbound name 'value'", grammar_path.display()
+        ));
+        assert!(! out_path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -139,6 +139,10 @@ fn reorder_capture_indices(pat: Pat, good: &CaptureState, bad: &CaptureState) ->
             AnyOf(pats) => {
                 AnyOf(pats.into_iter().map(|p| inner(p, map)).collect())
             }
+            SepBy(item, sep, flags) => {
+                SepBy(Box::new(inner(*item, map)), Box::new(inner(*sep, map)), flags)
+            }
+            Call(..) => unreachable!(),
         }
     }
     inner(pat, &map)
@@ -180,7 +184,7 @@ pub fn find_and_assign_captures(pat: Pat) -> (Vec<CaptureType>, Pat) {
                 };
                 let inner_context = match *boxed {
                     Token(_) | Rule(_) => Free,
-                    Seq(_) | ZeroPlus(_) | OnePlus(_) | Loop(_) => Repetition,
+                    Seq(_) | ZeroPlus(_) | OnePlus(_) | Loop(_) | SepBy(..) => Repetition,
                     Cap(_, _) => {
                         panic!("Cannot have a capture just inside a capture :/");
                     }
@@ -202,6 +206,7 @@ pub fn find_and_assign_captures(pat: Pat) -> (Vec<CaptureType>, Pat) {
                     BreakOnToken(_) => {
                         panic!("Cannot capture 'break on token ( \"token\"! )' pattern!");
                     }
+                    Call(..) => unreachable!(),
                 };
                 let actual = match (context, inner_context) {
                     (Repetition, _) => Repetition,
@@ -231,6 +236,11 @@ pub fn find_and_assign_captures(pat: Pat) -> (Vec<CaptureType>, Pat) {
             Loop(boxed) => {
                 Loop(Box::new(inner(*boxed, CaptureContext::Repetition, state)))
             }
+            SepBy(item, sep, flags) => {
+                // Every item lands in the same groups.
+                let item = inner(*item, CaptureContext::Repetition, state);
+                SepBy(Box::new(item), Box::new(inner(*sep, CaptureContext::Repetition, state)), flags)
+            }
             AnyOf(mut pats) => {
                 let pre_state = state.clone();
                 let mut drained = pats.drain(..);
@@ -267,6 +277,7 @@ pub fn find_and_assign_captures(pat: Pat) -> (Vec<CaptureType>, Pat) {
                 AnyOf(assigned_pats)
             }
             Token(_) | Rule(_) | BreakOnToken(_) => pat,
+            Call(..) => unreachable!(),
         }
     }
     let mut state = CaptureState { 
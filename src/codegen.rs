@@ -1,7 +1,14 @@
+//! Functions to generate Rust source code from a grammar.
 
-use parser::ParserRules;
-use std::collections::HashSet;
+use parser::{ParserRules, ParserRule, Assoc, find_expected_tokens};
+use analysis::child_nodes;
+use lexer::{LexerRules, ModeChange};
+use symbols::{Symbols, TokenId};
+use grammar::{Pat, GrammarToken, CaptureInfo};
+use std::collections::{HashSet, HashMap};
 use std::ops::Deref;
+use std::rc::Rc;
+use std::iter;
 
 /*
 Example signatures:
@@ -30,9 +37,19 @@ pub fn generate_reducer_signatures(parser_rules: &ParserRules) -> Vec<String> {
     let rule_names = parser_rules.iter().map(|(ref name, _)| name.clone()).collect::<HashSet<_>>();
     for (_, ref rule) in parser_rules {
         let mut signature = String::new();
+        for line in rule.doc() {
+            signature.push_str(&doc_line(line));
+            signature.push('\n');
+        }
         signature.push_str(&format!(
             "fn reduce_{}(m: &Match, source: &str) -> T {{", rule.name
         ));
+        if ! rule.operators.is_empty() {
+            // Binary operations capture their operands instead of the rule's captures.
+            signature.push_str(&format!(
+                "\n    if let Some(ref op) = m.operator {{\n        let lhs = reduce_{0}(m.single(0).unwrap(), source);\n        let rhs = reduce_{0}(m.single(1).unwrap(), source);\n        \n    }}", rule.name
+            ));
+        }
         for (i, &(ref name, cap)) in rule.captures.iter().enumerate() {
             let mut reducer = None;
             let capname = if let &Some(ref name) = name {
@@ -83,4 +100,1159 @@ pub fn generate_reducer_signatures(parser_rules: &ParserRules) -> Vec<String> {
         signatures.push(signature);
     }
     signatures
-}
\ No newline at end of file
+}
+
+/// Marks the lines of the text like the lexer of an indentation-sensitive 
+/// grammar does.
+const LAYOUT_RUNTIME: &str = r#"
+/// Marks the lines of the text with NEWLINE, INDENT and DEDENT tokens.
+fn add_layout_tokens(tokens: Vec<Token>, text: &str) -> Result<Vec<Token>, ParseError> {
+    let mut layout_tokens = Vec::with_capacity(tokens.len());
+    let mut blocks = vec![""];
+    let mut prev_end = None;
+    for token in tokens {
+        let line_start = text[..token.start].rfind('\n').map_or(0, |index| index + 1);
+        if prev_end.map_or(true, |end| end <= line_start) {
+            if let Some(end) = prev_end {
+                layout_tokens.push(Token { kind: TokenKind::NEWLINE, start: end, end });
+            }
+            let line = &text[line_start..];
+            let indentation = &line[..line.len() - line.trim_start_matches(|ch| ch == ' ' || ch == '\t').len()];
+            let current = *blocks.last().unwrap();
+            if indentation.len() > current.len() && indentation.starts_with(current) {
+                blocks.push(indentation);
+                layout_tokens.push(Token { kind: TokenKind::INDENT, start: line_start, end: line_start + indentation.len() });
+            } else if indentation != current {
+                while blocks.last().map_or(false, |block| block.len() > indentation.len()) {
+                    blocks.pop();
+                    layout_tokens.push(Token { kind: TokenKind::DEDENT, start: token.start, end: token.start });
+                }
+                if *blocks.last().unwrap() != indentation {
+                    return Err(ParseError::Dedent { pos: line_start + indentation.len() });
+                }
+            }
+        }
+        prev_end = Some(token.end);
+        layout_tokens.push(token);
+    }
+    if let Some(end) = prev_end {
+        layout_tokens.push(Token { kind: TokenKind::NEWLINE, start: end, end });
+        for _ in 1..blocks.len() {
+            layout_tokens.push(Token { kind: TokenKind::DEDENT, start: end, end });
+        }
+    }
+    Ok(layout_tokens)
+}
+"#;
+
+/// The types and functions shared by every generated parser.
+const PARSER_RUNTIME: &str = r#"
+/// A description of a small part of a source text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    /// The 'type' of this token; what kind of function this text part serves.
+    pub kind: TokenKind,
+    /// The starting byte index of this token in the source text.
+    pub start: usize,
+    /// The ending (excl) byte index of this token in the source text.
+    pub end: usize,
+}
+impl Token {
+    /// Returns the name of this token in the grammar.
+    pub fn name(&self) -> &'static str {
+        self.kind.name()
+    }
+
+    /// Returns the slice of the source text that this token represents.
+    pub fn slice<'a>(&self, text: &'a str) -> &'a str {
+        &text[self.start..self.end]
+    }
+}
+
+/// Describes the value of a Rule or Token matched and captured by a '$' capture pattern.
+#[derive(Debug, Clone)]
+pub enum Capture {
+    /// A single value that is always present.
+    Single(Box<Match>),
+    /// A value that may or may not be assigned when parsing.
+    Optional(Option<Box<Match>>),
+    /// A set of zero or more values, captured by repetitions.
+    Multiple(Vec<Match>),
+    /// A single token.
+    Token(Token),
+}
+impl Capture {
+    fn assign(&mut self, value: Match) {
+        match *self {
+            Capture::Single(_) => *self = Capture::Single(Box::new(value)),
+            Capture::Optional(None) => *self = Capture::Optional(Some(Box::new(value))),
+            Capture::Optional(Some(_)) => panic!("Optional value assigned twice!"),
+            Capture::Multiple(ref mut values) => values.push(value),
+            Capture::Token(_) => unreachable!(),
+        }
+    }
+}
+
+/// Describes a matched rule, including the values that were captured by it.
+#[derive(Debug, Clone)]
+pub struct Match {
+    /// What rule was matched.
+    pub rule: &'static str,
+    /// The values that were captured.
+    pub captures: Vec<Capture>,
+    /// The starting byte index of this match in the source text.
+    pub start: usize,
+    /// The ending (excl) byte index of this match in the source text.
+    pub end: usize,
+    /// The operator token, if this is a binary operation. The operands are
+    /// then captured as the single values 0 and 1.
+    pub operator: Option<Token>,
+}
+impl Match {
+    fn new(rule: &'static str, captures: Vec<Capture>) -> Match {
+        Match { rule, captures, start: 0, end: 0, operator: None }
+    }
+
+    fn missing() -> Match {
+        Match::new("", Vec::new())
+    }
+
+    fn from_token(token: Token) -> Match {
+        Match { 
+            rule: token.name(), start: token.start, end: token.end, 
+            captures: vec![Capture::Token(token)], operator: None,
+        }
+    }
+
+    /// Accesses a 'single' capture value of this match at the given capture index.
+    pub fn single(&self, index: usize) -> Option<&Match> {
+        match self.captures.get(index) {
+            Some(&Capture::Single(ref value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Accesses an 'optional' capture value of this match at the given capture index.
+    pub fn optional(&self, index: usize) -> Option<Option<&Match>> {
+        match self.captures.get(index) {
+            Some(&Capture::Optional(ref value)) => Some(value.as_ref().map(|b| &**b)),
+            _ => None,
+        }
+    }
+
+    /// Accesses a 'multiple' capture value of this match at the given capture index.
+    pub fn multiple(&self, index: usize) -> Option<&Vec<Match>> {
+        match self.captures.get(index) {
+            Some(&Capture::Multiple(ref values)) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Accesses the token of this match, if it is a token match.
+    pub fn token(&self) -> Option<&Token> {
+        match self.captures.get(0) {
+            Some(&Capture::Token(ref token)) if self.captures.len() == 1 => Some(token),
+            _ => None,
+        }
+    }
+
+    /// Accesses the token in the capture value of this match at the given capture index.
+    pub fn single_token(&self, index: usize) -> Option<&Token> {
+        self.single(index).and_then(|m| m.token())
+    }
+
+    /// Returns the slice of the source text that this match spans.
+    pub fn slice<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.start..self.end]
+    }
+}
+
+/// Describes why a text could not be lexed or parsed.
+#[derive(Debug, Clone)]
+pub enum ParseError {
+    /// No token definition matched the text at the given byte index.
+    Lex { pos: usize },
+    /// The line at the given byte index is indented less than the line 
+    /// before it, but not as much as any enclosing line.
+    Dedent { pos: usize },
+    /// A token was found where the grammar doesn't allow it.
+    UnexpectedToken { found: Token, scope: Vec<&'static str>, expected: Vec<TokenKind> },
+    /// The token stream ended while the parse still expected tokens.
+    UnexpectedEof { scope: Vec<&'static str>, expected: Vec<TokenKind> },
+    /// A loop ('%') pattern reached the end of the tokens without being broken.
+    UnclosedLoop { pos: usize, scope: Vec<&'static str> },
+}
+
+/// Changes the stack of lexer modes after a token of the given kind.
+fn change_mode(modes: &mut Vec<usize>, kind: TokenKind) {
+    if let Some(&(_, mode)) = PUSHES.iter().find(|&&(push, _)| push == kind) {
+        modes.push(mode);
+    } else if POPS.contains(&kind) && modes.len() > 1 {
+        modes.pop();
+    }
+}
+
+/// An automaton that finds the longest token of a lexer mode.
+struct ModeLexer {
+    /// The first char of each range of chars that are read alike.
+    range_starts: &'static [u32],
+    /// The column of each range of chars in the transition table.
+    range_classes: &'static [u32],
+    /// The number of columns in the transition table.
+    nof_classes: usize,
+    /// The next state for each state and column. Lexing starts in state 1,
+    /// and state 0 reads no more chars.
+    transitions: &'static [u32],
+    /// The tokens that each state lexes, best first, with whether they can
+    /// only be followed by a non-letter.
+    accepts: &'static [&'static [(TokenKind, bool)]],
+}
+impl ModeLexer {
+    /// Returns the longest token at the start of the text, and its length.
+    fn longest_match(&self, text: &str) -> Option<(TokenKind, usize)> {
+        let mut state = 1;
+        let mut found = None;
+        for (index, ch) in text.char_indices() {
+            let range = match self.range_starts.binary_search(&(ch as u32)) {
+                Ok(range) => range,
+                Err(range) => range - 1,
+            };
+            state = self.transitions[state * self.nof_classes + self.range_classes[range] as usize] as usize;
+            if state == 0 {
+                break;
+            }
+            let end = index + ch.len_utf8();
+            let letter_follows = text[end..].chars().next().map_or(false, |ch| ch.is_alphabetic());
+            if let Some(&(kind, _)) = self.accepts[state].iter().find(|&&(_, alpha)| ! (alpha && letter_follows)) {
+                found = Some((kind, end));
+            }
+        }
+        found
+    }
+}
+
+/// Splits the given text into tokens, lexing the longest token at each 
+/// position.
+pub fn lex(text: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut modes = vec![0];
+    let mut start = 0;
+    while start < text.len() {
+        let kind = match MODES[*modes.last().unwrap()].longest_match(&text[start..]) {
+            Some((kind, len)) => {
+                if ! kind.is_skipped() {
+                    tokens.push(Token { kind, start, end: start + len });
+                }
+                start += len;
+                kind
+            }
+            None => return Err(ParseError::Lex { pos: start }),
+        };
+        change_mode(&mut modes, kind);
+    }
+    add_layout_tokens(tokens, text)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    last_end: usize,
+    scope: Vec<&'static str>,
+    /// The tokens expected by the optional patterns that were skipped since
+    /// the last token was read, which are expected by errors as well.
+    skipped: Vec<&'static [TokenKind]>,
+}
+impl Parser {
+    fn new(mut tokens: Vec<Token>, text: &str) -> Parser {
+        tokens.push(Token { kind: TokenKind::EOF, start: text.len(), end: text.len() });
+        Parser { tokens, pos: 0, last_end: 0, scope: Vec::new(), skipped: Vec::new() }
+    }
+
+    fn peek_kind(&self) -> Option<TokenKind> {
+        self.tokens.get(self.pos).map(|token| token.kind)
+    }
+
+    fn peeks(&self, kinds: &[TokenKind]) -> bool {
+        self.peek_kind().map_or(false, |kind| kinds.contains(&kind))
+    }
+
+    fn can_consume(&mut self, kinds: &[TokenKind], expected: &'static [TokenKind]) -> bool {
+        let consumes = self.peeks(kinds);
+        if ! consumes {
+            self.skipped.push(expected);
+        }
+        consumes
+    }
+
+    fn start(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.last_end, |token| token.start)
+    }
+
+    fn end(&self, start: usize) -> usize {
+        if self.last_end > start { self.last_end } else { start }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        if let Some(ref token) = token {
+            self.pos += 1;
+            self.last_end = token.end;
+            self.skipped.clear();
+        }
+        token
+    }
+
+    fn expect(&mut self, kind: TokenKind, expected: &[TokenKind]) -> Result<Token, ParseError> {
+        match self.peek_kind() {
+            Some(found) if found != kind => Err(self.unexpected(expected)),
+            Some(_) => Ok(self.next().unwrap()),
+            None => Err(self.eof(expected)),
+        }
+    }
+
+    fn expected(&self, expected: &[TokenKind]) -> Vec<TokenKind> {
+        let mut kinds = Vec::new();
+        for &kind in self.skipped.iter().flat_map(|skipped| skipped.iter()).chain(expected) {
+            if ! kinds.contains(&kind) {
+                kinds.push(kind);
+            }
+        }
+        kinds
+    }
+
+    fn unexpected(&self, expected: &[TokenKind]) -> ParseError {
+        ParseError::UnexpectedToken {
+            found: self.tokens[self.pos].clone(),
+            scope: self.scope.clone(),
+            expected: self.expected(expected),
+        }
+    }
+
+    fn eof(&self, expected: &[TokenKind]) -> ParseError {
+        ParseError::UnexpectedEof { scope: self.scope.clone(), expected: self.expected(expected) }
+    }
+
+    fn unclosed_loop(&self, pos: usize) -> ParseError {
+        ParseError::UnclosedLoop { pos, scope: self.scope.clone() }
+    }
+}
+"#;
+
+/// The kinds of tokens that a generated parser knows, named by their
+/// enum variants, in the order of their token ids.
+struct TokenKinds<'a> {
+    symbols: &'a Symbols,
+    names: Vec<Rc<String>>,
+    variants: Vec<String>,
+}
+impl<'a> TokenKinds<'a> {
+    fn new(symbols: &'a Symbols) -> TokenKinds<'a> {
+        let mut kinds = TokenKinds { symbols, names: Vec::new(), variants: Vec::new() };
+        for index in 0..symbols.token_count() {
+            let name = symbols.token_name(TokenId::from_index(index)).clone();
+            // Use the name of the token if it is a valid identifier.
+            let is_ident = name.as_str() != "_"
+                && name.chars().next().map_or(false, |ch| ch.is_ascii_uppercase() || ch == '_')
+                && name.chars().all(|ch| ch.is_ascii_uppercase() || ch.is_ascii_digit() || ch == '_');
+            let variant = if is_ident {
+                name.to_string()
+            } else {
+                format!("Unnamed{}", index)
+            };
+            kinds.names.push(name);
+            kinds.variants.push(variant);
+        }
+        kinds
+    }
+
+    fn variant(&self, name: &str) -> String {
+        let id = self.symbols.token_id(name).expect("Token kind not found");
+        format!("TokenKind::{}", self.variants[id.index()])
+    }
+
+    /// Formats the given token names as a slice of token kinds.
+    fn slice<S: Deref<Target=String>>(&self, names: &[S]) -> String {
+        let kinds = names.iter().map(|name| self.variant(name)).collect::<Vec<_>>();
+        format!("&[{}]", kinds.join(", "))
+    }
+}
+
+/// Returns the names that the functions parsing each rule are suffixed with,
+/// ie: 'rule_key_name'. Rules whose names aren't identifiers get a number if
+/// the name is taken, so that 'key-name' and 'key_name' get different names.
+fn rule_fns(names: &[String]) -> HashMap<&str, String> {
+    let ident = |name: &str| name.chars().map(|ch| if ch.is_alphanumeric() { ch } else { '_' }).collect::<String>();
+    let mut used = Vec::new();
+    let mut fns = HashMap::new();
+    for name in names.iter().filter(|name| ident(name) == **name) {
+        fns.insert(name.as_str(), unique_name(ident(name), &mut used));
+    }
+    for name in names.iter().filter(|name| ident(name) != **name) {
+        fns.insert(name.as_str(), unique_name(ident(name), &mut used));
+    }
+    fns
+}
+
+/// Returns a line of a doc comment.
+fn doc_line(line: &str) -> String {
+    if line.is_empty() {
+        "///".to_string()
+    } else {
+        format!("/// {}", line)
+    }
+}
+
+/// Returns the doc comment of a rule, or the given line if it has none.
+fn rule_doc(rule: &ParserRule, default: String) -> Vec<String> {
+    if rule.doc().is_empty() {
+        vec![default]
+    } else {
+        rule.doc().to_vec()
+    }
+}
+
+fn push_line(out: &mut String, indent: usize, line: &str) {
+    out.extend(iter::repeat(' ').take(indent));
+    out.push_str(line);
+    out.push('\n');
+}
+
+/// Generates the parsing functions of a single rule.
+struct RuleGenerator<'a> {
+    rules: &'a ParserRules,
+    kinds: &'a TokenKinds<'a>,
+    rule: &'a ParserRule,
+    /// The suffixes of the functions parsing each rule (see 'rule_fns').
+    fns: &'a HashMap<&'a str, String>,
+    /// Functions for patterns that need to be parsed separately.
+    helpers: Vec<String>,
+}
+impl<'a> RuleGenerator<'a> {
+    /// Returns the name of the function parsing the given rule.
+    fn rule_fn(&self, rule: &str) -> String {
+        format!("rule_{}", self.fns[rule])
+    }
+    
+    /// Returns the names of the tokens that the predicate holds for, like the
+    /// runtime parser decides with the sets of the nodes of the rule.
+    fn tokens_where<F: Fn(TokenId) -> bool>(&self, predicate: F) -> Vec<Rc<String>> {
+        self.kinds.names.iter().enumerate()
+            .filter(|&(index, _)| predicate(TokenId::from_index(index)))
+            .map(|(_, name)| name.clone())
+            .collect()
+    }
+    
+    /// Returns the tokens that the node is parsed with.
+    fn consuming(&self, node: usize) -> String {
+        let first = &self.rule.sets[node].first;
+        self.kinds.slice(&self.tokens_where(|id| first.contains(id)))
+    }
+    
+    fn expected(&self, pat: &'a Pat) -> String {
+        let mut expected = Vec::new();
+        find_expected_tokens(pat, self.rules, &mut expected, &mut HashMap::new());
+        self.kinds.slice(&expected)
+    }
+    
+    /// Returns an expression of whether the next token starts the pattern at
+    /// the given node, which otherwise adds the pattern to the skipped ones.
+    fn can_consume(&self, pat: &'a Pat, node: usize) -> String {
+        format!("p.can_consume({}, {})", self.consuming(node), self.expected(pat))
+    }
+    
+    /// Generates a function that parses the pattern into the captures of a
+    /// match, returning whether a break token was read.
+    fn gen_pat_fn(&mut self, name: &str, pat: &'a Pat, node: usize, cap_idx: Option<usize>) -> String {
+        let mut out = String::new();
+        push_line(&mut out, 0, &format!(
+            "fn {}(p: &mut Parser, caps: &mut Vec<Capture>) -> Result<bool, ParseError> {{", name
+        ));
+        self.gen_pat(pat, node, cap_idx, 4, &mut out);
+        push_line(&mut out, 4, "Ok(false)");
+        push_line(&mut out, 0, "}");
+        out
+    }
+    
+    /// Generates statements that parse the pattern at the given node like 
+    /// 'parse_with_pattern' does, returning 'Ok(true)' when a break token is
+    /// read.
+    fn gen_pat(&mut self, pat: &'a Pat, mut node: usize, mut cap_idx: Option<usize>, indent: usize, 
+        out: &mut String) 
+    {
+        use grammar::Pat::*;
+        let mut pat = pat;
+        if let Cap(CaptureInfo::Assigned(idx), ref inner) = *pat {
+            cap_idx = Some(idx);
+            pat = inner;
+            node += 1;
+        }
+        match *pat {
+            Rule(ref name) => {
+                let function = if self.rule.prefix_operands.contains(&node) {
+                    format!("operand_{}", self.fns[name.as_str()])
+                } else {
+                    self.rule_fn(name)
+                };
+                push_line(out, indent, &match cap_idx {
+                    Some(idx) => format!("caps[{}].assign({}(p)?);", idx, function),
+                    None => format!("{}(p)?;", function),
+                });
+            }
+            Token(GrammarToken::Named(_, ref name)) => {
+                let expect = format!("p.expect({}, {})?", self.kinds.variant(name), self.expected(pat));
+                push_line(out, indent, &match cap_idx {
+                    Some(idx) => format!("caps[{}].assign(Match::from_token({}));", idx, expect),
+                    None => format!("{};", expect),
+                });
+            }
+            BreakOnToken(GrammarToken::Named(_, ref name)) => {
+                push_line(out, indent, &format!("if p.peeks(&[{}]) {{", self.kinds.variant(name)));
+                push_line(out, indent + 4, "p.next();");
+                push_line(out, indent + 4, "return Ok(true);");
+                push_line(out, indent, "}");
+                push_line(out, indent, &format!("p.skipped.push({});", self.expected(pat)));
+            }
+            Token(_) | BreakOnToken(_) => { 
+                panic!("Attempted to generate a parser without assigning token names"); 
+            }
+            Seq(ref pats) => {
+                for (pat, child) in pats.iter().zip(child_nodes(&self.rule.sets, node, pats.len())) {
+                    self.gen_pat(pat, child, cap_idx, indent, out);
+                }
+            }
+            Opt(ref inner) => {
+                push_line(out, indent, &format!("if {} {{", self.can_consume(inner, node + 1)));
+                if has_break(inner) {
+                    // Breaks don't propagate out of optional patterns.
+                    let name = format!("part_{}_{}", self.fns[self.rule.name.as_str()], self.helpers.len());
+                    let helper = self.gen_pat_fn(&name, inner, node + 1, cap_idx);
+                    self.helpers.push(helper);
+                    push_line(out, indent + 4, &format!("{}(p, caps)?;", name));
+                } else {
+                    self.gen_pat(inner, node + 1, cap_idx, indent + 4, out);
+                }
+                push_line(out, indent, "}");
+            }
+            ZeroPlus(ref inner) => {
+                push_line(out, indent, &format!("while {} {{", self.can_consume(inner, node + 1)));
+                self.gen_pat(inner, node + 1, cap_idx, indent + 4, out);
+                push_line(out, indent, "}");
+            }
+            OnePlus(ref inner) => {
+                push_line(out, indent, "loop {");
+                self.gen_pat(inner, node + 1, cap_idx, indent + 4, out);
+                push_line(out, indent + 4, &format!("if ! {} {{", self.can_consume(inner, node + 1)));
+                push_line(out, indent + 8, "break;");
+                push_line(out, indent + 4, "}");
+                push_line(out, indent, "}");
+            }
+            SepBy(ref item, ref sep, flags) => {
+                let children = child_nodes(&self.rule.sets, node, 2);
+                let (item_node, sep_node) = (children[0], children[1]);
+                let mut indent = indent;
+                if flags.empty {
+                    push_line(out, indent, &format!("if {} {{", self.can_consume(item, item_node)));
+                    indent += 4;
+                }
+                push_line(out, indent, "loop {");
+                self.gen_pat(item, item_node, cap_idx, indent + 4, out);
+                push_line(out, indent + 4, &format!("if ! {} {{", self.can_consume(sep, sep_node)));
+                push_line(out, indent + 8, "break;");
+                push_line(out, indent + 4, "}");
+                // The separators are not part of the captured items.
+                self.gen_pat(sep, sep_node, None, indent + 4, out);
+                if flags.trailing {
+                    push_line(out, indent + 4, &format!("if ! {} {{", self.can_consume(item, item_node)));
+                    push_line(out, indent + 8, "break;");
+                    push_line(out, indent + 4, "}");
+                }
+                push_line(out, indent, "}");
+                if flags.empty {
+                    push_line(out, indent - 4, "}");
+                }
+            }
+            AnyOf(ref pats) => {
+                push_line(out, indent, "match p.peek_kind() {");
+                push_line(out, indent + 4, &format!("None => return Err(p.eof({})),", self.expected(pat)));
+                // Parse with the branch that the token is chosen for.
+                let sets = &self.rule.sets[node];
+                let children = child_nodes(&self.rule.sets, node, pats.len());
+                for (branch, (pat, &child)) in pats.iter().zip(&children).enumerate() {
+                    let names = self.tokens_where(|id| sets.choice(id) == Some(branch));
+                    if names.is_empty() {
+                        continue;
+                    }
+                    push_line(out, indent + 4, &format!("{} => {{", self.arm(&names)));
+                    self.gen_pat(pat, child, cap_idx, indent + 8, out);
+                    push_line(out, indent + 4, "}");
+                }
+                // An optional branch lets the parse continue without it.
+                let ignored = self.tokens_where(|id| sets.choice(id).is_none() && sets.passes.contains(id));
+                if ! ignored.is_empty() {
+                    push_line(out, indent + 4, &format!("{} => {{}}", self.arm(&ignored)));
+                }
+                push_line(out, indent + 4, &format!("Some(_) => return Err(p.unexpected({})),", self.expected(pat)));
+                push_line(out, indent, "}");
+            }
+            Loop(ref inner) => {
+                push_line(out, indent, "let start = match p.peek_kind() {");
+                push_line(out, indent + 4, "Some(_) => p.start(),");
+                push_line(out, indent + 4, &format!("None => return Err(p.eof({})),", self.expected(inner)));
+                push_line(out, indent, "};");
+                push_line(out, indent, "while p.peek_kind().is_some() {");
+                self.gen_pat(inner, node + 1, cap_idx, indent + 4, out);
+                push_line(out, indent, "}");
+                push_line(out, indent, "return Err(p.unclosed_loop(start));");
+            }
+            Cap(_, _) => panic!("Found a capture inside another capture!"),
+            Call(..) => unreachable!(),
+        }
+    }
+    
+    fn arm(&self, names: &[Rc<String>]) -> String {
+        names.iter().map(|name| format!("Some({})", self.kinds.variant(name)))
+            .collect::<Vec<_>>().join(" | ")
+    }
+    
+    /// Generates the functions that parse the rule.
+    fn gen_rule(&mut self) -> String {
+        use captures::CaptureType::*;
+        let rule = self.rule;
+        let name = self.rule_fn(&rule.name);
+        // Each kind of function has its own prefix, so that the names of
+        // different rules don't clash.
+        let operators_fn = format!("operators_{}", self.fns[rule.name.as_str()]);
+        let pat_fn = format!("pat_{}", self.fns[rule.name.as_str()]);
+        let mut out = String::new();
+        let operand_fn = if rule.operators.is_empty() {
+            name.clone()
+        } else {
+            format!("operand_{}", self.fns[rule.name.as_str()])
+        };
+        if ! rule.operators.is_empty() {
+            push_line(&mut out, 0, &format!("fn {}(p: &mut Parser) -> Result<Match, ParseError> {{", name));
+            push_line(&mut out, 4, &format!("{}(p, 0)", operators_fn));
+            push_line(&mut out, 0, "}");
+            push_line(&mut out, 0, "");
+            push_line(&mut out, 0, &format!(
+                "fn {}(p: &mut Parser, min_precedence: usize) -> Result<Match, ParseError> {{", operators_fn
+            ));
+            push_line(&mut out, 4, &format!("let mut lhs = {}(p)?;", operand_fn));
+            push_line(&mut out, 4, "loop {");
+            push_line(&mut out, 8, "let (precedence, is_right) = match p.peek_kind() {");
+            let mut seen = Vec::new();
+            for op in &rule.operators {
+                if seen.contains(&op.token) {
+                    continue;
+                }
+                seen.push(op.token);
+                push_line(&mut out, 12, &format!("Some({}) => ({}, {}),", 
+                    self.kinds.variant(self.rules.symbols.token_name(op.token)), 
+                    op.precedence, op.assoc == Assoc::Right));
+            }
+            push_line(&mut out, 12, "_ => break,");
+            push_line(&mut out, 8, "};");
+            push_line(&mut out, 8, "if precedence < min_precedence {");
+            push_line(&mut out, 12, "break;");
+            push_line(&mut out, 8, "}");
+            push_line(&mut out, 8, "let token = p.next().unwrap();");
+            push_line(&mut out, 8, &format!(
+                "let rhs = {}(p, if is_right {{ precedence }} else {{ precedence + 1 }})?;", operators_fn
+            ));
+            push_line(&mut out, 8, &format!("let mut mtc = Match::new({:?}, Vec::new());", rule.name));
+            push_line(&mut out, 8, "mtc.start = lhs.start;");
+            push_line(&mut out, 8, "mtc.end = rhs.end;");
+            push_line(&mut out, 8, "mtc.operator = Some(token);");
+            push_line(&mut out, 8, "mtc.captures = vec![Capture::Single(Box::new(lhs)), Capture::Single(Box::new(rhs))];");
+            push_line(&mut out, 8, "lhs = mtc;");
+            push_line(&mut out, 4, "}");
+            push_line(&mut out, 4, "Ok(lhs)");
+            push_line(&mut out, 0, "}");
+            push_line(&mut out, 0, "");
+        }
+        let captures = rule.captures.iter().map(|&(_, ref cap)| {
+            match *cap {
+                Single => "Capture::Single(Box::new(Match::missing()))",
+                Optional => "Capture::Optional(None)",
+                Multiple => "Capture::Multiple(Vec::new())",
+            }
+        }).collect::<Vec<_>>();
+        push_line(&mut out, 0, &format!("fn {}(p: &mut Parser) -> Result<Match, ParseError> {{", operand_fn));
+        push_line(&mut out, 4, &format!("let mut mtc = Match::new({:?}, vec![{}]);", rule.name, captures.join(", ")));
+        push_line(&mut out, 4, "mtc.start = p.start();");
+        push_line(&mut out, 4, &format!("p.scope.push({:?});", rule.name));
+        push_line(&mut out, 4, &format!("{}(p, &mut mtc.captures)?;", pat_fn));
+        push_line(&mut out, 4, "p.scope.pop();");
+        push_line(&mut out, 4, "mtc.end = p.end(mtc.start);");
+        push_line(&mut out, 4, "Ok(mtc)");
+        push_line(&mut out, 0, "}");
+        push_line(&mut out, 0, "");
+        out.push_str(&self.gen_pat_fn(&pat_fn, &rule.pat, 0, None));
+        for helper in &self.helpers {
+            push_line(&mut out, 0, "");
+            out.push_str(helper);
+        }
+        out
+    }
+}
+
+/// Returns whether a break ('!') token in the pattern would end the pattern.
+fn has_break(pat: &Pat) -> bool {
+    use grammar::Pat::*;
+    match *pat {
+        BreakOnToken(_) => true,
+        Token(_) | Rule(_) => false,
+        // Optional patterns parse their breaks separately.
+        Opt(_) => false,
+        Seq(ref pats) | AnyOf(ref pats) => pats.iter().any(has_break),
+        Cap(_, ref inner) | ZeroPlus(ref inner) | OnePlus(ref inner) | Loop(ref inner) => has_break(inner),
+        SepBy(ref item, ref sep, _) => has_break(item) || has_break(sep),
+        Call(..) => unreachable!(),
+    }
+}
+
+/// Returns the source code of a self-contained Rust module that lexes and
+/// parses text with the given (validated) rules, producing the same matches 
+/// as 'parse_with_rules' without reading the grammar at runtime. Each rule 
+/// gets a 'parse_<rule>' function, numbered if another rule has the same name
+/// as an identifier, ie: 'key-name' and 'key_name'. The module has no 
+/// dependencies, and is meant to be written to its own file.
+pub fn generate_parser(parser_rules: &ParserRules, lexer_rules: &LexerRules) -> String {
+    let kinds = TokenKinds::new(&parser_rules.symbols);
+    let mut names = parser_rules.iter().map(|(name, _)| name.clone()).collect::<Vec<_>>();
+    names.sort();
+    let fns = rule_fns(&names);
+    
+    let mut out = String::new();
+    out.push_str("//! A lexer and parser generated by heck. Do not edit by hand.\n");
+    out.push_str("#![allow(dead_code, unused_mut, unused_variables, unreachable_code, \n");
+    out.push_str("    unreachable_patterns, non_camel_case_types)]\n\n");
+    
+    out.push_str("/// The kinds of tokens in the grammar.\n");
+    out.push_str("#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]\n");
+    out.push_str("pub enum TokenKind {\n");
+    for (name, variant) in kinds.names.iter().zip(&kinds.variants) {
+        push_line(&mut out, 4, &format!("/// {:?}", name));
+        push_line(&mut out, 4, &format!("{},", variant));
+    }
+    out.push_str("}\n");
+    out.push_str("impl TokenKind {\n");
+    push_line(&mut out, 4, "/// Returns the name of the token in the grammar.");
+    push_line(&mut out, 4, "pub fn name(&self) -> &'static str {");
+    push_line(&mut out, 8, "match *self {");
+    for name in &kinds.names {
+        push_line(&mut out, 12, &format!("{} => {:?},", kinds.variant(name), name));
+    }
+    push_line(&mut out, 8, "}");
+    push_line(&mut out, 4, "}");
+    push_line(&mut out, 0, "");
+    push_line(&mut out, 4, "/// Returns whether tokens of this kind are left out by the lexer.");
+    push_line(&mut out, 4, "pub fn is_skipped(&self) -> bool {");
+    push_line(&mut out, 8, "match *self {");
+    for name in kinds.names.iter().filter(|name| name.starts_with("_")) {
+        push_line(&mut out, 12, &format!("{} => true,", kinds.variant(name)));
+    }
+    push_line(&mut out, 12, "_ => false,");
+    push_line(&mut out, 8, "}");
+    push_line(&mut out, 4, "}");
+    out.push_str("}\n\n");
+    
+    out.push_str("/// The automaton of each lexer mode, starting with the default mode.\n");
+    out.push_str("const MODES: &'static [ModeLexer] = &[\n");
+    for (mode, mode_lexer) in lexer_rules.modes().iter().zip(&lexer_rules.mode_lexers) {
+        let dfa = &mode_lexer.dfa;
+        let (range_starts, range_classes) = dfa.ranges();
+        let (transitions, nof_classes) = dfa.transitions();
+        push_line(&mut out, 4, &format!("// {}", mode.name));
+        push_line(&mut out, 4, "ModeLexer {");
+        push_line(&mut out, 8, &format!("range_starts: &{:?},", range_starts));
+        push_line(&mut out, 8, &format!("range_classes: &{:?},", range_classes));
+        push_line(&mut out, 8, &format!("nof_classes: {},", nof_classes));
+        push_line(&mut out, 8, "transitions: &[");
+        for row in transitions.chunks(nof_classes) {
+            let row = row.iter().map(|state| state.to_string()).collect::<Vec<_>>();
+            push_line(&mut out, 12, &format!("{},", row.join(", ")));
+        }
+        push_line(&mut out, 8, "],");
+        push_line(&mut out, 8, "accepts: &[");
+        for accepts in dfa.accepts() {
+            let accepts = accepts.iter().map(|&pattern| {
+                let (id, _) = mode_lexer.tokens[pattern];
+                format!("({}, {})", kinds.variant(parser_rules.symbols.token_name(id)), dfa.is_keyword(pattern))
+            }).collect::<Vec<_>>();
+            push_line(&mut out, 12, &format!("&[{}],", accepts.join(", ")));
+        }
+        push_line(&mut out, 8, "],");
+        push_line(&mut out, 4, "},");
+    }
+    out.push_str("];\n\n");
+    let mut changes = lexer_rules.mode_changes.iter().collect::<Vec<_>>();
+    changes.sort_by_key(|&(&id, _)| id);
+    let pushes = changes.iter().filter_map(|&(&id, &change)| match change {
+        ModeChange::Push(mode) => Some(format!("({}, {})", kinds.variant(parser_rules.symbols.token_name(id)), mode)),
+        ModeChange::Pop => None,
+    }).collect::<Vec<_>>();
+    let pops = changes.iter().filter(|&&(_, &change)| change == ModeChange::Pop)
+        .map(|&(&id, _)| parser_rules.symbols.token_name(id).clone())
+        .collect::<Vec<_>>();
+    out.push_str("/// The tokens that enter a lexer mode, with the index of the mode.\n");
+    push_line(&mut out, 0, &format!("const PUSHES: &'static [(TokenKind, usize)] = &[{}];", pushes.join(", ")));
+    out.push_str("/// The tokens that return to the lexer mode entered before the current one.\n");
+    push_line(&mut out, 0, &format!("const POPS: &'static [TokenKind] = {};", kinds.slice(&pops)));
+    if lexer_rules.tracks_indentation() {
+        out.push_str(LAYOUT_RUNTIME);
+    } else {
+        out.push_str("\n/// Returns the tokens, since the grammar doesn't track indentation.\n");
+        out.push_str("fn add_layout_tokens(tokens: Vec<Token>, text: &str) -> Result<Vec<Token>, ParseError> {\n");
+        push_line(&mut out, 4, "Ok(tokens)");
+        out.push_str("}\n");
+    }
+    out.push_str(PARSER_RUNTIME);
+    
+    for name in &names {
+        push_line(&mut out, 0, "");
+        push_line(&mut out, 0, &format!("/// Lexes and parses the text, starting with the rule '{}'.", name));
+        push_line(&mut out, 0, &format!(
+            "pub fn parse_{}(text: &str) -> Result<Match, ParseError> {{", fns[name.as_str()]
+        ));
+        push_line(&mut out, 4, "let mut p = Parser::new(lex(text)?, text);");
+        push_line(&mut out, 4, &format!("rule_{}(&mut p)", fns[name.as_str()]));
+        push_line(&mut out, 0, "}");
+    }
+    for name in &names {
+        let mut generator = RuleGenerator {
+            rules: parser_rules,
+            kinds: &kinds,
+            rule: parser_rules.get(name).unwrap(),
+            fns: &fns,
+            helpers: Vec::new(),
+        };
+        push_line(&mut out, 0, "");
+        out.push_str(&generator.gen_rule());
+    }
+    out
+}
+
+/// A value that a capture group can hold.
+#[derive(Debug, Clone, PartialEq)]
+enum Captured {
+    Rule(String),
+    Token(String),
+}
+
+/// Adds the rules and tokens that each capture group of the pattern can hold
+/// to 'values', by capture index.
+fn find_captured_into(pat: &Pat, cap_idx: Option<usize>, values: &mut Vec<Vec<Captured>>) {
+    use grammar::Pat::*;
+    let value = match *pat {
+        Rule(ref name) => Captured::Rule(name.clone()),
+        Token(GrammarToken::Named(_, ref name)) => Captured::Token(name.to_string()),
+        // Break tokens are never captured.
+        Token(_) | BreakOnToken(_) => return,
+        Cap(CaptureInfo::Assigned(idx), ref inner) => {
+            return find_captured_into(inner, Some(idx), values);
+        }
+        Cap(_, ref inner) | Opt(ref inner) | ZeroPlus(ref inner) 
+        | OnePlus(ref inner) | Loop(ref inner) => {
+            return find_captured_into(inner, cap_idx, values);
+        }
+        Seq(ref pats) | AnyOf(ref pats) => {
+            for pat in pats {
+                find_captured_into(pat, cap_idx, values);
+            }
+            return;
+        }
+        SepBy(ref item, ref sep, _) => {
+            find_captured_into(item, cap_idx, values);
+            return find_captured_into(sep, None, values);
+        }
+        Call(..) => unreachable!(),
+    };
+    if let Some(idx) = cap_idx {
+        if values.len() <= idx {
+            values.resize(idx + 1, Vec::new());
+        }
+        if ! values[idx].contains(&value) {
+            values[idx].push(value);
+        }
+    }
+}
+
+/// Converts a rule or token name to a CamelCase type name.
+fn camel_case(name: &str) -> String {
+    let mut ident = String::new();
+    for part in name.split(|ch: char| ! ch.is_alphanumeric()) {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            ident.extend(first.to_uppercase());
+            ident.extend(chars.flat_map(|ch| ch.to_lowercase()));
+        }
+    }
+    if ident.chars().next().map_or(true, |ch| ch.is_numeric()) {
+        ident.insert(0, 'T');
+    }
+    ident
+}
+
+/// Converts a rule or token name to a snake_case field name.
+pub(crate) fn snake_case(name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "as", "box", "break", "const", "continue", "crate", "else", "enum", "extern", 
+        "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", 
+        "mut", "pub", "ref", "return", "self", "static", "struct", "super", "trait", 
+        "true", "type", "unsafe", "use", "where", "while", "abstract", "alignof", 
+        "become", "do", "final", "macro", "offsetof", "override", "priv", "proc", 
+        "pure", "sizeof", "typeof", "unsized", "virtual", "yield", "async", "await", 
+        "dyn", "try",
+    ];
+    let mut ident = name.chars().flat_map(|ch| ch.to_lowercase())
+        .map(|ch| if ch.is_alphanumeric() { ch } else { '_' })
+        .collect::<String>();
+    if ident.chars().next().map_or(true, |ch| ch.is_numeric()) {
+        ident.insert(0, '_');
+    }
+    if KEYWORDS.contains(&ident.as_str()) {
+        ident.push('_');
+    }
+    ident
+}
+
+/// Returns the name, made unique among the given names by adding a number.
+fn unique_name(name: String, used: &mut Vec<String>) -> String {
+    let mut unique = name.clone();
+    let mut i = 1;
+    while used.contains(&unique) {
+        unique = format!("{}{}", name, i);
+        i += 1;
+    }
+    used.push(unique.clone());
+    unique
+}
+
+/// Generates the typed syntax tree of each rule.
+struct AstGenerator {
+    out: String,
+}
+impl AstGenerator {
+    /// Returns the type and the conversion of a value of the given match, 
+    /// boxing rule values that aren't held in a Vec, since rules can be
+    /// recursive.
+    fn value(value: &Captured, mtc: &str, boxed: bool) -> (String, String) {
+        match *value {
+            Captured::Rule(ref name) => {
+                let ty = camel_case(name);
+                if boxed {
+                    (format!("Box<{}>", ty), format!("Box::new({}::from({}))", ty, mtc))
+                } else {
+                    (ty.clone(), format!("{}::from({})", ty, mtc))
+                }
+            }
+            Captured::Token(_) => {
+                ("Token".to_string(), format!("{}.token().unwrap().clone()", mtc))
+            }
+        }
+    }
+    
+    /// Generates an enum of the given values, converted from the match of 
+    /// one of them, with an optional variant for binary operations.
+    fn gen_enum(&mut self, ty: &str, doc: &[String], values: &[Captured], binary: bool, unwrap: Option<usize>) {
+        let mut used = Vec::new();
+        let mut variants = Vec::new();
+        let mut conversions = Vec::new();
+        if binary {
+            used.push("Binary".to_string());
+        }
+        for value in values {
+            let name = match *value {
+                Captured::Rule(ref name) | Captured::Token(ref name) => name,
+            };
+            let variant = unique_name(camel_case(name), &mut used);
+            let (value_ty, conversion) = AstGenerator::value(value, "m", true);
+            variants.push(format!("{}({}),", variant, value_ty));
+            conversions.push(format!("{:?} => {}::{}({}),", name, ty, variant, conversion));
+        }
+        for line in doc {
+            push_line(&mut self.out, 0, &doc_line(line));
+        }
+        push_line(&mut self.out, 0, "#[derive(Debug, Clone)]");
+        push_line(&mut self.out, 0, &format!("pub enum {} {{", ty));
+        if binary {
+            push_line(&mut self.out, 4, &format!("Binary(Box<{0}>, Token, Box<{0}>),", ty));
+        }
+        for variant in &variants {
+            push_line(&mut self.out, 4, variant);
+        }
+        push_line(&mut self.out, 0, "}");
+        push_line(&mut self.out, 0, &format!("impl<'a> From<&'a Match> for {} {{", ty));
+        push_line(&mut self.out, 4, &format!("fn from(m: &'a Match) -> {} {{", ty));
+        if binary {
+            self.gen_binary(ty, 8);
+        }
+        if let Some(idx) = unwrap {
+            push_line(&mut self.out, 8, &format!("let m = m.single({}).unwrap();", idx));
+        }
+        push_line(&mut self.out, 8, "match &m.rule[..] {");
+        for conversion in &conversions {
+            push_line(&mut self.out, 12, conversion);
+        }
+        push_line(&mut self.out, 12, "rule => panic!(\"Unexpected match of {:?}\", rule),");
+        push_line(&mut self.out, 8, "}");
+        push_line(&mut self.out, 4, "}");
+        push_line(&mut self.out, 0, "}");
+        push_line(&mut self.out, 0, "");
+    }
+    
+    fn gen_binary(&mut self, ty: &str, indent: usize) {
+        push_line(&mut self.out, indent, "if let Some(ref op) = m.operator {");
+        push_line(&mut self.out, indent + 4, &format!(
+            "let lhs = Box::new({}::from(m.single(0).unwrap()));", ty
+        ));
+        push_line(&mut self.out, indent + 4, &format!(
+            "let rhs = Box::new({}::from(m.single(1).unwrap()));", ty
+        ));
+        push_line(&mut self.out, indent + 4, &format!("return {}::Binary(lhs, op.clone(), rhs);", ty));
+        push_line(&mut self.out, indent, "}");
+    }
+    
+    fn gen_rule(&mut self, rule: &ParserRule) {
+        use captures::CaptureType::*;
+        let mut values = Vec::new();
+        find_captured_into(&rule.pat, None, &mut values);
+        values.resize(rule.captures.len(), Vec::new());
+        let ty = camel_case(&rule.name);
+        let binary = ! rule.operators.is_empty();
+        
+        // Rules that capture one of several values become enums of them.
+        if rule.captures.len() == 1 && rule.captures[0].1 == Single && values[0].len() > 1 {
+            let doc = rule_doc(rule, format!("The '{}' rule.", rule.name));
+            return self.gen_enum(&ty, &doc, &values[0], binary, Some(0));
+        }
+        let struct_ty = if binary { format!("{}Operand", ty) } else { ty.clone() };
+        
+        // Name the fields after the capture names, or what they capture.
+        let mut used = Vec::new();
+        let mut fields = Vec::new();
+        for (i, &(ref name, cap)) in rule.captures.iter().enumerate() {
+            let name = match *name {
+                Some(ref name) => snake_case(name),
+                None if values[i].len() == 1 => match values[i][0] {
+                    Captured::Rule(ref name) | Captured::Token(ref name) => snake_case(name),
+                },
+                None => format!("cap_{}", i),
+            };
+            let name = unique_name(name, &mut used);
+            // Repeated and optional values are converted in a closure.
+            let mtc = if cap == Single { format!("m.single({}).unwrap()", i) } else { "m".to_string() };
+            let (value_ty, conversion) = if values[i].len() == 1 {
+                AstGenerator::value(&values[i][0], &mtc, cap != Multiple)
+            } else {
+                let enum_ty = format!("{}{}", struct_ty, camel_case(&name));
+                let doc = vec![format!("A value captured by '{}' in the '{}' rule.", name, rule.name)];
+                self.gen_enum(&enum_ty, &doc, &values[i], false, None);
+                (enum_ty.clone(), format!("{}::from({})", enum_ty, mtc))
+            };
+            let (field_ty, field) = match cap {
+                Single => (value_ty, conversion),
+                Optional => (
+                    format!("Option<{}>", value_ty), 
+                    format!("m.optional({}).unwrap().map(|m| {})", i, conversion)
+                ),
+                Multiple => (
+                    format!("Vec<{}>", value_ty), 
+                    format!("m.multiple({}).unwrap().iter().map(|m| {}).collect()", i, conversion)
+                ),
+            };
+            fields.push((name, field_ty, field));
+        }
+        
+        for line in rule_doc(rule, format!("The '{}' rule.", rule.name)) {
+            push_line(&mut self.out, 0, &doc_line(&line));
+        }
+        push_line(&mut self.out, 0, "#[derive(Debug, Clone)]");
+        if fields.is_empty() {
+            push_line(&mut self.out, 0, &format!("pub struct {};", struct_ty));
+        } else {
+            push_line(&mut self.out, 0, &format!("pub struct {} {{", struct_ty));
+            for &(ref name, ref field_ty, _) in &fields {
+                push_line(&mut self.out, 4, &format!("pub {}: {},", name, field_ty));
+            }
+            push_line(&mut self.out, 0, "}");
+        }
+        push_line(&mut self.out, 0, &format!("impl<'a> From<&'a Match> for {} {{", struct_ty));
+        push_line(&mut self.out, 4, &format!("fn from(m: &'a Match) -> {} {{", struct_ty));
+        if fields.is_empty() {
+            push_line(&mut self.out, 8, &struct_ty);
+        } else {
+            push_line(&mut self.out, 8, &format!("{} {{", struct_ty));
+            for &(ref name, _, ref field) in &fields {
+                push_line(&mut self.out, 12, &format!("{}: {},", name, field));
+            }
+            push_line(&mut self.out, 8, "}");
+        }
+        push_line(&mut self.out, 4, "}");
+        push_line(&mut self.out, 0, "}");
+        push_line(&mut self.out, 0, "");
+        
+        if binary {
+            push_line(&mut self.out, 0, &format!("/// The '{}' rule, or a binary operation of it.", rule.name));
+            push_line(&mut self.out, 0, "#[derive(Debug, Clone)]");
+            push_line(&mut self.out, 0, &format!("pub enum {} {{", ty));
+            push_line(&mut self.out, 4, &format!("Binary(Box<{0}>, Token, Box<{0}>),", ty));
+            push_line(&mut self.out, 4, &format!("Operand({}),", struct_ty));
+            push_line(&mut self.out, 0, "}");
+            push_line(&mut self.out, 0, &format!("impl<'a> From<&'a Match> for {} {{", ty));
+            push_line(&mut self.out, 4, &format!("fn from(m: &'a Match) -> {} {{", ty));
+            self.gen_binary(&ty, 8);
+            push_line(&mut self.out, 8, &format!("{}::Operand({}::from(m))", ty, struct_ty));
+            push_line(&mut self.out, 4, "}");
+            push_line(&mut self.out, 0, "}");
+            push_line(&mut self.out, 0, "");
+        }
+    }
+}
+
+/// Returns the source code of a Rust module with a typed syntax tree for the
+/// given rules: a struct for each rule with a field for each of its capture
+/// groups, or an enum for rules that capture one of several values. Every 
+/// type can be converted from the 'Match' of a succesful parse. The module
+/// uses the 'Match' and 'Token' types of the module at 'types_path', which 
+/// can be 'heck' or a generated parser.
+pub fn generate_ast(parser_rules: &ParserRules, types_path: &str) -> String {
+    let mut generator = AstGenerator { out: String::new() };
+    generator.out.push_str("//! Typed syntax trees generated by heck. Do not edit by hand.\n");
+    generator.out.push_str("#![allow(dead_code, unused_imports, unused_variables)]\n\n");
+    generator.out.push_str(&format!("use {}::{{Match, Token}};\n\n", types_path));
+    let mut names = parser_rules.iter().map(|(name, _)| name.clone()).collect::<Vec<_>>();
+    names.sort();
+    for name in &names {
+        generator.gen_rule(parser_rules.get(name).unwrap());
+    }
+    generator.out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grammar::parse_raw_rules;
+    use parser::find_parser_rules;
+    
+    const GRAMMAR: &str = "/// A list.\n///\n/// Of items.\nlist: $item* EOF\nitem: $A\nA: \"a\"\n";
+    
+    #[test]
+    fn doc_comments_lead_the_reducer_signatures() {
+        let parser_rules = find_parser_rules(&parse_raw_rules(GRAMMAR).unwrap());
+        let signatures = generate_reducer_signatures(&parser_rules);
+        let list = signatures.iter().find(|sig| sig.contains("fn reduce_list(")).unwrap();
+        assert!(list.starts_with("/// A list.\n///\n/// Of items.\nfn reduce_list("), "{}", list);
+        let item = signatures.iter().find(|sig| sig.contains("fn reduce_item(")).unwrap();
+        assert!(item.starts_with("fn reduce_item("), "{}", item);
+    }
+    
+    #[test]
+    fn doc_comments_lead_the_ast_types() {
+        let parser_rules = find_parser_rules(&parse_raw_rules(GRAMMAR).unwrap());
+        let code = generate_ast(&parser_rules, "heck");
+        assert!(code.contains("/// A list.\n///\n/// Of items.\n#[derive(Debug, Clone)]\npub struct List "), "{}", code);
+        assert!(code.contains("/// The 'item' rule.\n#[derive(Debug, Clone)]\npub struct Item "), "{}", code);
+    }
+}
@@ -27,3 +27,48 @@ pub fn get_position(text: &str, byte_offset: usize) -> (usize, usize) {
     }
     (line, col)
 }
+
+/// A range of a source text, with the 1-indexed line/column pair of its start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    /// The starting byte index of the range.
+    pub start: usize,
+    /// The ending (excl) byte index of the range.
+    pub end: usize,
+    /// The 1-indexed line of the start of the range.
+    pub line: usize,
+    /// The 1-indexed column of the start of the range.
+    pub col: usize,
+    /// The index of the file of the range in a grammar that is loaded from
+    /// several files, or 0.
+    pub file: usize,
+}
+impl Span {
+    /// Creates a span with an unknown line/column pair.
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end, line: 0, col: 0, file: 0 }
+    }
+    
+    /// Returns a span covering both this span and the given one.
+    pub fn to(&self, other: &Span) -> Span {
+        Span { start: self.start, end: other.end, line: self.line, col: self.col, file: self.file }
+    }
+}
+
+/// Returns the byte offsets where each line in the text starts.
+pub fn find_line_starts(text: &str) -> Vec<usize> {
+    let mut starts = vec![0];
+    starts.extend(text.char_indices().filter(|&(_, ch)| ch == '\n').map(|(i, _)| i + 1));
+    starts
+}
+
+/// Sets the line/column pair of the span, using the line starts of the text
+/// that it is a range of.
+pub fn locate_span(span: &mut Span, text: &str, line_starts: &[usize]) {
+    let line = match line_starts.binary_search(&span.start) {
+        Ok(line) => line,
+        Err(next_line) => next_line - 1,
+    };
+    span.line = line + 1;
+    span.col = text[line_starts[line]..span.start].chars().count() + 1;
+}
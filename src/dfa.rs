@@ -0,0 +1,564 @@
+//! A deterministic finite automaton that matches every token of a lexer mode
+//! at once, so that the longest token at a position is found in one pass
+//! over the text.
+//!
+//! The literals and regexes of the tokens are compiled into one NFA, which is
+//! turned into a DFA by subset construction. The chars are grouped into
+//! classes that every token treats alike, so that the transition table has a
+//! column per class instead of per char.
+//!
+//! Since a token is matched without looking at the text around it, regexes
+//! with anchors or word boundaries can't be compiled, and neither can those
+//! whose automaton gets too large. Lazy repetitions can't be compiled either,
+//! since the longest match is always found.
+
+use regex_syntax::{Error, Parser};
+use regex_syntax::hir::{self, Hir, HirKind, RepetitionKind, RepetitionRange};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+/// A token pattern to match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Pattern<'a> {
+    /// A literal string. Literals of only letters are keywords, which don't
+    /// match the start of a longer word, ie: "if" in "iffy".
+    Literal(&'a str),
+    /// A regex, matched from the position of the token.
+    Regex(&'a str),
+}
+
+/// The state that matches nothing more.
+const DEAD: u32 = 0;
+/// The state that matching starts in.
+pub(crate) const START: usize = 1;
+/// The largest NFA that a set of patterns is compiled into, so that large
+/// counted repetitions don't exhaust memory.
+const MAX_NFA_STATES: usize = 100_000;
+/// The largest DFA that a set of patterns is turned into, since the subset
+/// construction can take exponential time, ie: for '[ab]*a[ab]{20}'.
+const MAX_DFA_STATES: usize = 10_000;
+/// One more than the largest char.
+const CHAR_END: u32 = 0x110000;
+
+/// An automaton that finds the longest token at the start of a text.
+///
+/// When several patterns match the longest token, the one that was given
+/// first wins. Keywords are not matched when a letter follows them.
+#[derive(Debug, Clone)]
+pub(crate) struct Dfa {
+    /// The first char of each char range that is matched alike.
+    range_starts: Vec<u32>,
+    /// The class of each char range.
+    range_classes: Vec<u32>,
+    /// The class of each ASCII char.
+    ascii_classes: Vec<u32>,
+    /// The number of classes, which is the width of the transition table.
+    nof_classes: usize,
+    /// The next state for each state and class, at 'state * nof_classes +
+    /// class'.
+    transitions: Vec<u32>,
+    /// The patterns that each state matches, best first.
+    accepts: Vec<Vec<usize>>,
+    /// Whether each pattern is a keyword.
+    keywords: Vec<bool>,
+}
+
+impl Dfa {
+    /// Compiles the given patterns, best first, into an automaton. The
+    /// patterns that can't be compiled are left out, and returned with their
+    /// index and the reason. A regex is left out as well when the automaton
+    /// would get too large with it.
+    pub fn new(patterns: &[Pattern]) -> (Dfa, Vec<(usize, String)>) {
+        let keywords = patterns.iter().map(|pattern| match *pattern {
+            Pattern::Literal(string) => ! string.is_empty() && string.chars().all(char::is_alphabetic),
+            Pattern::Regex(_) => false,
+        }).collect::<Vec<_>>();
+        let mut left_out = vec![false; patterns.len()];
+        let mut errors = Vec::new();
+        loop {
+            let nfa = Nfa::new(patterns, &mut left_out, &mut errors);
+            if let Some(dfa) = Dfa::from_nfa(&nfa, keywords.clone()) {
+                errors.sort_by_key(|&(index, _)| index);
+                return (dfa, errors);
+            }
+            // Leave out the first regex that is too large on its own, or else
+            // the last regex, until the rest fits.
+            let regexes = (0..patterns.len()).filter(|&index| {
+                ! left_out[index] && match patterns[index] {
+                    Pattern::Regex(_) => true,
+                    Pattern::Literal(_) => false,
+                }
+            }).collect::<Vec<_>>();
+            let too_large = regexes.iter().cloned().find(|&index| {
+                let mut alone = (0..patterns.len()).map(|other| other != index).collect::<Vec<_>>();
+                Dfa::from_nfa(&Nfa::new(patterns, &mut alone, &mut Vec::new()), keywords.clone()).is_none()
+            });
+            let (index, err) = match too_large {
+                Some(index) => (index, format!("Its automaton needs more than {} states", MAX_DFA_STATES)),
+                None => {
+                    // Only a very large number of literals leaves no regex.
+                    let last = regexes.last().cloned().or_else(|| {
+                        (0..patterns.len()).rev().find(|&index| ! left_out[index])
+                    }).expect("An automaton without patterns is too large");
+                    (last, format!(
+                        "The automaton of the tokens of the mode needs more than {} states with it", MAX_DFA_STATES
+                    ))
+                }
+            };
+            left_out[index] = true;
+            errors.push((index, err));
+        }
+    }
+
+    /// Turns the NFA into an automaton, or returns None when it would have
+    /// more than 'MAX_DFA_STATES' states.
+    fn from_nfa(nfa: &Nfa, keywords: Vec<bool>) -> Option<Dfa> {
+        // Split the chars into the ranges where no NFA transition starts or
+        // ends.
+        let mut bounds = BTreeSet::new();
+        bounds.insert(0);
+        for state in &nfa.states {
+            for &(lo, hi, _) in &state.ranges {
+                bounds.insert(lo);
+                if hi + 1 < CHAR_END {
+                    bounds.insert(hi + 1);
+                }
+            }
+        }
+        let range_starts = bounds.into_iter().collect::<Vec<_>>();
+        let range_of = |ch: u32| match range_starts.binary_search(&ch) {
+            Ok(index) => index,
+            Err(index) => index - 1,
+        };
+        let nfa_ranges = nfa.states.iter().map(|state| {
+            state.ranges.iter().map(|&(lo, hi, to)| (range_of(lo), range_of(hi), to)).collect::<Vec<_>>()
+        }).collect::<Vec<_>>();
+
+        // Subset construction, with a column for each char range.
+        let nof_ranges = range_starts.len();
+        let mut sets: Vec<Vec<usize>> = vec![Vec::new(), nfa.closure(vec![0])];
+        let mut ids: HashMap<Vec<usize>, u32> = HashMap::new();
+        ids.insert(sets[0].clone(), DEAD);
+        ids.insert(sets[1].clone(), START as u32);
+        let mut columns = Vec::new();
+        let mut state = 0;
+        while state < sets.len() {
+            let mut targets = vec![Vec::new(); nof_ranges];
+            for &nfa_state in &sets[state] {
+                for &(lo, hi, to) in &nfa_ranges[nfa_state] {
+                    for target in &mut targets[lo..hi + 1] {
+                        target.push(to);
+                    }
+                }
+            }
+            // Ranges that reach the same NFA states reach the same state.
+            let mut moves: HashMap<Vec<usize>, u32> = HashMap::new();
+            for target in targets {
+                let next = match moves.get(&target) {
+                    Some(&next) => next,
+                    None => {
+                        let set = nfa.closure(target.clone());
+                        let next = match ids.get(&set) {
+                            Some(&next) => next,
+                            None if sets.len() >= MAX_DFA_STATES => return None,
+                            None => {
+                                let next = sets.len() as u32;
+                                ids.insert(set.clone(), next);
+                                sets.push(set);
+                                next
+                            }
+                        };
+                        moves.insert(target, next);
+                        next
+                    }
+                };
+                columns.push(next);
+            }
+            state += 1;
+        }
+
+        // Ranges whose columns are alike are matched as one class.
+        let mut classes: HashMap<Vec<u32>, u32> = HashMap::new();
+        let mut range_classes = Vec::with_capacity(nof_ranges);
+        let mut class_columns = Vec::new();
+        for range in 0..nof_ranges {
+            let column = (0..sets.len()).map(|state| columns[state * nof_ranges + range]).collect::<Vec<_>>();
+            let next_class = classes.len() as u32;
+            let class = *classes.entry(column.clone()).or_insert(next_class);
+            if class == next_class {
+                class_columns.push(column);
+            }
+            range_classes.push(class);
+        }
+        let nof_classes = class_columns.len();
+        let mut transitions = vec![DEAD; sets.len() * nof_classes];
+        for (class, column) in class_columns.iter().enumerate() {
+            for (state, &next) in column.iter().enumerate() {
+                transitions[state * nof_classes + class] = next;
+            }
+        }
+        let ascii_classes = (0..128).map(|ch| range_classes[range_of(ch)]).collect();
+        let accepts = sets.iter().map(|set| {
+            let mut accepts = set.iter().filter_map(|&state| nfa.states[state].accept).collect::<Vec<_>>();
+            accepts.sort();
+            accepts.dedup();
+            accepts
+        }).collect();
+        Some(Dfa { range_starts, range_classes, ascii_classes, nof_classes, transitions, accepts, keywords })
+    }
+
+    /// Returns the class of the given char.
+    #[inline]
+    fn class(&self, ch: char) -> usize {
+        let code = ch as u32;
+        let class = if code < 128 {
+            self.ascii_classes[code as usize]
+        } else {
+            match self.range_starts.binary_search(&code) {
+                Ok(index) => self.range_classes[index],
+                Err(index) => self.range_classes[index - 1],
+            }
+        };
+        class as usize
+    }
+
+    /// Returns the best pattern that the given state matches, when the given
+    /// text follows it.
+    #[inline]
+    fn accepted(&self, state: u32, rest: &str) -> Option<usize> {
+        let accepts = &self.accepts[state as usize];
+        if accepts.is_empty() {
+            return None;
+        }
+        let letter_follows = rest.chars().next().map_or(false, char::is_alphabetic);
+        accepts.iter().cloned().find(|&pattern| ! (letter_follows && self.keywords[pattern]))
+    }
+
+    /// Returns the best pattern that matches the longest non-empty prefix of
+    /// the text, along with the length of the prefix.
+    pub fn longest_match(&self, text: &str) -> Option<(usize, usize)> {
+        let mut state = START as u32;
+        let mut found = None;
+        for (index, ch) in text.char_indices() {
+            state = self.transitions[state as usize * self.nof_classes + self.class(ch)];
+            if state == DEAD {
+                break;
+            }
+            let end = index + ch.len_utf8();
+            if let Some(pattern) = self.accepted(state, &text[end..]) {
+                found = Some((pattern, end));
+            }
+        }
+        found
+    }
+
+    /// Returns the first char of each char range, and the class of each range.
+    pub fn ranges(&self) -> (&[u32], &[u32]) {
+        (&self.range_starts, &self.range_classes)
+    }
+
+    /// Returns the transition table, with a row of a next state for each
+    /// class per state.
+    pub fn transitions(&self) -> (&[u32], usize) {
+        (&self.transitions, self.nof_classes)
+    }
+
+    /// Returns the patterns that each state matches, best first.
+    pub fn accepts(&self) -> &[Vec<usize>] {
+        &self.accepts
+    }
+
+    /// Returns whether the given pattern is a keyword.
+    pub fn is_keyword(&self, pattern: usize) -> bool {
+        self.keywords[pattern]
+    }
+
+    /// Returns the patterns that match the empty text, which are never 
+    /// matched.
+    pub fn empty_matches(&self) -> &[usize] {
+        &self.accepts[START]
+    }
+
+    /// Returns one of the shortest texts that lead to each state, if any do.
+    pub fn examples(&self) -> Vec<Option<String>> {
+        // Show each class as a letter or a digit if possible, since they read
+        // best in messages.
+        let mut chars = vec![None; self.nof_classes];
+        let preferred = (b'a'..b'z' + 1).chain(b'A'..b'Z' + 1).chain(b'0'..b'9' + 1).chain(b' '..b'~' + 1);
+        for byte in preferred {
+            let class = self.ascii_classes[byte as usize] as usize;
+            if chars[class].is_none() {
+                chars[class] = Some(byte as char);
+            }
+        }
+        for (&start, &class) in self.range_starts.iter().zip(&self.range_classes) {
+            if chars[class as usize].is_none() {
+                chars[class as usize] = ::std::char::from_u32(start);
+            }
+        }
+        let mut examples = vec![None; self.accepts.len()];
+        examples[START] = Some(String::new());
+        let mut queue = VecDeque::new();
+        queue.push_back(START);
+        while let Some(state) = queue.pop_front() {
+            for (class, &ch) in chars.iter().enumerate() {
+                let next = self.transitions[state * self.nof_classes + class] as usize;
+                if let Some(ch) = ch {
+                    if next != DEAD as usize && examples[next].is_none() {
+                        let mut example = examples[state].clone().unwrap();
+                        example.push(ch);
+                        examples[next] = Some(example);
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        examples
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct NfaState {
+    /// The states that are reached without reading a char.
+    eps: Vec<usize>,
+    /// The states reached by reading a char in a range (inclusive).
+    ranges: Vec<(u32, u32, usize)>,
+    /// The pattern that is matched in this state.
+    accept: Option<usize>,
+}
+
+/// A nondeterministic automaton of the patterns, starting in state 0.
+struct Nfa {
+    states: Vec<NfaState>,
+}
+
+impl Nfa {
+    /// Compiles the patterns that aren't left out into one automaton. The
+    /// patterns that can't be compiled are left out, and added to the errors
+    /// with their index and the reason.
+    fn new(patterns: &[Pattern], left_out: &mut [bool], errors: &mut Vec<(usize, String)>) -> Nfa {
+        let mut nfa = Nfa { states: vec![NfaState::default()] };
+        for (index, pattern) in patterns.iter().enumerate() {
+            if left_out[index] {
+                continue;
+            }
+            let before = nfa.states.len();
+            match nfa.compile_pattern(pattern) {
+                Ok((start, end)) => {
+                    nfa.states[0].eps.push(start);
+                    nfa.states[end].accept = Some(index);
+                }
+                Err(err) => {
+                    nfa.states.truncate(before);
+                    left_out[index] = true;
+                    errors.push((index, err));
+                }
+            }
+        }
+        nfa
+    }
+
+    fn add(&mut self) -> Result<usize, String> {
+        if self.states.len() >= MAX_NFA_STATES {
+            return Err("The pattern is too large".to_string());
+        }
+        self.states.push(NfaState::default());
+        Ok(self.states.len() - 1)
+    }
+
+    /// Returns the sorted set of states that are reached from the given
+    /// states without reading a char.
+    fn closure(&self, mut states: Vec<usize>) -> Vec<usize> {
+        let mut set = BTreeSet::new();
+        while let Some(state) = states.pop() {
+            if set.insert(state) {
+                states.extend(&self.states[state].eps);
+            }
+        }
+        set.into_iter().collect()
+    }
+
+    /// Compiles the pattern, and returns its start and end state.
+    fn compile_pattern(&mut self, pattern: &Pattern) -> Result<(usize, usize), String> {
+        match *pattern {
+            Pattern::Literal(string) => {
+                let start = self.add()?;
+                let mut end = start;
+                for ch in string.chars() {
+                    let next = self.add()?;
+                    self.states[end].ranges.push((ch as u32, ch as u32, next));
+                    end = next;
+                }
+                Ok((start, end))
+            }
+            Pattern::Regex(regex) => {
+                let hir = Parser::new().parse(regex).map_err(|err| match err {
+                    // Leave out the pattern and pointer that the errors are
+                    // displayed with, to keep them to one line.
+                    Error::Parse(ref err) => err.kind().to_string(),
+                    Error::Translate(ref err) => err.kind().to_string(),
+                    _ => err.to_string(),
+                })?;
+                self.compile(&hir)
+            }
+        }
+    }
+
+    /// Compiles the expression, and returns its start and end state.
+    fn compile(&mut self, hir: &Hir) -> Result<(usize, usize), String> {
+        let start = self.add()?;
+        let end = self.add()?;
+        match *hir.kind() {
+            HirKind::Empty => {
+                self.states[start].eps.push(end);
+            }
+            HirKind::Literal(hir::Literal::Unicode(ch)) => {
+                self.states[start].ranges.push((ch as u32, ch as u32, end));
+            }
+            HirKind::Literal(hir::Literal::Byte(byte)) => {
+                if byte >= 0x80 {
+                    return Err(format!("The byte \\x{:x} can't be part of a token", byte));
+                }
+                self.states[start].ranges.push((byte as u32, byte as u32, end));
+            }
+            HirKind::Class(hir::Class::Unicode(ref class)) => {
+                for range in class.ranges() {
+                    self.states[start].ranges.push((range.start() as u32, range.end() as u32, end));
+                }
+            }
+            HirKind::Class(hir::Class::Bytes(ref class)) => {
+                for range in class.ranges() {
+                    if range.end() >= 0x80 {
+                        return Err("Classes of non-ASCII bytes can't be part of a token".to_string());
+                    }
+                    self.states[start].ranges.push((range.start() as u32, range.end() as u32, end));
+                }
+            }
+            HirKind::Anchor(_) | HirKind::WordBoundary(_) => {
+                return Err("Anchors and word boundaries can't be part of a token".to_string());
+            }
+            HirKind::Group(ref group) => {
+                let (inner_start, inner_end) = self.compile(&group.hir)?;
+                self.states[start].eps.push(inner_start);
+                self.states[inner_end].eps.push(end);
+            }
+            HirKind::Concat(ref hirs) => {
+                let mut last = start;
+                for hir in hirs {
+                    let (inner_start, inner_end) = self.compile(hir)?;
+                    self.states[last].eps.push(inner_start);
+                    last = inner_end;
+                }
+                self.states[last].eps.push(end);
+            }
+            HirKind::Alternation(ref hirs) => {
+                for hir in hirs {
+                    let (inner_start, inner_end) = self.compile(hir)?;
+                    self.states[start].eps.push(inner_start);
+                    self.states[inner_end].eps.push(end);
+                }
+            }
+            HirKind::Repetition(ref repetition) => {
+                // Only the longest match is found, so a lazy repetition
+                // would match more than it says.
+                if ! repetition.greedy {
+                    return Err("Lazy repetitions ('*?', '+?', '??') can't be part of a token, since the longest token is always lexed".to_string());
+                }
+                let (min, max) = match repetition.kind {
+                    RepetitionKind::ZeroOrOne => (0, Some(1)),
+                    RepetitionKind::ZeroOrMore => (0, None),
+                    RepetitionKind::OneOrMore => (1, None),
+                    RepetitionKind::Range(RepetitionRange::Exactly(n)) => (n, Some(n)),
+                    RepetitionKind::Range(RepetitionRange::AtLeast(n)) => (n, None),
+                    RepetitionKind::Range(RepetitionRange::Bounded(m, n)) => (m, Some(n)),
+                };
+                let mut last = start;
+                for _ in 0..min {
+                    let (inner_start, inner_end) = self.compile(&repetition.hir)?;
+                    self.states[last].eps.push(inner_start);
+                    last = inner_end;
+                }
+                match max {
+                    None => {
+                        let (inner_start, inner_end) = self.compile(&repetition.hir)?;
+                        self.states[last].eps.push(inner_start);
+                        self.states[inner_end].eps.push(inner_start);
+                        self.states[inner_end].eps.push(end);
+                    }
+                    Some(max) => {
+                        for _ in min..max {
+                            let (inner_start, inner_end) = self.compile(&repetition.hir)?;
+                            self.states[last].eps.push(inner_start);
+                            self.states[last].eps.push(end);
+                            last = inner_end;
+                        }
+                    }
+                }
+                self.states[last].eps.push(end);
+            }
+        }
+        Ok((start, end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Compiles the patterns, which must all be valid.
+    fn dfa(patterns: &[Pattern]) -> Dfa {
+        let (dfa, errors) = Dfa::new(patterns);
+        assert!(errors.is_empty(), "{:?}", errors);
+        dfa
+    }
+    
+    #[test]
+    fn matches_the_longest_token() {
+        let dfa = dfa(&[Pattern::Literal("="), Pattern::Literal("=="), Pattern::Regex("[0-9]+(\\.[0-9]+)?")]);
+        assert_eq!(dfa.longest_match("== 1"), Some((1, 2)));
+        assert_eq!(dfa.longest_match("=1"), Some((0, 1)));
+        assert_eq!(dfa.longest_match("12.5x"), Some((2, 4)));
+        // The longest match is found past a prefix that isn't a token.
+        assert_eq!(dfa.longest_match("12.x"), Some((2, 2)));
+        assert_eq!(dfa.longest_match("x"), None);
+    }
+    
+    #[test]
+    fn prefers_the_first_pattern_and_keywords_before_words() {
+        let dfa = dfa(&[Pattern::Literal("if"), Pattern::Regex("[a-z]+")]);
+        assert_eq!(dfa.longest_match("if x"), Some((0, 2)));
+        assert_eq!(dfa.longest_match("if(x)"), Some((0, 2)));
+        // A keyword doesn't match the start of a longer word.
+        assert_eq!(dfa.longest_match("iffy"), Some((1, 4)));
+        assert_eq!(dfa.longest_match("if2"), Some((0, 2)));
+        assert!(dfa.is_keyword(0));
+        assert!(! dfa.is_keyword(1));
+    }
+    
+    #[test]
+    fn matches_unicode_classes() {
+        let dfa = dfa(&[Pattern::Regex("\\p{Greek}+"), Pattern::Regex("\\w+"), Pattern::Literal("→")]);
+        assert_eq!(dfa.longest_match("αβγ δ"), Some((0, 6)));
+        assert_eq!(dfa.longest_match("αβc"), Some((1, 5)));
+        assert_eq!(dfa.longest_match("日本語!"), Some((1, 9)));
+        assert_eq!(dfa.longest_match("→x"), Some((2, 3)));
+        // Ranges of chars that every pattern treats alike share a class.
+        let (starts, classes) = dfa.ranges();
+        assert_eq!(starts.len(), classes.len());
+        assert!(dfa.transitions().1 < starts.len());
+    }
+    
+    #[test]
+    fn leaves_out_patterns_that_cant_be_compiled() {
+        let patterns = [
+            Pattern::Regex("\\bx"), Pattern::Regex("(a"), Pattern::Regex("[ab]*a[ab]{20}"), 
+            Pattern::Regex("y*"), Pattern::Literal("z"), Pattern::Regex("#.*?"),
+        ];
+        let (dfa, errors) = Dfa::new(&patterns);
+        assert_eq!(errors.iter().map(|&(index, _)| index).collect::<Vec<_>>(), vec![0, 1, 2, 5]);
+        assert!(errors[2].1.contains("states"), "{}", errors[2].1);
+        assert!(errors[3].1.starts_with("Lazy repetitions"), "{}", errors[3].1);
+        assert_eq!(dfa.empty_matches(), &[3]);
+        assert_eq!(dfa.longest_match("z y"), Some((4, 1)));
+        assert_eq!(dfa.longest_match("yy"), Some((3, 2)));
+    }
+}
@@ -0,0 +1,210 @@
+//! The error types returned when reading a grammar, or lexing or parsing a
+//! text fails.
+
+use lexer::Token;
+use common::Span;
+use std::rc::Rc;
+use std::fmt;
+use std::error::Error;
+
+/// A problem found in a grammar, when reading or validating it.
+#[derive(Debug, Clone)]
+pub struct GrammarError {
+    /// The byte index of the error in the grammar.
+    pub pos: usize,
+    /// The 1-indexed line of the error in the grammar, or 0 if the error has
+    /// no place in it, ie: when the grammar file couldn't be read.
+    pub line: usize,
+    /// The 1-indexed column of the error in the grammar.
+    pub col: usize,
+    /// A description of the problem.
+    pub message: String,
+    /// The grammar file of the error, if the grammar was loaded from files.
+    pub file: Option<String>,
+    /// The index of the file in the loaded grammar.
+    pub(crate) file_index: usize,
+}
+impl GrammarError {
+    /// Creates a new error at the given byte index of the grammar.
+    pub fn new(pos: usize, message: String) -> GrammarError {
+        GrammarError { pos, message, line: 0, col: 0, file: None, file_index: 0 }
+    }
+    
+    /// Creates a new error at the start of the given span of the grammar.
+    pub fn at(span: Span, message: String) -> GrammarError {
+        GrammarError { 
+            pos: span.start, line: span.line, col: span.col, message, 
+            file: None, file_index: span.file,
+        }
+    }
+}
+
+impl fmt::Display for GrammarError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref file) = self.file {
+            write!(f, "{}:", file)?;
+        }
+        if self.line > 0 {
+            write!(f, "{}:{}:", self.line, self.col)?;
+        }
+        if self.file.is_some() || self.line > 0 {
+            write!(f, " ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for GrammarError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+/// Describes why a text could not be lexed or parsed.
+#[derive(Debug, Clone)]
+pub enum ParseError {
+    /// The grammar could not be read, or is not logically sound.
+    Grammar(Vec<GrammarError>),
+    /// No token definition matched the text at the given position.
+    Lex {
+        /// The byte index in the source text where lexing failed.
+        pos: usize,
+        /// The 1-indexed line of the position.
+        line: usize,
+        /// The 1-indexed column of the position.
+        col: usize,
+    },
+    /// A line of an indentation-sensitive text is indented less than the 
+    /// line before it, but not as much as any of the lines that it ends the
+    /// indented blocks of.
+    Dedent {
+        /// The byte index in the source text where the line starts.
+        pos: usize,
+        /// The 1-indexed line of the position.
+        line: usize,
+        /// The 1-indexed column of the position.
+        col: usize,
+    },
+    /// A token was found where the grammar doesn't allow it.
+    UnexpectedToken {
+        /// The offending token.
+        found: Token,
+        /// The name of the offending token.
+        found_name: Rc<String>,
+        /// The 1-indexed line where the token starts.
+        line: usize,
+        /// The 1-indexed column where the token starts.
+        col: usize,
+        /// The rules that were being parsed, outermost first.
+        scope: Vec<Rc<String>>,
+        /// The names of every token that would have been accepted instead.
+        expected: Vec<Rc<String>>,
+    },
+    /// The token stream ended while the parse still expected tokens.
+    UnexpectedEof {
+        /// The rules that were being parsed, outermost first.
+        scope: Vec<Rc<String>>,
+        /// The names of every token that would have been accepted instead.
+        expected: Vec<Rc<String>>,
+    },
+    /// A loop ('%') pattern reached the end of the tokens without being broken.
+    UnclosedLoop {
+        /// The byte index in the source text where the loop started.
+        pos: usize,
+        /// The 1-indexed line where the loop started.
+        line: usize,
+        /// The 1-indexed column where the loop started.
+        col: usize,
+        /// The rules that were being parsed, outermost first.
+        scope: Vec<Rc<String>>,
+    },
+    /// The parse was started with a rule that isn't defined.
+    UnknownRule(String),
+}
+
+impl ParseError {
+    /// Returns the rules that were being parsed when this error occurred.
+    pub fn scope(&self) -> &[Rc<String>] {
+        use self::ParseError::*;
+        match *self {
+            UnexpectedToken { ref scope, .. }
+            | UnexpectedEof { ref scope, .. }
+            | UnclosedLoop { ref scope, .. } => scope,
+            Grammar(_) | Lex { .. } | Dedent { .. } | UnknownRule(_) => &[],
+        }
+    }
+
+    /// Returns the names of the tokens that would have been accepted where
+    /// this error occurred.
+    pub fn expected(&self) -> &[Rc<String>] {
+        use self::ParseError::*;
+        match *self {
+            UnexpectedToken { ref expected, .. }
+            | UnexpectedEof { ref expected, .. } => expected,
+            Grammar(_) | Lex { .. } | Dedent { .. } | UnclosedLoop { .. } | UnknownRule(_) => &[],
+        }
+    }
+}
+
+fn fmt_scope(scope: &[Rc<String>]) -> String {
+    scope.iter().flat_map(|s| Some('.').into_iter().chain(s.chars())).skip(1).collect()
+}
+
+fn fmt_expected(expected: &[Rc<String>]) -> String {
+    let names = expected.iter().map(|name| format!("<{}>", name)).collect::<Vec<_>>();
+    match names.len() {
+        0 => "nothing".to_string(),
+        1 => names[0].clone(),
+        _ => format!("one of {}", names.join(", ")),
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::ParseError::*;
+        match *self {
+            Grammar(ref errors) => {
+                write!(f, "Grammar errors:")?;
+                for (i, err) in errors.iter().enumerate() {
+                    write!(f, "\n  {}: {}", i+1, err)?;
+                }
+                Ok(())
+            }
+            Lex { line, col, .. } => {
+                write!(f, "{}:{}: Could not Lex text (no rules matched)", line, col)
+            }
+            Dedent { line, col, .. } => {
+                write!(f, "{}:{}: Inconsistent dedent (no enclosing line is indented as much)", line, col)
+            }
+            UnexpectedToken { ref found_name, line, col, ref scope, ref expected, .. } => {
+                write!(f, "{}:{}:{}: Expected {}, found {}",
+                    fmt_scope(scope), line, col, fmt_expected(expected), found_name)
+            }
+            UnexpectedEof { ref scope, ref expected } => {
+                write!(f, "{}: Unexpected EOF, expected {}",
+                    fmt_scope(scope), fmt_expected(expected))
+            }
+            UnclosedLoop { line, col, ref scope, .. } => {
+                write!(f, "{}:{}:{}: Unclosed loop expression", fmt_scope(scope), line, col)
+            }
+            UnknownRule(ref rule) => {
+                write!(f, "Rule {:?} not found in the given set of rules.", rule)
+            }
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn description(&self) -> &str {
+        use self::ParseError::*;
+        match *self {
+            Grammar(_) => "invalid grammar",
+            Lex { .. } => "no token definition matched the text",
+            Dedent { .. } => "inconsistent dedent",
+            UnexpectedToken { .. } => "unexpected token",
+            UnexpectedEof { .. } => "unexpected end of tokens",
+            UnclosedLoop { .. } => "unclosed loop expression",
+            UnknownRule(_) => "unknown rule",
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! Expansion of parameterized rules, ie: 'list<item, sep>: item (sep item)*'.
+//! A parameterized rule is a template: every use of it is replaced by its
+//! pattern, with the parameters replaced by the arguments of the use.
+//!
+//! The captures of the expanded pattern belong to the rule that uses the 
+//! template, as if the pattern was written out there. So 'list<$$expr, ",">'
+//! captures every 'expr' of the list in one group.
+//!
+//! A template can't use itself, directly or through other templates, since
+//! its expansion would never end. Repetitions are written with '*' or
+//! '{item, sep}' instead.
+
+use common::Span;
+use grammar::{GrammarRule, Pat, RawRules};
+use std::collections::HashMap;
+
+/// The parameterized rules of a grammar, by name.
+pub(crate) type Templates<'a> = HashMap<&'a str, &'a GrammarRule>;
+
+/// Returns the parameterized rules in the given set of raw rules.
+pub(crate) fn find_templates(rules: &RawRules) -> Templates<'_> {
+    rules.iter()
+        .filter(|&&(_, ref rule)| ! rule.params.is_empty())
+        .map(|&(ref name, ref rule)| (name.as_str(), rule))
+        .collect()
+}
+
+/// Where the spans of the expanded nodes come from.
+enum Spans<'s> {
+    /// The nodes are written in the grammar, with the given spans in preorder.
+    Walk(&'s [Span], usize),
+    /// The nodes come from a template that is used at the given span.
+    At(Span),
+}
+impl<'s> Spans<'s> {
+    fn next(&mut self) -> Span {
+        match *self {
+            Spans::Walk(spans, ref mut index) => {
+                let span = spans.get(*index).cloned().unwrap_or_default();
+                *index += 1;
+                span
+            }
+            Spans::At(span) => span,
+        }
+    }
+}
+
+/// The arguments of a template that is being expanded, with their spans.
+type Args<'a> = HashMap<&'a str, (Pat, Vec<Span>)>;
+
+/// Replaces every use of a parameterized rule in the given pattern with the
+/// pattern of that rule, and returns it with the spans of its nodes in
+/// preorder. The expanded nodes of a template are spanned by its use.
+///
+/// Uses of unknown rules are left as rule names, a use with the wrong number
+/// of arguments is replaced by the sequence of its arguments, and a template
+/// that uses itself is expanded to an empty sequence where it does. These are
+/// reported by the validator.
+pub(crate) fn expand_calls(pat: &Pat, spans: &[Span], templates: &Templates) -> (Pat, Vec<Span>) {
+    let mut expanded_spans = Vec::with_capacity(spans.len());
+    let mut expanding = Vec::new();
+    let pat = expand(pat, &mut Spans::Walk(spans, 0), &HashMap::new(), templates,
+        &mut expanding, &mut expanded_spans);
+    (pat, expanded_spans)
+}
+
+fn expand<'a>(pat: &Pat, spans: &mut Spans, args: &Args, templates: &Templates<'a>,
+    expanding: &mut Vec<&'a str>, out: &mut Vec<Span>) -> Pat
+{
+    use grammar::Pat::*;
+    let span = spans.next();
+    let boxed = |inner: &Pat, spans: &mut Spans, expanding: &mut Vec<&'a str>, out: &mut Vec<Span>| {
+        Box::new(expand(inner, spans, args, templates, expanding, out))
+    };
+    match *pat {
+        Rule(ref name) => {
+            if let Some(&(ref arg, ref arg_spans)) = args.get(name.as_str()) {
+                out.extend(arg_spans);
+                return arg.clone();
+            }
+            out.push(span);
+            pat.clone()
+        }
+        Token(_) | BreakOnToken(_) => {
+            out.push(span);
+            pat.clone()
+        }
+        Seq(ref pats) | AnyOf(ref pats) => {
+            out.push(span);
+            let pats = pats.iter()
+                .map(|pat| expand(pat, spans, args, templates, expanding, out))
+                .collect();
+            if let Seq(_) = *pat { Seq(pats) } else { AnyOf(pats) }
+        }
+        Cap(cap, ref inner) => {
+            let mut inner_spans = Vec::new();
+            let inner = expand(inner, spans, args, templates, expanding, &mut inner_spans);
+            // An argument that is captured where the template is used keeps
+            // that capture instead.
+            if let Cap(..) = inner {
+                out.extend(inner_spans);
+                return inner;
+            }
+            out.push(span);
+            out.extend(inner_spans);
+            Cap(cap, Box::new(inner))
+        }
+        Opt(ref inner) => {
+            out.push(span);
+            Opt(boxed(inner, spans, expanding, out))
+        }
+        ZeroPlus(ref inner) => {
+            out.push(span);
+            ZeroPlus(boxed(inner, spans, expanding, out))
+        }
+        OnePlus(ref inner) => {
+            out.push(span);
+            OnePlus(boxed(inner, spans, expanding, out))
+        }
+        Loop(ref inner) => {
+            out.push(span);
+            Loop(boxed(inner, spans, expanding, out))
+        }
+        SepBy(ref item, ref sep, flags) => {
+            out.push(span);
+            let item = boxed(item, spans, expanding, out);
+            SepBy(item, boxed(sep, spans, expanding, out), flags)
+        }
+        Call(ref name, ref call_args) => {
+            // The arguments are expanded where they are written.
+            let mut expanded_args = Vec::with_capacity(call_args.len());
+            for arg in call_args {
+                let mut arg_spans = Vec::new();
+                let arg = expand(arg, spans, args, templates, expanding, &mut arg_spans);
+                expanded_args.push((arg, arg_spans));
+            }
+            let template = match templates.get(name.as_str()) {
+                Some(&template) if expanding.contains(&template.name.as_str()) => {
+                    // Only the template is reported, not its name as unbound.
+                    out.push(span);
+                    return Seq(Vec::new());
+                }
+                Some(&template) if template.params.len() != expanded_args.len() => {
+                    // Its parameters can't be bound, so only the arity is reported.
+                    out.push(span);
+                    let mut pats = Vec::with_capacity(expanded_args.len());
+                    for (arg, arg_spans) in expanded_args {
+                        out.extend(arg_spans);
+                        pats.push(arg);
+                    }
+                    return Seq(pats);
+                }
+                Some(&template) => template,
+                None => {
+                    out.push(span);
+                    return Rule(name.clone());
+                }
+            };
+            let template_args = template.params.iter()
+                .map(|param| param.as_str())
+                .zip(expanded_args)
+                .collect::<Args>();
+            expanding.push(template.name.as_str());
+            let expanded = expand(&template.pat, &mut Spans::At(span), &template_args,
+                templates, expanding, out);
+            expanding.pop();
+            expanded
+        }
+    }
+}
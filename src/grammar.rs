@@ -2,24 +2,199 @@
 //! grammar.
 use pest::prelude::*;
 use common::*;
+use error::GrammarError;
+use symbols::TokenId;
 use std::rc::Rc;
+use std::slice;
 
 /// Rules as returned from the parser (only structure, no semantics).
-pub type RawRules = Vec<(String, GrammarRule)>;
+#[derive(Debug, Clone)]
+pub struct RawRules {
+    /// The rule definitions, in the order they were defined.
+    pub(crate) rules: Vec<(String, GrammarRule)>,
+    /// The grammar-wide directives, in the order they were defined.
+    pub(crate) directives: Vec<Directive>,
+    /// The files imported by the grammar, in the order they were imported.
+    pub(crate) imports: Vec<Import>,
+    /// The files that the rules were loaded from, indexed by the 'file' of
+    /// their spans. Empty if the rules were parsed from a single text.
+    pub(crate) files: Vec<String>,
+}
+impl RawRules {
+    /// Creates an empty set of rules.
+    pub fn new() -> RawRules {
+        RawRules { rules: Vec::new(), directives: Vec::new(), imports: Vec::new(), files: Vec::new() }
+    }
+    
+    /// Returns the files that the rules were loaded from, in the order they
+    /// were loaded.
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+    
+    /// Sets the file of the given error to the file that its position is 
+    /// in, if the rules were loaded from files.
+    pub fn locate_error(&self, err: &mut GrammarError) {
+        err.file = self.files.get(err.file_index).cloned();
+    }
+    
+    /// Marks every span in these rules as being in the file with the given
+    /// index.
+    pub(crate) fn set_file(&mut self, file: usize) {
+        for &mut (_, ref mut rule) in self.rules.iter_mut() {
+            rule.span.file = file;
+            for span in rule.spans.iter_mut() {
+                span.file = file;
+            }
+        }
+        for directive in self.directives.iter_mut() {
+            directive.span.file = file;
+        }
+        for import in self.imports.iter_mut() {
+            import.span.file = file;
+        }
+    }
+    
+    /// Iterates over the named rule definitions.
+    pub fn iter<'a>(&'a self) -> slice::Iter<'a, (String, GrammarRule)> {
+        self.rules.iter()
+    }
+    
+    /// Returns the directives with the given name, in definition order.
+    pub(crate) fn directives_named<'a>(&'a self, name: &str) -> Vec<&'a Directive> {
+        self.directives.iter().filter(|d| d.name == name).collect()
+    }
+}
+impl<'a> IntoIterator for &'a RawRules {
+    type Item = &'a (String, GrammarRule);
+    type IntoIter = slice::Iter<'a, (String, GrammarRule)>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.rules.iter()
+    }
+}
 
 /// Attempts to parse a set of lexing and parsing rules from the given grammar.
-pub fn parse_raw_rules(grammar: &str) -> Result<RawRules, String> {
+pub fn parse_raw_rules(grammar: &str) -> Result<RawRules, GrammarError> {
     let mut parser = Rdp::new(StringInput::new(grammar));
     parser.rules();
     if !parser.end() {
         let (rules, strpos) = parser.expected();
         let (line, col) = get_position(grammar, strpos);
-        Err(format!("{}:{}: Parsing error: expected one of rules: {:?}", line, col, rules))
+        let mut err = GrammarError::new(strpos, format!("Parsing error: expected one of rules: {:?}", rules));
+        err.line = line;
+        err.col = col;
+        Err(err)
     } else {
-        Ok(parser.main())
+        let mut raw_rules = parser.main();
+        // The reducers only know the byte ranges, so find the lines here.
+        let line_starts = find_line_starts(grammar);
+        for &mut (_, ref mut rule) in raw_rules.rules.iter_mut() {
+            rule.doc = find_doc_comment(&grammar[..rule.span.start]);
+            locate_span(&mut rule.span, grammar, &line_starts);
+            for span in rule.spans.iter_mut() {
+                locate_span(span, grammar, &line_starts);
+            }
+        }
+        for directive in raw_rules.directives.iter_mut() {
+            locate_span(&mut directive.span, grammar, &line_starts);
+        }
+        for import in raw_rules.imports.iter_mut() {
+            locate_span(&mut import.span, grammar, &line_starts);
+        }
+        Ok(raw_rules)
     }
 }
 
+/// Returns the lines of the comments at the end of the given text, which
+/// make up the doc comment of the rule right after it. The comments have to
+/// be on the lines right above the rule: a blank line ends the doc comment.
+fn find_doc_comment(before: &str) -> Vec<String> {
+    /// Removes the given marker from the start of a line, along with one 
+    /// space after it. Returns None if the line doesn't start with it.
+    fn strip<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+        line.strip_prefix(marker).map(|line| line.strip_prefix(' ').unwrap_or(line))
+    }
+    
+    let mut lines = before.lines().rev().map(str::trim);
+    // The rule may be indented, but nothing else can come before it.
+    if ! before.ends_with('\n') {
+        lines.next();
+    }
+    let mut doc = Vec::new();
+    while let Some(line) = lines.next() {
+        if let Some(text) = strip(line, "///").or_else(|| strip(line, "//")).or_else(|| strip(line, "#")) {
+            doc.push(text.to_string());
+        } else if let Some(last) = line.strip_suffix("*/") {
+            // Read the block comment up to the line that opens it, unless 
+            // the comment comes after something else on that line.
+            if line.contains("/*") && ! line.starts_with("/*") {
+                break;
+            }
+            let mut block = vec![last.trim_end()];
+            let mut line = line;
+            while ! line.starts_with("/*") {
+                line = match lines.next() {
+                    Some(line) if ! line.contains("/*") || line.starts_with("/*") => line,
+                    _ => break,
+                };
+                block.push(line);
+            }
+            if ! line.starts_with("/*") {
+                break;
+            }
+            for line in block {
+                let line = strip(line, "/**")
+                    .or_else(|| strip(line, "/*"))
+                    .or_else(|| strip(line, "*"))
+                    .unwrap_or(line);
+                doc.push(line.trim_end().to_string());
+            }
+        } else {
+            break;
+        }
+    }
+    doc.reverse();
+    // Drop the blank lines that open or close the comments.
+    while doc.last().map_or(false, |line| line.is_empty()) {
+        doc.pop();
+    }
+    let blank = doc.iter().take_while(|line| line.is_empty()).count();
+    doc.drain(..blank);
+    doc
+}
+
+/// A pattern along with the spans of its nodes, in preorder.
+type Spanned = (Pat, Vec<Span>);
+
+/// Combines the given patterns into one, which spans all of them.
+fn combine_spanned<F: FnOnce(Vec<Pat>) -> Pat>(make: F, items: Vec<Spanned>) -> Spanned {
+    let span = items[0].1[0].to(&items[items.len() - 1].1[0]);
+    let mut spans = vec![span];
+    let mut pats = Vec::with_capacity(items.len());
+    for (pat, item_spans) in items {
+        pats.push(pat);
+        spans.extend(item_spans);
+    }
+    (make(pats), spans)
+}
+
+/// Wraps the given pattern in another, which has the same span.
+fn wrap_spanned<F: FnOnce(Box<Pat>) -> Pat>(make: F, spanned: Spanned) -> Spanned {
+    let (pat, spans) = spanned;
+    let mut wrapped_spans = Vec::with_capacity(spans.len() + 1);
+    wrapped_spans.push(spans[0]);
+    wrapped_spans.extend(spans);
+    (make(Box::new(pat)), wrapped_spans)
+}
+
+/// Returns the span of the next node in a preorder walk of a pattern, and 
+/// advances the walk.
+pub fn next_span(spans: &[Span], index: &mut usize) -> Span {
+    let span = spans.get(*index).cloned().unwrap_or_default();
+    *index += 1;
+    span
+}
+
 const DEBUG_REDUCER: bool = false;
 
 #[inline(always)]
@@ -41,6 +216,12 @@ pub enum Pat {
     AnyOf(Vec<Pat>),
     Loop(Box<Pat>),
     BreakOnToken(GrammarToken),
+    /// One or more items separated by a separator, ie: '{expr, ","}'.
+    SepBy(Box<Pat>, Box<Pat>, SepFlags),
+    /// A use of a parameterized rule with the given arguments, ie: 
+    /// 'list<expr, ",">'. Replaced by the pattern of the rule in
+    /// 'find_parser_rules'.
+    Call(String, Vec<Pat>),
 }
 impl Pat {
     pub fn fmt(&self) -> String {
@@ -64,7 +245,7 @@ impl Pat {
                 s.push_str(&format!("{:?}", inner));
                 s.push('#');
             }
-            Token(GrammarToken::Named(ref inner)) => {
+            Token(GrammarToken::Named(_, ref inner)) => {
                 s.push('<');
                 s.push_str(inner); // add '<>' ?
                 s.push('>');
@@ -134,12 +315,38 @@ impl Pat {
                 s.push('#');
                 s.push('!');
             }
-            BreakOnToken(GrammarToken::Named(ref inner)) => {
+            BreakOnToken(GrammarToken::Named(_, ref inner)) => {
                 s.push('<');
                 s.push_str(inner);
                 s.push('>');
                 s.push('!');
             }
+            SepBy(ref item, ref sep, flags) => {
+                s.push('{');
+                item.fmt_acc(s);
+                s.push_str(", ");
+                sep.fmt_acc(s);
+                if flags.trailing {
+                    s.push(',');
+                }
+                s.push('}');
+                if flags.empty {
+                    s.push('*');
+                }
+            }
+            Call(ref name, ref args) => {
+                s.push('\'');
+                s.push_str(name);
+                s.push_str("'<");
+                let last = args.len() - 1;
+                for (i, arg) in args.iter().enumerate() {
+                    arg.fmt_acc(s);
+                    if i != last {
+                        s.push_str(", ");
+                    }
+                }
+                s.push('>');
+            }
         }
     }
 }
@@ -157,6 +364,36 @@ pub struct GrammarRule {
     pub(crate) nof_captures: usize,
     /// Names for the capture group. Might be empty.
     pub(crate) capture_names: Vec<String>,
+    /// The names of the parameters of the rule, which make it a template for
+    /// the rules that use it. Empty for normal rules.
+    pub(crate) params: Vec<String>,
+    /// Where the rule is defined in the grammar.
+    pub(crate) span: Span,
+    /// Where each node of the pattern is defined in the grammar, in preorder.
+    pub(crate) spans: Vec<Span>,
+    /// The lines of the comments right before the rule, without the comment
+    /// markers. Empty if the rule has none.
+    pub(crate) doc: Vec<String>,
+}
+
+/// A grammar-wide setting, ie: '@sync NEWLINE "}"'.
+#[derive(Debug, Clone)]
+pub struct Directive {
+    /// The name of the directive, without the '@'.
+    pub(crate) name: String,
+    /// The tokens and rule names given to the directive.
+    pub(crate) args: Vec<Pat>,
+    /// Where the directive is defined in the grammar.
+    pub(crate) span: Span,
+}
+
+/// An import of another grammar file, ie: 'import "common.heck"'.
+#[derive(Debug, Clone)]
+pub struct Import {
+    /// The path of the imported file, as written in the grammar.
+    pub(crate) path: String,
+    /// Where the import is written in the grammar.
+    pub(crate) span: Span,
 }
 
 /// Describes a text token.
@@ -166,9 +403,9 @@ pub enum GrammarToken {
     Str(String),
     /// A pattern matching this regular expression, eg: r"[a-zA-Z]+"
     Re(String),
-    /// Refers to a token with the given id.
+    /// Refers to the token with the given id and name.
     /// Assigned by 'find_parser_rules'.
-    Named(Rc<String>),
+    Named(TokenId, Rc<String>),
 }
 
 /// Describes what kind of capture this is.
@@ -185,6 +422,15 @@ pub enum CaptureInfo {
     Assigned(usize),
 }
 
+/// Describes which lists a separated list pattern accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SepFlags {
+    /// The list can end with a separator, ie: '{expr, ",",}'.
+    pub trailing: bool,
+    /// The list can be empty, ie: '{expr, ","}*'.
+    pub empty: bool,
+}
+
 /// Describes how many times a pattern should be parsed.
 #[derive(Debug, Clone)]
 pub enum Quantifier {
@@ -203,7 +449,7 @@ pub enum Quantifier {
 // TODO: Stricter whitespace rules wrt captures and quantifiers
 impl_rdp! {
     grammar! {
-        rules = { (newline | ruledef)+ }
+        rules = { soi ~ (newline | import | directive | ruledef)+ ~ eoi }
         
         rule_name   =   { plain_name | quoted_name }        
         letter      =  _{ ['a'..'z'] | ['A'..'Z'] | ["_"] }
@@ -212,25 +458,37 @@ impl_rdp! {
         
         newline     =  _{ ["\n"] | ["\r\n"] }
         whitespace  =  _{ [" "] | ["\t"] }
+        comment     =  _{ 
+            (["//"] | ["#"]) ~ (!newline ~ any)* 
+            | ["/*"] ~ (!["*/"] ~ any)* ~ ["*/"] 
+        }
         ruledef     =   { 
-            rule_name ~ cap_names? ~ colon ~ 
+            rule_name ~ rule_head? ~ colon ~ 
             newline* ~ 
             pats_or_or ~ (newline | eoi) 
         }
+        import      =   { ["import"] ~ str_token ~ (newline | eoi) }
+        directive   =   { directive_name ~ (token | rule_name)* ~ (newline | eoi) }
+        directive_name = @{ ["@"] ~ letter ~ (letter | ['0'..'9'])* }
+        // A named alternative, since an optional one isn't used as a value.
+        rule_head   =  _{ params | cap_names }
         cap_names   =   { paropen ~ cap_name ~ ([","] ~ cap_name)* ~ parclose }
         cap_name    =   { rule_name } // Same rules make sense, I guess
+        params      =   { angopen ~ rule_name ~ ([","] ~ rule_name)* ~ angclose }
+        sep_by      =   { braceopen ~ pats_or_or_nl ~ comma ~ pats_or_or_nl ~ comma? ~ braceclose }
+        call        =   { rule_name ~ angopen ~ pats_or_or_nl ~ (comma ~ pats_or_or_nl)* ~ angclose }
         patseq      =   { pat+ }
         patseq_nl   =   { (pat_nl ~ newline*)+ }
         pats_or_or  =   { patseq ~ (line ~ patseq)* }
         pats_or_or_nl = { newline* ~ patseq_nl ~ (newline* ~ line ~ newline* ~ patseq_nl)* ~ newline* }
         pat         =   { 
                             capture?
-                            ~ (token | rule_name | paropen ~ pats_or_or_nl ~ parclose) 
+                            ~ (token | call | rule_name | sep_by | paropen ~ pats_or_or_nl ~ parclose) 
                             ~ quantifier? 
                         }
         pat_nl      =   { 
                             capture? 
-                            ~ (token | rule_name | paropen ~ pats_or_or_nl ~ parclose) 
+                            ~ (token | call | rule_name | sep_by | paropen ~ pats_or_or_nl ~ parclose) 
                             ~ quantifier?
                             ~ newline*
                         }
@@ -242,6 +500,11 @@ impl_rdp! {
         
         paropen     =  { ["("] }
         parclose    =  { [")"] }
+        angopen     =  { ["<"] }
+        angclose    =  { [">"] }
+        braceopen   =  { ["{"] }
+        braceclose  =  { ["}"] }
+        comma       =  { [","] }
         dollar      =  { ["$"] }
         star        =  { ["*"] }
         qmark       =  { ["?"] }
@@ -255,24 +518,85 @@ impl_rdp! {
     process! {
         main(&self) -> RawRules {
             (_: rules, mut rev_rules: _rules()) => {
-                rev_rules.reverse();
+                rev_rules.rules.reverse();
+                rev_rules.directives.reverse();
+                rev_rules.imports.reverse();
                 rev_rules
             }
         }
         
-        _rules(&self) -> Vec<(String, GrammarRule)> {
-            (_: ruledef, rule: _ruledef(), mut rule_list: _rules()) => {
-                rule_list.push((rule.name.clone(), rule));
+        _rules(&self) -> RawRules {
+            (def: ruledef, mut rule: _ruledef(), mut rule_list: _rules()) => {
+                rule.span = Span::new(def.start, def.end);
+                rule_list.rules.push((rule.name.clone(), rule));
                 rule_list
             },
+            (def: directive, mut directive: _directive(), mut rule_list: _rules()) => {
+                directive.span = Span::new(def.start, def.end);
+                rule_list.directives.push(directive);
+                rule_list
+            },
+            (def: import, path: _token(), mut rule_list: _rules()) => {
+                if let GrammarToken::Str(path) = path {
+                    rule_list.imports.push(Import { path, span: Span::new(def.start, def.end) });
+                }
+                rule_list
+            },
+            () => {
+                RawRules::new()
+            }
+        }
+        
+        _directive(&self) -> Directive {
+            (&name: directive_name, mut rev_args: _directive_args()) => {
+                rev_args.reverse();
+                Directive { name: name[1..].to_string(), args: rev_args, span: Span::default() }
+            }
+        }
+        
+        _directive_args(&self) -> Vec<Pat> {
+            (_: rule_name, name: _rule_name(), mut tail: _directive_args()) => {
+                tail.push(Pat::Rule(name));
+                tail
+            },
+            (_: token, token: _token(), mut tail: _directive_args()) => {
+                tail.push(Pat::Token(token));
+                tail
+            },
             () => {
                 Vec::new()
             }
         }
         
         _ruledef(&self) -> GrammarRule {
-            (name: _rule_name(), capture_names: _cap_names(), _: pats_or_or, pat: _pats_or_or()) => {
-                GrammarRule { name, pat, capture_names, nof_captures: 1 }
+            (name: _rule_name(), params: _params(), capture_names: _cap_names(), _: pats_or_or, pat: _pats_or_or()) => {
+                let (pat, spans) = pat;
+                GrammarRule { 
+                    name, pat, capture_names, params, spans, 
+                    nof_captures: 1, 
+                    span: Span::default(),
+                    doc: Vec::new(),
+                }
+            }
+        }
+
+        _params(&self) -> Vec<String> {
+            (_: params, _: angopen, mut rev_names: __params()) => {
+                rev_names.reverse();
+                rev_names
+            },
+            () => {
+                Vec::new()
+            }
+        }
+        
+        __params(&self) -> Vec<String> {
+            (_: angclose) => {
+                Vec::new()
+            },
+            (name: _rule_name(), mut tail: __params()) => {
+                tail.push(name);
+                tail
             }
         }
 
@@ -296,19 +620,19 @@ impl_rdp! {
             }
         }
         
-        _pats_or_or(&self) -> Pat {
+        _pats_or_or(&self) -> Spanned {
             (mut rev_pats: __pats_or_or()) => {
                 let has_one = rev_pats.len() == 1;
                 if has_one {
                     rev_pats.pop().unwrap()
                 } else {
                     rev_pats.reverse();
-                    Pat::AnyOf(rev_pats)
+                    combine_spanned(Pat::AnyOf, rev_pats)
                 }
             }
         }
         
-        __pats_or_or(&self) -> Vec<Pat> {
+        __pats_or_or(&self) -> Vec<Spanned> {
             (_: patseq, pat: _patseq(), _: line, mut tail: __pats_or_or()) => {
                 print("__pats_or_or:1");
                 tail.push(pat);
@@ -329,7 +653,7 @@ impl_rdp! {
             }
         }
         
-        _patseq(&self) -> Pat {
+        _patseq(&self) -> Spanned {
             (mut rev_pats: __patseq()) => {
                 print("_patseq:1");
                 print(&format!("_patseq({:?})", rev_pats));
@@ -338,12 +662,12 @@ impl_rdp! {
                     rev_pats.pop().unwrap()
                 } else {
                     rev_pats.reverse();
-                    Pat::Seq(rev_pats)
+                    combine_spanned(Pat::Seq, rev_pats)
                 }
             }
         }
         
-        __patseq(&self) -> Vec<Pat> {
+        __patseq(&self) -> Vec<Spanned> {
             (_: pat, head: _pat(), mut rev_pats: __patseq()) => {
                 print("__patseq:1");
                 rev_pats.push(head);
@@ -360,14 +684,36 @@ impl_rdp! {
             }
         }
         
-        _inner_pat(&self) -> Pat {
-            (_: rule_name, name: _rule_name()) => {
+        _inner_pat(&self) -> Spanned {
+            (tok: call, name: _rule_name(), _: angopen, mut rev_args: _call_args()) => {
+                print("_inner_pat:6");
+                rev_args.reverse();
+                let mut spans = vec![Span::new(tok.start, tok.end)];
+                let mut args = Vec::with_capacity(rev_args.len());
+                for (arg, arg_spans) in rev_args {
+                    args.push(arg);
+                    spans.extend(arg_spans);
+                }
+                (Pat::Call(name, args), spans)
+            },
+            (tok: sep_by, _: braceopen, _: pats_or_or_nl, item: _pats_or_or(), _: comma, 
+                _: pats_or_or_nl, sep: _pats_or_or(), trailing: _trailing(), _: braceclose) => {
+                print("_inner_pat:7");
+                let (item, item_spans) = item;
+                let (sep, sep_spans) = sep;
+                let mut spans = vec![Span::new(tok.start, tok.end)];
+                spans.extend(item_spans);
+                spans.extend(sep_spans);
+                let flags = SepFlags { trailing, empty: false };
+                (Pat::SepBy(Box::new(item), Box::new(sep), flags), spans)
+            },
+            (tok: rule_name, name: _rule_name()) => {
                 print("_inner_pat:1");
-                Pat::Rule(name)
+                (Pat::Rule(name), vec![Span::new(tok.start, tok.end)])
             },
-            (_: token, token: _token()) => {
+            (tok: token, token: _token()) => {
                 print("_inner_pat:2");
-                Pat::Token(token)
+                (Pat::Token(token), vec![Span::new(tok.start, tok.end)])
             },
             (_: pats_or_or, pat: _pats_or_or()) => {
                 print("_inner_pat:3");
@@ -383,19 +729,44 @@ impl_rdp! {
             }
         }
         
-        _pat(&self) -> Pat {
+        _trailing(&self) -> bool {
+            (_: comma) => {
+                true
+            },
+            () => {
+                false
+            }
+        }
+        
+        _call_args(&self) -> Vec<Spanned> {
+            (_: pats_or_or_nl, arg: _pats_or_or(), _: comma, mut tail: _call_args()) => {
+                tail.push(arg);
+                tail
+            },
+            (_: pats_or_or_nl, arg: _pats_or_or(), _: angclose) => {
+                vec![arg]
+            }
+        }
+        
+        _pat(&self) -> Spanned {
             (capture: _capture(), pat: _inner_pat(), quantifier: _quantifier()) => {
                 print("_pat:1");
                 print(&format!("_pat(cap: {:?}, pat: {:?}, quantifier: {:?})", capture, pat, quantifier));
                 let pat = if let Some(quantifier) = quantifier {
-                    match quantifier {
-                        Quantifier::Opt => Pat::Opt(Box::new(pat)),
-                        Quantifier::ZeroPlus => Pat::ZeroPlus(Box::new(pat)),
-                        Quantifier::OnePlus => Pat::OnePlus(Box::new(pat)),
-                        Quantifier::Loop => Pat::Loop(Box::new(pat)),
-                        Quantifier::BreakOnToken => {
-                            if let Pat::Token(token) = pat {
-                                Pat::BreakOnToken(token)
+                    match (quantifier, pat) {
+                        // Separated lists are repeated already.
+                        (Quantifier::ZeroPlus, (Pat::SepBy(item, sep, flags), spans)) => {
+                            let flags = SepFlags { empty: true, ..flags };
+                            (Pat::SepBy(item, sep, flags), spans)
+                        }
+                        (Quantifier::OnePlus, pat @ (Pat::SepBy(..), _)) => pat,
+                        (Quantifier::Opt, pat) => wrap_spanned(Pat::Opt, pat),
+                        (Quantifier::ZeroPlus, pat) => wrap_spanned(Pat::ZeroPlus, pat),
+                        (Quantifier::OnePlus, pat) => wrap_spanned(Pat::OnePlus, pat),
+                        (Quantifier::Loop, pat) => wrap_spanned(Pat::Loop, pat),
+                        (Quantifier::BreakOnToken, pat) => {
+                            if let (Pat::Token(token), spans) = pat {
+                                (Pat::BreakOnToken(token), spans)
                             } else {
                                 // TODO: This should probably just be ignored, 
                                 // then handled in a grammar validation step afterwards
@@ -407,7 +778,7 @@ impl_rdp! {
                     pat
                 };
                 if let Some(cap) = capture {
-                    Pat::Cap(cap, Box::new(pat))
+                    wrap_spanned(|pat| Pat::Cap(cap, pat), pat)
                 } else {
                     pat
                 }
@@ -509,3 +880,39 @@ impl_rdp! {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Returns the doc comment of the rule with the given name.
+    fn doc(raw_rules: &RawRules, name: &str) -> Vec<String> {
+        raw_rules.iter().find(|&&(ref rule, _)| rule == name).unwrap().1.doc.clone()
+    }
+    
+    #[test]
+    fn line_comments_document_the_next_rule() {
+        let grammar = "// A list.\n//  Of items.\nlist: $item* EOF\n\n# One item.\n#\nitem: A\n/// The token.\nA: \"a\"\n";
+        let raw_rules = parse_raw_rules(grammar).unwrap();
+        assert_eq!(doc(&raw_rules, "list"), vec!["A list.", " Of items."]);
+        assert_eq!(doc(&raw_rules, "item"), vec!["One item."]);
+        assert_eq!(doc(&raw_rules, "A"), vec!["The token."]);
+    }
+    
+    #[test]
+    fn block_comments_document_the_next_rule() {
+        let grammar = "/**\n * A list.\n *\n * Of items.\n */\nlist: $item* EOF\n/* One item. */\n  item: A\nA: \"a\" /* Not a doc. */\nB: \"b\"\n";
+        let raw_rules = parse_raw_rules(grammar).unwrap();
+        assert_eq!(doc(&raw_rules, "list"), vec!["A list.", "", "Of items."]);
+        assert_eq!(doc(&raw_rules, "item"), vec!["One item."]);
+        assert!(doc(&raw_rules, "B").is_empty());
+    }
+    
+    #[test]
+    fn blank_line_ends_a_doc_comment() {
+        let grammar = "// About the grammar.\n\n// A list.\nlist: A* EOF\n// Not about A.\n\nA: \"a\"\n";
+        let raw_rules = parse_raw_rules(grammar).unwrap();
+        assert_eq!(doc(&raw_rules, "list"), vec!["A list."]);
+        assert!(doc(&raw_rules, "A").is_empty());
+    }
+}
@@ -2,92 +2,344 @@
 //! syntactically meaningful bites.
 
 use common::*;
-use grammar::{GrammarToken, Pat, RawRules};
-use trie::Trie;
-use std::rc::Rc;
-use regex::Regex;
+use grammar::{GrammarToken, Pat, RawRules, next_span};
+use dfa::{Dfa, Pattern};
+use std::collections::HashMap;
+use std::slice;
+use std::vec;
+use error::ParseError;
+use symbols::{Symbols, TokenId, LAYOUT_TOKENS};
 
 /// Rules that tell the lexing function how to split a text into tokens.
-pub type LexerRules = Vec<TokenDef>;
+#[derive(Debug, Clone)]
+pub struct LexerRules {
+    /// The token definitions, in the order they were defined.
+    pub(crate) tokendefs: Vec<TokenDef>,
+    /// The lexer modes, starting with the default mode.
+    pub(crate) modes: Vec<LexerMode>,
+    /// How lexing a token changes the mode, by token.
+    pub(crate) mode_changes: HashMap<TokenId, ModeChange>,
+    /// Whether the lines of the text are marked with NEWLINE, INDENT and 
+    /// DEDENT tokens ('@indent').
+    pub(crate) indentation: bool,
+    /// The ids of the tokens that are lexed.
+    pub(crate) symbols: Symbols,
+    /// The automaton that lexes the tokens of each mode.
+    pub(crate) mode_lexers: Vec<ModeLexer>,
+    /// The token rules whose patterns aren't literals or regexes, which are
+    /// left out, and reported by the validator.
+    pub(crate) invalid_rules: Vec<(String, Span)>,
+}
+impl LexerRules {
+    /// Iterates over the token definitions.
+    pub fn iter<'a>(&'a self) -> slice::Iter<'a, TokenDef> {
+        self.tokendefs.iter()
+    }
+    
+    /// Returns the lexer modes, starting with the mode that lexing starts in.
+    pub fn modes(&self) -> &[LexerMode] {
+        &self.modes
+    }
+    
+    /// Returns how lexing the given token changes the mode, if it does.
+    pub fn mode_change(&self, id: TokenId) -> Option<ModeChange> {
+        self.mode_changes.get(&id).cloned()
+    }
+    
+    /// Returns whether the lexer marks the lines and indented blocks of the
+    /// text with NEWLINE, INDENT and DEDENT tokens.
+    pub fn tracks_indentation(&self) -> bool {
+        self.indentation
+    }
+    
+    /// Returns the table of token names that the lexed tokens refer to.
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+}
+impl<'a> IntoIterator for &'a LexerRules {
+    type Item = &'a TokenDef;
+    type IntoIter = slice::Iter<'a, TokenDef>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.tokendefs.iter()
+    }
+}
 
 /// A token as described in the Heck grammar.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum TokenDef {
     /// A named token, ie: 'TOKEN: "token"'
-    Named(String, GrammarToken),
+    Named(String, GrammarToken, Span),
     /// An unnamed token, ie: 'rule: "[" expr "]"'
-    Unnamed(GrammarToken),
+    Unnamed(GrammarToken, Span),
+}
+impl TokenDef {
+    /// Returns where this token is first defined in the grammar.
+    pub fn span(&self) -> Span {
+        match *self {
+            TokenDef::Named(_, _, span) | TokenDef::Unnamed(_, span) => span,
+        }
+    }
+}
+
+/// The name of the mode that lexing starts in.
+pub const DEFAULT_MODE: &str = "default";
+
+/// A set of tokens that are lexed together, ie: the parts of a string 
+/// literal. Tokens are lexed in the modes that '@mode' lists them in, or in
+/// the default mode if no '@mode' lists them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexerMode {
+    /// The name of the mode, ie: 'string' for '@mode string STR_TEXT'.
+    pub(crate) name: String,
+    /// The tokens that are lexed in this mode.
+    pub(crate) tokens: Vec<TokenId>,
+}
+impl LexerMode {
+    /// Returns the name of the mode.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    
+    /// Returns the tokens that are lexed in this mode.
+    pub fn tokens(&self) -> &[TokenId] {
+        &self.tokens
+    }
+}
+
+/// How lexing a token changes the stack of lexer modes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModeChange {
+    /// Enters the mode with the given index, ie: '@push string STR_START'.
+    Push(usize),
+    /// Returns to the mode that was entered before the current one, ie: 
+    /// '@pop STR_END'.
+    Pop,
+}
+
+/// An automaton that finds the longest token of a lexer mode at once.
+#[derive(Debug, Clone)]
+pub(crate) struct ModeLexer {
+    pub(crate) dfa: Dfa,
+    /// The token that each pattern of the automaton lexes, and whether the
+    /// token is skipped.
+    pub(crate) tokens: Vec<(TokenId, bool)>,
+    /// The index of the token definition of each pattern.
+    pub(crate) tokendefs: Vec<usize>,
+    /// The patterns that couldn't be compiled, and why.
+    pub(crate) errors: Vec<(usize, String)>,
+}
+
+/// Compiles the tokens of each mode into an automaton, which prefers them
+/// the way 'lex' describes. Regexes that can't be compiled are left out, and
+/// reported by the validator.
+fn find_mode_lexers(tokendefs: &[TokenDef], modes: &[LexerMode], symbols: &Symbols) -> Vec<ModeLexer> {
+    modes.iter().map(|mode| {
+        let mut literals = Vec::new();
+        let mut regexes = Vec::new();
+        for (index, tokendef) in tokendefs.iter().enumerate() {
+            let (name, token) = match *tokendef {
+                TokenDef::Named(ref name, ref token, _) => (name, token),
+                TokenDef::Unnamed(ref token, _) => match *token {
+                    GrammarToken::Str(ref name) | GrammarToken::Re(ref name) => (name, token),
+                    GrammarToken::Named(..) => {
+                        panic!("TokenDef contained a 'GrammarToken::Named' value, which shouldn't be possible");
+                    }
+                },
+            };
+            let id = symbols.token_id(name).expect("Lexer token missing from the symbol table");
+            if ! mode.tokens.contains(&id) {
+                continue;
+            }
+            // Tokens whose names start with '_' are skipped.
+            let lexed = (id, name.starts_with("_"), index);
+            match *token {
+                GrammarToken::Str(ref string) => literals.push((Pattern::Literal(string), lexed)),
+                GrammarToken::Re(ref regex) => regexes.push((Pattern::Regex(regex), lexed)),
+                GrammarToken::Named(..) => {
+                    panic!("TokenDef contained a 'GrammarToken::Named' value, which shouldn't be possible");
+                }
+            }
+        }
+        literals.extend(regexes);
+        let patterns = literals.iter().map(|&(pattern, _)| pattern).collect::<Vec<_>>();
+        let (dfa, errors) = Dfa::new(&patterns);
+        ModeLexer { 
+            dfa, 
+            tokens: literals.iter().map(|&(_, (id, skipped, _))| (id, skipped)).collect(),
+            tokendefs: literals.iter().map(|&(_, (_, _, index))| index).collect(),
+            errors,
+        }
+    }).collect()
+}
+
+/// Returns the id of the token that is given to a directive.
+fn directive_token_id(arg: &Pat, symbols: &Symbols) -> Option<TokenId> {
+    match *arg {
+        Pat::Rule(ref name) if is_token_id(name) => symbols.token_id(name),
+        Pat::Token(GrammarToken::Str(ref name)) | Pat::Token(GrammarToken::Re(ref name)) => {
+            symbols.token_id(name)
+        }
+        _ => None,
+    }
+}
+
+/// Finds the lexer modes of the grammar, and the tokens that change them. 
+/// Malformed directives are reported by the validator, and left out here.
+fn find_modes(rules: &RawRules, tokendefs: &[TokenDef], symbols: &Symbols) 
+    -> (Vec<LexerMode>, HashMap<TokenId, ModeChange>) 
+{
+    let mut modes = vec![LexerMode { name: DEFAULT_MODE.to_string(), tokens: Vec::new() }];
+    let mut listed = Vec::new();
+    for directive in rules.directives_named("mode") {
+        let name = match directive.args.first() {
+            Some(&Pat::Rule(ref name)) if ! is_token_id(name) => name,
+            _ => continue,
+        };
+        let index = match modes.iter().position(|mode| &mode.name == name) {
+            Some(index) => index,
+            None => {
+                modes.push(LexerMode { name: name.clone(), tokens: Vec::new() });
+                modes.len() - 1
+            }
+        };
+        for id in directive.args[1..].iter().filter_map(|arg| directive_token_id(arg, symbols)) {
+            if ! modes[index].tokens.contains(&id) {
+                modes[index].tokens.push(id);
+            }
+            listed.push(id);
+        }
+    }
+    // Tokens that no mode lists are lexed in the default mode.
+    for tokendef in tokendefs {
+        let name = match *tokendef {
+            TokenDef::Named(ref name, _, _) => name,
+            TokenDef::Unnamed(GrammarToken::Str(ref name), _) 
+            | TokenDef::Unnamed(GrammarToken::Re(ref name), _) => name,
+            TokenDef::Unnamed(GrammarToken::Named(..), _) => continue,
+        };
+        if let Some(id) = symbols.token_id(name) {
+            if ! listed.contains(&id) && ! modes[0].tokens.contains(&id) {
+                modes[0].tokens.push(id);
+            }
+        }
+    }
+    
+    let mut mode_changes = HashMap::new();
+    for directive in &rules.directives {
+        let (change, tokens) = match directive.name.as_str() {
+            "push" => {
+                let mode = match directive.args.first() {
+                    Some(&Pat::Rule(ref name)) => modes.iter().position(|mode| &mode.name == name),
+                    _ => None,
+                };
+                match mode {
+                    Some(mode) => (ModeChange::Push(mode), &directive.args[1..]),
+                    None => continue,
+                }
+            }
+            "pop" => (ModeChange::Pop, &directive.args[..]),
+            _ => continue,
+        };
+        for id in tokens.iter().filter_map(|arg| directive_token_id(arg, symbols)) {
+            mode_changes.entry(id).or_insert(change);
+        }
+    }
+    (modes, mode_changes)
+}
+
+/// Adds the token definition, unless it has already been defined elsewhere.
+fn add_tokendef(tokendefs: &mut Vec<TokenDef>, tokendef: TokenDef) {
+    use self::TokenDef::*;
+    let is_defined = tokendefs.iter().any(|other| {
+        match (other, &tokendef) {
+            (&Named(ref a, ref at, _), &Named(ref b, ref bt, _)) => a == b && at == bt,
+            (&Unnamed(ref at, _), &Unnamed(ref bt, _)) => at == bt,
+            _ => false,
+        }
+    });
+    if ! is_defined {
+        tokendefs.push(tokendef);
+    }
 }
 
 /// Finds the lexer rules defined in the given set of raw rules.
 pub fn find_lexer_rules(rules: &RawRules) -> LexerRules {
-    #[inline]
-    fn find_tokendefs_into(pat: &Pat, tokendefs: &mut Vec<TokenDef>) {
+    fn find_tokendefs_into(pat: &Pat, spans: &[Span], index: &mut usize, 
+        tokendefs: &mut Vec<TokenDef>) 
+    {
         use grammar::Pat::*;
+        let span = next_span(spans, index);
         match *pat {
             Rule(_) => {}
             Token(ref token) | BreakOnToken(ref token) => {
-                let t = TokenDef::Unnamed(token.clone());
-                if ! tokendefs.contains(&t) {
-                    tokendefs.push(t);
-                }
+                add_tokendef(tokendefs, TokenDef::Unnamed(token.clone(), span));
             }
-            Seq(ref pats) | AnyOf(ref pats) => {
+            Seq(ref pats) | AnyOf(ref pats) | Call(_, ref pats) => {
                 for pat in pats {
-                    find_tokendefs_into(pat, tokendefs);
+                    find_tokendefs_into(pat, spans, index, tokendefs);
                 }
             }
             Cap(_, ref pat) | Opt(ref pat) | ZeroPlus(ref pat) | OnePlus(ref pat) 
             | Loop(ref pat) => {
-                find_tokendefs_into(pat, tokendefs);
+                find_tokendefs_into(pat, spans, index, tokendefs);
+            }
+            SepBy(ref item, ref sep, _) => {
+                find_tokendefs_into(item, spans, index, tokendefs);
+                find_tokendefs_into(sep, spans, index, tokendefs);
             }
         }
     }
     
     let mut tokendefs = Vec::new();
+    let mut invalid_rules = Vec::new();
     // Iterate the rules in reverse insertion order, since they are inserted
     // in reversed order when reducing recursively in the Pest parser.
     for &(ref key, ref rule) in rules.iter() {
         if is_token_id(key) {
             match rule.pat {
                 Pat::Token(ref token) => {
-                    let t = TokenDef::Named(key.clone(), token.clone());
-                    if ! tokendefs.contains(&t) {
-                        tokendefs.push(t);
-                    }
+                    add_tokendef(&mut tokendefs, TokenDef::Named(key.clone(), token.clone(), rule.span));
                 }
                 Pat::AnyOf(ref pats) => {
-                    // do the above
+                    // The alternatives that are tokens are still lexed.
+                    let mut is_valid = true;
                     for pat in pats {
-                         if let &Pat::Token(ref token) = pat {
-                             let t = TokenDef::Named(key.clone(), token.clone());
-                             if ! tokendefs.contains(&t) {
-                                 tokendefs.push(t);
-                             }
-                         } else {
-                             println!("Found non-token pattern for token rule '{}': {:?}", key, pat);
-                             println!("Warning: TOKEN rules must only contain a single string or regex pattern");
-                         }
+                        if let Pat::Token(ref token) = *pat {
+                            add_tokendef(&mut tokendefs, TokenDef::Named(key.clone(), token.clone(), rule.span));
+                        } else {
+                            is_valid = false;
+                        }
+                    }
+                    if ! is_valid {
+                        invalid_rules.push((key.clone(), rule.span));
                     }
                 }
-                _ => {
-                    println!("Found non-token pattern for token rule '{}': {:?}", key, rule.pat);
-                    println!("Warning: TOKEN rules must only contain a single string or regex pattern");
-                }
+                _ => invalid_rules.push((key.clone(), rule.span)),
             }
         } else {
-            find_tokendefs_into(&rule.pat, &mut tokendefs);
+            find_tokendefs_into(&rule.pat, &rule.spans, &mut 0, &mut tokendefs);
         }
     }
-    tokendefs
-    
+    // Synchronization and operator tokens must be lexed, even if no rule 
+    // mentions them.
+    for directive in &rules.directives {
+        for arg in &directive.args {
+            find_tokendefs_into(arg, &[directive.span], &mut 0, &mut tokendefs);
+        }
+    }
+    let symbols = Symbols::new(rules);
+    let (modes, mode_changes) = find_modes(rules, &tokendefs, &symbols);
+    let indentation = ! rules.directives_named("indent").is_empty();
+    let mode_lexers = find_mode_lexers(&tokendefs, &modes, &symbols);
+    LexerRules { tokendefs, modes, mode_changes, indentation, symbols, mode_lexers, invalid_rules }
 }
 
 /// A description of a small part of a source text.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     /// The 'type' of this token; what kind of function this text part serves.
-    pub name: Rc<String>,
+    pub id: TokenId,
     /// The starting byte index of this token in the source text.
     pub start: usize,
     /// The ending (excl) byte index of this token in the source text.
@@ -95,111 +347,309 @@ pub struct Token {
 }
 impl Token {
     /// Creates a new token.
-    pub fn new(name: Rc<String>, start: usize, end: usize) -> Token {
-        Token { name, start, end }
+    pub fn new(id: TokenId, start: usize, end: usize) -> Token {
+        Token { id, start, end }
+    }
+    
+    /// Returns the name of this token in the given table.
+    pub fn name<'a>(&self, symbols: &'a Symbols) -> &'a str {
+        symbols.token_name(self.id)
     }
     
     /// Returns the slice of the source text that this token represents.
     pub fn slice<'a>(&self, text: &'a str) -> &'a str {
         &text[self.start..self.end]
     }
+    
+    /// Returns the 1-indexed line/column pair where this token starts.
+    pub fn start_position(&self, text: &str) -> (usize, usize) {
+        get_position(text, self.start)
+    }
 }
 
-/// Splits the given text into tokens, based on the given set of rules.
-pub fn lex(text: &str, rules: &LexerRules) -> Result<Vec<Token>, String> {
-    use self::TokenDef::*;
-    let mut literals = Trie::new();
-    let mut regexes = Vec::new();
-    #[inline]
-    fn is_alpha(literal: &str) -> bool {
-        literal.chars().all(|ch| ch.is_alphabetic())
-    }
-    for token_def in rules {
-        match *token_def {
-            Named(ref name, GrammarToken::Str(ref string)) => {
-                literals.insert(string, (Rc::new(name.clone()), is_alpha(string)));
-            }
-            Unnamed(GrammarToken::Str(ref string)) => {
-                literals.insert(string, (Rc::new(string.clone()), is_alpha(string)));
-            }
-            Named(ref name, GrammarToken::Re(ref regex)) => {
-                let mut re = "^".to_string();
-                re.push_str(regex);
-                let reg = match Regex::new(&re) {
-                    Ok(reg) => reg,
-                    Err(err) => {
-                        println!("Error: Could not parse regex {:?}: {:?}", regex, err);
-                        continue;
-                    }
-                };
-                regexes.push((reg, Rc::new(name.clone())));
-            }
-            Unnamed(GrammarToken::Re(ref regex)) => {
-                let mut re = "^".to_string();
-                re.push_str(regex);
-                let reg = match Regex::new(&re) {
-                    Ok(reg) => reg,
-                    Err(err) => {
-                        println!("Error: Could not parse regex {:?}: {:?}", regex, err);
-                        continue;
-                    }
-                };
-                regexes.push((reg, Rc::new(regex.clone())));
-            }
-            _ => {
-                return Err(format!("TokenDef contained a 'GrammarToken::Named' \
-                    value, which shouldn't be possible"))
-            }
-        }
+/// A token read from a token source, as its kind and the byte range of it in
+/// the source text, or the error that stopped the source.
+pub type SourceToken<K> = Result<(K, usize, usize), ParseError>;
+
+/// A lexer that the parser can read tokens from as they are needed, so that
+/// a text can be parsed with a lexer that wasn't made from the grammar.
+pub trait TokenSource {
+    /// The kind of the tokens that are read, ie: an enum of token types.
+    type Kind;
+    
+    /// Reads the next token, or returns None when the text has no more 
+    /// tokens.
+    fn next_token(&mut self) -> Option<SourceToken<Self::Kind>>;
+    
+    /// Returns the id of the grammar token that tokens of the given kind
+    /// are parsed as, usually by its name, ie: 'symbols.token_id("NUM")'.
+    /// Kinds that the grammar has no token for stop the parse with a lexing
+    /// error.
+    fn token_id(&self, kind: &Self::Kind, symbols: &Symbols) -> Option<TokenId>;
+}
+
+/// Reads the tokens returned by 'lex'.
+impl TokenSource for vec::IntoIter<Token> {
+    type Kind = TokenId;
+    
+    fn next_token(&mut self) -> Option<SourceToken<TokenId>> {
+        self.next().map(|token| Ok((token.id, token.start, token.end)))
+    }
+    
+    fn token_id(&self, kind: &TokenId, _symbols: &Symbols) -> Option<TokenId> {
+        Some(*kind)
     }
+}
+
+/// A value that tokens can be read from, so that both token sources and the
+/// tokens returned by 'lex' can be parsed.
+pub trait IntoTokenSource {
+    /// The token source that the tokens are read from.
+    type Source: TokenSource;
+    
+    /// Returns the token source that reads this value.
+    fn into_token_source(self) -> Self::Source;
+}
+
+impl<S: TokenSource> IntoTokenSource for S {
+    type Source = S;
+    
+    fn into_token_source(self) -> S {
+        self
+    }
+}
+
+/// Reads the tokens returned by 'lex'.
+impl IntoTokenSource for Vec<Token> {
+    type Source = vec::IntoIter<Token>;
+    
+    fn into_token_source(self) -> vec::IntoIter<Token> {
+        self.into_iter()
+    }
+}
+
+/// Splits the given text into tokens, based on the given set of rules.
+/// The longest token at each position is lexed, and literals that only 
+/// consist of letters aren't lexed when a letter follows them, ie: 'if' in
+/// 'iffy'. When more than one token matches the longest text, a literal is
+/// preferred over a regex, and otherwise the token defined first is lexed.
+/// Lexing starts in the default mode, and keeps a stack of the modes that the
+/// tokens of the text enter.
+///
+/// The tokens of a mode are matched by one automaton, so regexes can't use
+/// anchors ('^', '$', '\A', '\z') or word boundaries ('\b', '\B'), and
+/// since the longest text is always matched, they can't use lazy repetitions
+/// ('*?', '+?', '??') either: 'r#"#.*?"#' would match a whole line. Such
+/// tokens are left out, and reported by 'validate_tokens_with'.
+pub fn lex(text: &str, rules: &LexerRules) -> Result<Vec<Token>, ParseError> {
+    lex_into(text, rules, None)
+}
+
+/// Splits the given text into tokens like 'lex', and returns the skipped 
+/// tokens (those whose names start with '_') as well, as the trivia of the
+/// text. The trivia are in the order they were lexed.
+pub fn lex_with_trivia(text: &str, rules: &LexerRules) -> Result<(Vec<Token>, Vec<Token>), ParseError> {
+    let mut trivia = Vec::new();
+    let tokens = lex_into(text, rules, Some(&mut trivia))?;
+    Ok((tokens, trivia))
+}
+
+/// Lexes the text, adding the skipped tokens to 'trivia' if it is given.
+fn lex_into(text: &str, rules: &LexerRules, mut trivia: Option<&mut Vec<Token>>) 
+    -> Result<Vec<Token>, ParseError> 
+{
     let mut found_tokens = Vec::new();
+    let mut mode_stack = vec![0];
     let mut start = 0;
     while start < text.len() {
-        //println!("{}:", start);
-        let slice = &text[start..];
-        if let Some((prefix, &(ref token_name, alpha))) = literals.find_longest_match(slice) {
-            let end = start + prefix.len();
-            // Check whether this is only the prefix of an identifier
-            let mut is_valid = true;
-            if alpha {
-                if let Some(ch) = (&text[end..]).chars().next() {
-                    if ch.is_alphabetic() {
-                        is_valid = false;
-                    }
-                }
+        let mode_lexer = &rules.mode_lexers[*mode_stack.last().unwrap()];
+        let (id, skipped, end) = match mode_lexer.dfa.longest_match(&text[start..]) {
+            Some((pattern, len)) => {
+                let (id, skipped) = mode_lexer.tokens[pattern];
+                (id, skipped, start + len)
             }
-            // If the prefix isn't valid, fall through to regex matching
-            if is_valid {
-                let token = Token::new(token_name.clone(), start, end);
-                //println!("-> {}: {:?}", token_name, token.slice(text));
-                if ! token_name.starts_with("_") {
-                    found_tokens.push(token);
-                }
-                start = end;
-                continue;
+            None => {
+                let (line, col) = get_position(text, start);
+                return Err(ParseError::Lex { pos: start, line, col });
             }
+        };
+        if ! skipped {
+            found_tokens.push(Token::new(id, start, end));
+        } else if let Some(ref mut trivia) = trivia {
+            trivia.push(Token::new(id, start, end));
+        }
+        start = end;
+        match rules.mode_change(id) {
+            Some(ModeChange::Push(mode)) => mode_stack.push(mode),
+            // Popping the first mode is left to the parser to report, as a
+            // token without the token that opened it.
+            Some(ModeChange::Pop) => if mode_stack.len() > 1 {
+                mode_stack.pop();
+            },
+            None => {}
         }
-        // Regex matching
-        let mut found = false;
-        for &(ref regex, ref name) in &regexes {
-            if let Some(m) = regex.find(slice) {
-                let s = start + m.start();
-                let e = start + m.end();
-                let token = Token::new(name.clone(), s, e);
-                //println!("-> {}: {:?}", name, token.slice(text));
-                if ! name.starts_with("_") {
-                    found_tokens.push(token);
+    }
+    if rules.indentation {
+        add_layout_tokens(found_tokens, text, &rules.symbols)
+    } else {
+        Ok(found_tokens)
+    }
+}
+
+/// Marks the lines of the text that the tokens were lexed from. Each line
+/// with tokens ends with a NEWLINE. A line that is indented more than the 
+/// line before it starts with an INDENT, and a line that is indented less 
+/// starts with a DEDENT for each indented block that it ends. The blocks end
+/// at the end of the text as well.
+fn add_layout_tokens(tokens: Vec<Token>, text: &str, symbols: &Symbols) -> Result<Vec<Token>, ParseError> {
+    let ids = LAYOUT_TOKENS.iter().map(|&name| {
+        symbols.token_id(name).expect("Layout token missing from the symbol table")
+    }).collect::<Vec<_>>();
+    let (newline, indent, dedent) = (ids[0], ids[1], ids[2]);
+    let mut layout_tokens = Vec::with_capacity(tokens.len());
+    // The indentation of each enclosing block, starting with the unindented
+    // lines.
+    let mut blocks = vec![""];
+    let mut prev_end = None;
+    for token in tokens {
+        let line_start = text[..token.start].rfind('\n').map_or(0, |index| index + 1);
+        // Tokens that span lines continue the line they start on.
+        if prev_end.map_or(true, |end| end <= line_start) {
+            if let Some(end) = prev_end {
+                layout_tokens.push(Token::new(newline, end, end));
+            }
+            let line = &text[line_start..];
+            let indentation = &line[..line.len() - line.trim_start_matches(|ch| ch == ' ' || ch == '\t').len()];
+            let current = *blocks.last().unwrap();
+            if indentation.len() > current.len() && indentation.starts_with(current) {
+                blocks.push(indentation);
+                layout_tokens.push(Token::new(indent, line_start, line_start + indentation.len()));
+            } else if indentation != current {
+                while blocks.last().map_or(false, |block| block.len() > indentation.len()) {
+                    blocks.pop();
+                    layout_tokens.push(Token::new(dedent, token.start, token.start));
+                }
+                if *blocks.last().unwrap() != indentation {
+                    let pos = line_start + indentation.len();
+                    let (line, col) = get_position(text, pos);
+                    return Err(ParseError::Dedent { pos, line, col });
                 }
-                start = e;
-                found = true;
-                break;
             }
         }
-        if ! found {
-            let (line, col) = get_position(text, start);
-            return Err(format!("{}:{}: Could not Lex text (no rules matched)", line, col));
+        prev_end = Some(token.end);
+        layout_tokens.push(token);
+    }
+    if let Some(end) = prev_end {
+        layout_tokens.push(Token::new(newline, end, end));
+        for _ in 1..blocks.len() {
+            layout_tokens.push(Token::new(dedent, end, end));
+        }
+    }
+    Ok(layout_tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grammar::parse_raw_rules;
+    
+    #[test]
+    fn lexes_each_mode_with_its_own_automaton() {
+        let grammar = r##"
+            program: $expr* EOF
+            expr: $NAME | QUOTE $part* ENDQUOTE
+            part: $TEXT | INTERP $expr* "}"
+            NAME: r#"[a-z]+"#
+            QUOTE: "\""
+            ENDQUOTE: "\""
+            TEXT: r#"[^"$]+"#
+            INTERP: "${"
+            _WS: r#"\s+"#
+            @mode string TEXT ENDQUOTE INTERP
+            @push string QUOTE
+            @pop ENDQUOTE "}"
+            @push default INTERP
+        "##;
+        let raw_rules = parse_raw_rules(grammar).unwrap();
+        let rules = find_lexer_rules(&raw_rules);
+        assert_eq!(rules.mode_lexers.len(), 2);
+        assert!(rules.mode_lexers.iter().all(|mode_lexer| mode_lexer.errors.is_empty()));
+        let text = r#"a "b c${d "e"} f" g"#;
+        let tokens = lex(text, &rules).unwrap().into_iter().map(|token| {
+            format!("{}:{}", rules.symbols().token_name(token.id), &text[token.start..token.end])
+        }).collect::<Vec<_>>();
+        assert_eq!(tokens, vec![
+            "NAME:a", "QUOTE:\"", "TEXT:b c", "INTERP:${", "NAME:d", "QUOTE:\"", "TEXT:e", 
+            "ENDQUOTE:\"", "}:}", "TEXT: f", "ENDQUOTE:\"", "NAME:g",
+        ]);
+    }
+    
+    #[test]
+    fn popping_the_first_mode_keeps_lexing_in_it() {
+        let grammar = r##"
+            program: $item* EOF
+            item: $NAME | "}" | "{" $TEXT* "}"
+            NAME: r#"[a-z]+"#
+            TEXT: r#"[a-z]+"#
+            _WS: r#"\s+"#
+            @mode default NAME "{" "}" _WS
+            @mode block TEXT "}" _WS
+            @push block "{"
+            @pop "}"
+        "##;
+        let rules = find_lexer_rules(&parse_raw_rules(grammar).unwrap());
+        // The stray '}'s are left to the parser, and the block still ends.
+        let text = "a } } { b c } d";
+        let tokens = lex(text, &rules).unwrap().into_iter().map(|token| {
+            format!("{}:{}", rules.symbols().token_name(token.id), &text[token.start..token.end])
+        }).collect::<Vec<_>>();
+        assert_eq!(tokens, vec![
+            "NAME:a", "}:}", "}:}", "{:{", "TEXT:b", "TEXT:c", "}:}", "NAME:d",
+        ]);
+    }
+    
+    const INDENTED: &str = r##"
+        program: $stmt* EOF
+        stmt: $NAME ":" NEWLINE INDENT $stmt+ DEDENT | $NAME NEWLINE
+        NAME: r#"[a-z]+"#
+        _WS: r#"[ \t\r\n]+"#
+        @indent
+    "##;
+    
+    /// Returns the names of the tokens lexed from the text, with the empty
+    /// ones at their position.
+    fn layout(text: &str) -> Result<Vec<String>, ParseError> {
+        let rules = find_lexer_rules(&parse_raw_rules(INDENTED).unwrap());
+        Ok(lex(text, &rules)?.into_iter().map(|token| {
+            let name = rules.symbols().token_name(token.id);
+            if token.start == token.end {
+                format!("{}@{}", name, token.start)
+            } else {
+                format!("{}:{}", name, &text[token.start..token.end])
+            }
+        }).collect())
+    }
+    
+    #[test]
+    fn indentation_is_lexed_as_layout_tokens() {
+        assert_eq!(layout("a:\n    b\n    c\nd\n").unwrap(), vec![
+            "NAME:a", ":::", "NEWLINE@2", "INDENT:    ", "NAME:b", "NEWLINE@8",
+            "NAME:c", "NEWLINE@14", "DEDENT@15", "NAME:d", "NEWLINE@16",
+        ]);
+        // Every block that is still open ends at the end of the text.
+        assert_eq!(layout("a:\n  b:\n    c").unwrap(), vec![
+            "NAME:a", ":::", "NEWLINE@2", "INDENT:  ", "NAME:b", ":::", "NEWLINE@7",
+            "INDENT:    ", "NAME:c", "NEWLINE@13", "DEDENT@13", "DEDENT@13",
+        ]);
+        // Blank lines don't change the indentation.
+        assert_eq!(layout("a\n\n   \nb").unwrap(), vec!["NAME:a", "NEWLINE@1", "NAME:b", "NEWLINE@8"]);
+    }
+    
+    #[test]
+    fn dedent_to_an_unknown_indentation_is_an_error() {
+        match layout("a:\n    b\n  c") {
+            Err(ParseError::Dedent { pos, line, col }) => assert_eq!((pos, line, col), (11, 3, 3)),
+            result => panic!("Expected a dedent error, found {:?}", result),
         }
-        
     }
-    Ok(found_tokens)
 }
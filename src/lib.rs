@@ -5,29 +5,49 @@
 
 #[macro_use]
 extern crate pest;
-extern crate regex;
+extern crate regex_syntax;
 
+mod analysis;
 mod common;
+mod dfa;
+mod error;
+mod expand;
 mod grammar;
+mod loader;
 pub mod lexer;
 mod captures;
 mod parser;
-mod trie;
+mod symbols;
+mod trivia;
 mod validate;
 mod codegen;
+pub mod build;
 
 pub use grammar::{GrammarRule, RawRules, GrammarToken, parse_raw_rules};
-pub use lexer::{Token, TokenDef, find_lexer_rules, lex, LexerRules};
+pub use loader::{Resolver, FileResolver, load_grammar};
+pub use lexer::{Token, TokenDef, TokenSource, IntoTokenSource, SourceToken, find_lexer_rules, lex, lex_with_trivia, LexerRules, LexerMode, ModeChange};
 pub use captures::{CaptureType};
-pub use parser::{find_parser_rules, parse_with_rules, Match, ParserRules};
+pub use common::Span;
+pub use symbols::{Symbols, TokenId, RuleId};
+pub use error::{ParseError, GrammarError};
+pub use parser::{find_parser_rules, parse_with_rules, parse_with_recovery, Match, ParserRule, ParserRules};
+pub use analysis::{TokenSet, PatSets};
 pub use validate::{
     validate_rules,
     validate_closed_in_with, 
+    validate_directives_with,
+    validate_params_with,
+    validate_imports_with,
+    validate_operators_with,
+    validate_ll1_conflicts_with,
     validate_unused_tokens_with,
+    validate_tokens_with,
+    validate_unreachable_with,
     validate_endless_loops_into, 
     validate_left_recursion_into,
 };
-pub use codegen::generate_reducer_signatures;
+pub use trivia::parse_lossless;
+pub use codegen::{generate_reducer_signatures, generate_parser, generate_ast};
 
 /*
 Ideas for API design:
@@ -42,20 +62,18 @@ What do I expose, and what would be convenient?
 /// to parse the given text with them, starting the parse at the rule with the
 /// given rule name.
 pub fn lex_and_parse_with_grammar(text: &str, grammar: &str, start_with_rule: &str) 
-    -> Result<Match, String> 
+    -> Result<Match, ParseError> 
 {
-    let raw_rules = parse_raw_rules(grammar)?;
+    let raw_rules = match parse_raw_rules(grammar) {
+        Ok(raw_rules) => raw_rules,
+        Err(err) => return Err(ParseError::Grammar(vec![err])),
+    };
     let lexer_rules = find_lexer_rules(&raw_rules);
     let parser_rules = find_parser_rules(&raw_rules);
     let errors = validate_rules(&raw_rules, &lexer_rules, &parser_rules);
     if errors.len() != 0 {
-        let mut s = String::new();
-        s.push_str("Grammar errors:");
-        for (i, err) in errors.into_iter().enumerate() {
-            s.push_str(&format!("\n  {}: {}", i+1, err.message));
-        }
-        return Err(s);
+        return Err(ParseError::Grammar(errors));
     }
     let tokens = lex(text, &lexer_rules)?;
-    parse_with_rules(start_with_rule, &parser_rules, tokens, text)
+    parse_with_rules(start_with_rule, &parser_rules, tokens.into_iter(), text)
 }
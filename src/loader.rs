@@ -0,0 +1,229 @@
+//! Loads grammars that are spread over several files, which import each
+//! other with 'import "common.heck"'.
+//!
+//! The rules and directives of an imported file are read before those of
+//! the file that imports it. A rule that is defined in the importing file
+//! overrides an imported rule with the same name, so that shared token
+//! definitions can be adjusted per language. Files are found and read by a
+//! 'Resolver', which reads them from disk by default.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use error::GrammarError;
+use grammar::{RawRules, parse_raw_rules};
+
+/// Finds and reads the files of a grammar.
+pub trait Resolver {
+    /// Returns the name of the file that is imported with the given path
+    /// from the file with the given name.
+    fn resolve(&self, path: &str, from: &str) -> String;
+
+    /// Returns the text of the file with the given name.
+    fn read(&mut self, file: &str) -> io::Result<String>;
+}
+
+/// Reads grammar files from disk, resolving imports relative to the
+/// directory of the importing file.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileResolver;
+impl Resolver for FileResolver {
+    fn resolve(&self, path: &str, from: &str) -> String {
+        match Path::new(from).parent() {
+            Some(dir) => dir.join(path).to_string_lossy().into_owned(),
+            None => path.to_string(),
+        }
+    }
+
+    fn read(&mut self, file: &str) -> io::Result<String> {
+        let mut text = String::new();
+        File::open(file)?.read_to_string(&mut text)?;
+        Ok(text)
+    }
+}
+
+/// Reads grammar files from a map of file names to their text. Imports are
+/// looked up by the path as written.
+impl Resolver for HashMap<String, String> {
+    fn resolve(&self, path: &str, _from: &str) -> String {
+        path.to_string()
+    }
+
+    fn read(&mut self, file: &str) -> io::Result<String> {
+        self.get(file).cloned().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no grammar with this name")
+        })
+    }
+}
+
+/// Reads the grammar in the given file along with every file it imports,
+/// and returns the rules of all of them. The files are listed in the
+/// returned rules, and errors point out the file they were found in.
+pub fn load_grammar<R: Resolver>(file: &str, resolver: &mut R) -> Result<RawRules, GrammarError> {
+    let mut loader = Loader { resolver, files: Vec::new(), loading: Vec::new() };
+    let text = match loader.resolver.read(file) {
+        Ok(text) => text,
+        Err(err) => {
+            // There is no import to point out, so the error only names the file.
+            let mut err = GrammarError::new(0, format!("Could not read grammar: {}", err));
+            err.file = Some(file.to_string());
+            return Err(err);
+        }
+    };
+    let mut rules = loader.load(file.to_string(), &text)?;
+    rules.files = loader.files;
+    Ok(rules)
+}
+
+/// The state of loading a grammar and its imports.
+struct Loader<'r, R: 'r> {
+    resolver: &'r mut R,
+    /// Every file that has been loaded so far, in order.
+    files: Vec<String>,
+    /// The files that are being loaded, with the ones they import.
+    loading: Vec<String>,
+}
+impl<'r, R: Resolver> Loader<'r, R> {
+    /// Marks the given error as found in the file with the given index.
+    fn in_file(&self, mut err: GrammarError, index: usize) -> GrammarError {
+        err.file_index = index;
+        err.file = Some(self.files[index].clone());
+        err
+    }
+
+    fn load(&mut self, file: String, text: &str) -> Result<RawRules, GrammarError> {
+        let index = self.files.len();
+        self.files.push(file.clone());
+        let mut own = match parse_raw_rules(text) {
+            Ok(rules) => rules,
+            Err(err) => return Err(self.in_file(err, index)),
+        };
+        own.set_file(index);
+
+        let mut rules = RawRules::new();
+        self.loading.push(file.clone());
+        for import in &own.imports {
+            let imported = self.resolver.resolve(&import.path, &file);
+            if self.loading.contains(&imported) {
+                let err = GrammarError::at(import.span, format!(
+                    "'{}' imports itself (through {})", imported, self.loading.join(" -> ")
+                ));
+                return Err(self.in_file(err, index));
+            }
+            if self.files.contains(&imported) {
+                // Its rules have already been imported.
+                continue;
+            }
+            let text = match self.resolver.read(&imported) {
+                Ok(text) => text,
+                Err(err) => {
+                    let err = GrammarError::at(import.span, format!(
+                        "Could not read '{}': {}", imported, err
+                    ));
+                    return Err(self.in_file(err, index));
+                }
+            };
+            let imported_rules = self.load(imported, &text)?;
+            rules.rules.extend(imported_rules.rules);
+            rules.directives.extend(imported_rules.directives);
+            rules.imports.extend(imported_rules.imports);
+        }
+        self.loading.pop();
+
+        // The rules of this file override the imported ones.
+        rules.rules.retain(|&(ref name, _)| ! own.rules.iter().any(|&(ref own_name, _)| own_name == name));
+        rules.rules.extend(own.rules);
+        rules.directives.extend(own.directives);
+        rules.imports.extend(own.imports);
+        Ok(rules)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lexer::find_lexer_rules;
+    use parser::find_parser_rules;
+    use validate::validate_rules;
+    
+    fn files(files: &[(&str, &str)]) -> HashMap<String, String> {
+        files.iter().map(|&(name, text)| (name.to_string(), text.to_string())).collect()
+    }
+    
+    /// Returns the errors of the loaded grammar, with their files.
+    fn errors(raw_rules: &RawRules) -> Vec<String> {
+        let lexer_rules = find_lexer_rules(raw_rules);
+        let parser_rules = find_parser_rules(raw_rules);
+        validate_rules(raw_rules, &lexer_rules, &parser_rules).iter().map(|err| err.to_string()).collect()
+    }
+    
+    const COMMON: &str = "ID: r#\"[a-z]+\"#\nNUM: r#\"[0-9]+\"#\nSTRING: r#\"\"[^\"]*\"\"#\n_WS: r#\"\\s+\"#\n";
+    
+    #[test]
+    fn imported_rules_come_before_the_own_ones() {
+        let mut resolver = files(&[
+            ("main.heck", "import \"common.heck\"\nprogram: $ID* EOF\n"),
+            ("common.heck", COMMON),
+        ]);
+        let raw_rules = load_grammar("main.heck", &mut resolver).unwrap();
+        let names = raw_rules.iter().map(|&(ref name, _)| name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["ID", "NUM", "STRING", "_WS", "program"]);
+        assert_eq!(raw_rules.files, vec!["main.heck", "common.heck"]);
+    }
+    
+    #[test]
+    fn unused_imported_tokens_are_not_reported() {
+        let mut resolver = files(&[
+            ("main.heck", "import \"common.heck\"\nprogram: $ID* EOF\nKEY: \"key\"\n"),
+            ("common.heck", COMMON),
+        ]);
+        let raw_rules = load_grammar("main.heck", &mut resolver).unwrap();
+        assert_eq!(errors(&raw_rules), vec!["main.heck:3:1: Unused token: <KEY>"]);
+    }
+    
+    #[test]
+    fn own_rules_override_the_imported_ones() {
+        let mut resolver = files(&[
+            ("main.heck", "import \"common.heck\"\nprogram: $NUM* EOF\nNUM: r#\"[0-9_]+\"#\n"),
+            ("common.heck", COMMON),
+        ]);
+        let raw_rules = load_grammar("main.heck", &mut resolver).unwrap();
+        let nums = raw_rules.iter().filter(|&&(ref name, _)| name == "NUM").collect::<Vec<_>>();
+        assert_eq!(nums.len(), 1);
+        assert_eq!(nums[0].1.span.file, 0);
+        assert_eq!(nums[0].1.span.line, 3);
+        assert!(errors(&raw_rules).is_empty());
+    }
+    
+    #[test]
+    fn import_cycles_are_reported() {
+        let mut resolver = files(&[
+            ("main.heck", "import \"a.heck\"\nprogram: $A EOF\n"),
+            ("a.heck", "import \"b.heck\"\nA: \"a\"\n"),
+            ("b.heck", "\nimport \"a.heck\"\n"),
+        ]);
+        let err = load_grammar("main.heck", &mut resolver).unwrap_err();
+        assert_eq!(err.to_string(), "b.heck:2:1: 'a.heck' imports itself (through main.heck -> a.heck -> b.heck)");
+    }
+    
+    #[test]
+    fn errors_name_the_file_they_are_found_in() {
+        let mut resolver = files(&[
+            ("main.heck", "import \"common.heck\"\nimport \"missing.heck\"\n"),
+            ("common.heck", COMMON),
+        ]);
+        let err = load_grammar("main.heck", &mut resolver).unwrap_err();
+        assert_eq!(err.to_string(), "main.heck:2:1: Could not read 'missing.heck': no grammar with this name");
+        
+        let mut resolver = files(&[
+            ("main.heck", "import \"common.heck\"\nprogram: $ID EOF\n"),
+            ("common.heck", "ID: r#\"[a-z]+\"#\nlist: ID value\n"),
+        ]);
+        let raw_rules = load_grammar("main.heck", &mut resolver).unwrap();
+        assert_eq!(errors(&raw_rules), vec!["common.heck:2:10: list: Unbound name 'value'"]);
+        
+        let err = load_grammar("nowhere.heck", &mut resolver).unwrap_err();
+        assert_eq!(err.to_string(), "nowhere.heck: Could not read grammar: no grammar with this name");
+    }
+}
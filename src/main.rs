@@ -6,9 +6,8 @@ use argonaut::{ArgDef, parse, ParseError, help_arg, version_arg};
 use std::process;
 use std::io::{self, Read, Write};
 use std::error::Error;
-use heck::{parse_raw_rules, find_lexer_rules, find_parser_rules, lex, parse_with_rules, LexerRules, ParserRules, validate_rules};
-use heck::generate_reducer_signatures;
-use std::path::Path;
+use heck::{load_grammar, FileResolver, RawRules, find_lexer_rules, find_parser_rules, lex, parse_with_recovery, LexerRules, ParserRules, validate_rules};
+use heck::{generate_reducer_signatures, generate_parser, generate_ast};
 use std::fs::File;
 
 fn main() {
@@ -18,7 +17,7 @@ fn main() {
     }
 }
 
-pub fn try_parse(source: &str, lexer_rules: &LexerRules, parser_rules: &ParserRules, verbose: bool) -> Option<i32> {
+pub fn try_parse(source: &str, lexer_rules: &LexerRules, parser_rules: &ParserRules, start_rule: &str, verbose: bool) -> Option<i32> {
     println!("Parsing...");
     let tokens = match lex(source, &lexer_rules) {
         Ok(tokens) => tokens,
@@ -30,47 +29,71 @@ pub fn try_parse(source: &str, lexer_rules: &LexerRules, parser_rules: &ParserRu
     if verbose {
         println!("Parsed tokens:");
         for token in &tokens {
-            println!("  {:?}", token);
+            println!("  {} {:?}", token.name(lexer_rules.symbols()), token);
         }
     }
     
-    let mtc = match parse_with_rules("program", &parser_rules, tokens, source) {
-        Ok(mtc) => mtc,
-        Err(err) => {
-            println!("Could not parse file: {}", err);
-            return Some(3);
+    let (mtc, errors) = parse_with_recovery(start_rule, &parser_rules, tokens.into_iter(), source);
+    if let Some(mtc) = mtc {
+        println!("Found match: {}", mtc.fmt(source));
+    }
+    if ! errors.is_empty() {
+        println!("Could not parse file:");
+        for err in &errors {
+            println!("  {}", err);
         }
-    };
-    println!("Found match: {}", mtc.fmt(source));
+        return Some(3);
+    }
     None
 }
 
 const INVALID_GRAMMAR: i32 = 4;
 
-pub fn run_prompt(grammar: &str, verbose: bool) -> Option<i32> {
-    let raw_rules = match parse_raw_rules(grammar) {
-        Ok(rules) => rules,
-        Err(err) => {
-            println!("Could not parse grammar: {}", err);
-            return Some(1);
+/// Returns the rule to parse with: the given one, or else the first start 
+/// rule of the grammar.
+fn find_start_rule(parser_rules: &ParserRules, rule: Option<String>) -> Result<String, String> {
+    match rule {
+        Some(rule) => {
+            if parser_rules.get(&rule).is_some() {
+                Ok(rule)
+            } else {
+                Err(format!("The grammar has no rule named '{}'", rule))
+            }
         }
-    };
-    let lexer_rules = find_lexer_rules(&raw_rules);
-    let parser_rules = find_parser_rules(&raw_rules);
-    let grammar_errors = validate_rules(&raw_rules, &lexer_rules, &parser_rules);
+        None => {
+            match parser_rules.start_rules().first() {
+                Some(rule) => Ok(rule.to_string()),
+                None => Err("The grammar has no start rule; declare one with '@start' or use '--rule'".to_string()),
+            }
+        }
+    }
+}
+
+pub fn run_prompt(raw_rules: &RawRules, start_rule: Option<String>, verbose: bool) -> Option<i32> {
+    let lexer_rules = find_lexer_rules(raw_rules);
+    let parser_rules = find_parser_rules(raw_rules);
+    let grammar_errors = validate_rules(raw_rules, &lexer_rules, &parser_rules);
     if ! grammar_errors.is_empty() {
         println!("The grammar has the following errors:");
         for (i, lint) in grammar_errors.iter().enumerate() {
             println!("{})", i+1);
-            println!("{}", lint.message);
+            println!("{}", lint);
             println!("");
         }
         return Some(INVALID_GRAMMAR);
     }
+    let mut start_rule = match find_start_rule(&parser_rules, start_rule) {
+        Ok(rule) => rule,
+        Err(err) => {
+            println!("{}", err);
+            return Some(1);
+        }
+    };
 
     println!("Welcome to the heck prompt. 
 Type text in the current grammar to let heck try to parse it.
-Type 'quit' to quit.");
+Type ':rule <name>' to parse with another rule (currently '{}').
+Type 'quit' to quit.", start_rule);
 
     let mut input = String::new();
     let mut prompt = "> ";
@@ -85,7 +108,22 @@ Type 'quit' to quit.");
         if input.trim() == "quit" {
             break;
         }
-        match try_parse(&input, &lexer_rules, &parser_rules, verbose) {
+        if input.trim().starts_with(":rule ") {
+            let rule = input.trim()[":rule ".len()..].trim().to_string();
+            match find_start_rule(&parser_rules, Some(rule)) {
+                Ok(rule) => {
+                    println!("Parsing with '{}'", rule);
+                    start_rule = rule;
+                    prompt = "> ";
+                }
+                Err(err) => {
+                    println!("{}", err);
+                    prompt = "! ";
+                }
+            }
+            continue;
+        }
+        match try_parse(&input, &lexer_rules, &parser_rules, &start_rule, verbose) {
             Some(_errno) => prompt = "! ",
             None => prompt = "> ",
         }
@@ -99,8 +137,11 @@ fn argonaut_main() -> Option<i32> {
     // Set variables
     let mut grammar_file = String::new();
     let mut source_file: Option<String> = None;
+    let mut start_rule: Option<String> = None;
     let mut do_validate = false;
     let mut do_generate_signatures = false;
+    let mut do_generate_parser = false;
+    let mut do_generate_ast = false;
     let mut verbose = false;
 
     let description = "
@@ -116,6 +157,10 @@ fn argonaut_main() -> Option<i32> {
             .short("i")
             .help("An optional source file to try to parse")
 
+        , ArgDef::setting("rule", &mut start_rule)
+            .short("r")
+            .help("The rule to parse with. Defaults to the start rule of the grammar ('@start').")
+
         , ArgDef::flag("validate", &mut do_validate)
             .short("v")
             .help("Validates the grammar, without starting the REPL")
@@ -124,6 +169,14 @@ fn argonaut_main() -> Option<i32> {
             .short("g")
             .help("Generates signatures for reducer functions for the productions (rules) in this grammar.")
         
+        , ArgDef::flag("generate-parser", &mut do_generate_parser)
+            .short("p")
+            .help("Generates a Rust module with a compiled lexer and parser for this grammar.")
+        
+        , ArgDef::flag("generate-ast", &mut do_generate_ast)
+            .short("a")
+            .help("Generates a Rust module with typed syntax trees for the rules in this grammar.")
+        
         , ArgDef::flag("verbose", &mut verbose)
             .short("d")
             .help("Prints the tokens when lexing.")
@@ -141,48 +194,37 @@ fn argonaut_main() -> Option<i32> {
     };
     
     // Use the parsed arguments after a succesful parse
-    let p = Path::new(&grammar_file);
     if ! grammar_file.ends_with(".heck") {
         println!("The grammar file should end with '.heck'! ('{}')", grammar_file);
         return Some(1);
     }
-    let mut file = match File::open(&p) {
-        Ok(file) => file,
+    // Read the grammar along with the files it imports.
+    let raw_rules = match load_grammar(&grammar_file, &mut FileResolver) {
+        Ok(rules) => rules,
         Err(err) => {
-            println!("Could not open grammar file: {}", err.description());
+            println!("Could not parse grammar: {}", err);
             return Some(1);
         }
     };
-    
-    let mut grammar = String::new();
-    if let Err(err) = file.read_to_string(&mut grammar) {
-        println!("Could not read grammar file: {}", err.description());
-        return Some(1);
-    }
 
     let read_grammar_now = 
           do_validate 
         || do_generate_signatures 
+        || do_generate_parser
+        || do_generate_ast
         || source_file.is_some();
     
     if ! read_grammar_now {
-        run_prompt(&grammar, verbose);
+        run_prompt(&raw_rules, start_rule, verbose);
         None
     } else {
-        let raw_rules = match parse_raw_rules(&grammar) {
-            Ok(rules) => rules,
-            Err(err) => {
-                println!("Could not parse grammar: {}", err);
-                return Some(1);
-            }
-        };
         let lexer_rules = find_lexer_rules(&raw_rules);
         let parser_rules = find_parser_rules(&raw_rules);
         let grammar_errors = validate_rules(&raw_rules, &lexer_rules, &parser_rules);
         if ! grammar_errors.is_empty() {
             println!("The grammar has the following errors:");
             for (i, lint) in grammar_errors.iter().enumerate() {
-                println!("  {}: {}", i+1, lint.message);
+                println!("  {}: {}", i+1, lint);
             }
             return Some(INVALID_GRAMMAR);
         }
@@ -200,6 +242,16 @@ fn argonaut_main() -> Option<i32> {
             return None;
         }
 
+        if do_generate_parser {
+            print!("{}", generate_parser(&parser_rules, &lexer_rules));
+            return None;
+        }
+
+        if do_generate_ast {
+            print!("{}", generate_ast(&parser_rules, "heck"));
+            return None;
+        }
+
         if let Some(source_file) = source_file {
             let mut sf = match File::open(&source_file) {
                 Ok(sf) => sf,
@@ -214,7 +266,14 @@ fn argonaut_main() -> Option<i32> {
                 return Some(1);
             }
             
-            try_parse(&source, &lexer_rules, &parser_rules, verbose)
+            let start_rule = match find_start_rule(&parser_rules, start_rule) {
+                Ok(rule) => rule,
+                Err(err) => {
+                    println!("{}", err);
+                    return Some(1);
+                }
+            };
+            try_parse(&source, &lexer_rules, &parser_rules, &start_rule, verbose)
         } else {
             unreachable!();
         }
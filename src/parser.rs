@@ -2,12 +2,16 @@
 use common::*;
 use std::rc::Rc;
 use std::collections::HashMap;
+use error::ParseError;
 use grammar::{Pat, CaptureInfo, GrammarToken, RawRules};
-use lexer::{self, Token};
+use lexer::{self, Token, TokenSource, IntoTokenSource};
+use symbols::{Symbols, TokenId};
 use std::iter::{self, Peekable};
-use std::vec;
+use std::slice;
 use std::ops::{Deref};
 use captures::{CaptureType, find_and_assign_captures};
+use analysis::{PatSets, TokenSet, find_pat_sets, find_unused_rules, child_nodes, children};
+use expand::{find_templates, expand_calls};
 
 /// A named parsing pattern, with a described set of captured matches or tokens.
 #[derive(Debug, Clone)]
@@ -16,56 +20,190 @@ pub struct ParserRule {
     pub(crate) pat: Pat,
     // Vec<(group_name?, captype)>
     pub(crate) captures: Vec<(Option<String>, CaptureType)>,
+    /// Where the rule is defined in the grammar.
+    pub(crate) span: Span,
+    /// Where each node of the pattern is defined in the grammar, in preorder.
+    pub(crate) spans: Vec<Span>,
+    /// The binary operators that combine matches of this rule, declared with
+    /// '@left' or '@right'. The pattern of the rule describes the operands.
+    pub(crate) operators: Vec<Operator>,
+    /// The nodes of the operands of the prefix operations of this rule, ie:
+    /// '$expr' in '"-" $expr', which bind tighter than any binary operator.
+    pub(crate) prefix_operands: Vec<usize>,
+    /// The tokens that decide how each node of the pattern is parsed, in 
+    /// preorder.
+    pub(crate) sets: Vec<PatSets>,
+    /// The lines of the comments right before the rule in the grammar.
+    pub(crate) doc: Vec<String>,
+}
+impl ParserRule {
+    /// Returns the operator of this rule that is read with the given token.
+    fn operator(&self, token: TokenId) -> Option<&Operator> {
+        self.operators.iter().find(|op| op.token == token)
+    }
+    
+    /// Returns the sets of every node of the pattern of this rule, in 
+    /// preorder, starting with the sets of the whole pattern.
+    pub fn sets(&self) -> &[PatSets] {
+        &self.sets
+    }
+    
+    /// Returns the tokens that this rule can start with (FIRST).
+    pub fn first(&self) -> &TokenSet {
+        self.sets[0].first()
+    }
+    
+    /// Returns the tokens that can be read right after this rule (FOLLOW).
+    pub fn follow(&self) -> &TokenSet {
+        self.sets[0].follow()
+    }
+    
+    /// Returns whether this rule can be parsed without reading a token.
+    pub fn is_nullable(&self) -> bool {
+        self.sets[0].is_nullable()
+    }
+    
+    /// Returns the lines of the doc comment of this rule.
+    pub fn doc(&self) -> &[String] {
+        &self.doc
+    }
+}
+
+/// How operators with the same precedence are grouped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assoc {
+    /// 'a - b - c' is parsed as '(a - b) - c'.
+    Left,
+    /// 'a ^ b ^ c' is parsed as 'a ^ (b ^ c)'.
+    Right,
+}
+
+/// A binary operator of a rule, ie: '@left expr "+" "-"'.
+#[derive(Debug, Clone)]
+pub struct Operator {
+    /// The token of the operator.
+    pub(crate) token: TokenId,
+    /// How tightly the operator binds. Higher binds tighter.
+    pub(crate) precedence: usize,
+    /// How the operator groups with operators of the same precedence.
+    pub(crate) assoc: Assoc,
+    /// Where the operator is declared in the grammar.
+    pub(crate) span: Span,
 }
 
 /// Rules that tells the parsing function how to combine tokens into structure.
-pub type ParserRules = HashMap<String, ParserRule>;
+#[derive(Debug, Clone)]
+pub struct ParserRules {
+    /// The parser rules, indexed by their rule ids.
+    pub(crate) rules: Vec<ParserRule>,
+    /// The tokens that the parser skips to when recovering from an error,
+    /// declared with '@sync', along with where they were declared.
+    pub(crate) sync_tokens: Vec<(TokenId, Span)>,
+    /// The ids of the tokens and rules of the grammar.
+    pub(crate) symbols: Symbols,
+    /// The rules that texts are parsed with.
+    pub(crate) start_rules: Vec<Rc<String>>,
+}
+impl ParserRules {
+    /// Returns the rule with the given name.
+    pub fn get(&self, name: &str) -> Option<&ParserRule> {
+        self.symbols.rule_id(name).map(|id| &self.rules[id.index()])
+    }
+    
+    /// Iterates over the rules and their names, in definition order.
+    pub fn iter<'a>(&'a self) -> Iter<'a> {
+        Iter { rules: self.rules.iter() }
+    }
+    
+    /// Returns the table of token and rule names that the rules refer to.
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+    
+    /// Returns the names of the rules that texts are parsed with, declared 
+    /// with '@start'. If none are declared, these are the rules that no other
+    /// rule uses.
+    pub fn start_rules(&self) -> &[Rc<String>] {
+        &self.start_rules
+    }
+}
+impl<'a> IntoIterator for &'a ParserRules {
+    type Item = (&'a String, &'a ParserRule);
+    type IntoIter = Iter<'a>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over parser rules and their names.
+pub struct Iter<'a> {
+    rules: slice::Iter<'a, ParserRule>,
+}
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a String, &'a ParserRule);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rules.next().map(|rule| (&*rule.name, rule))
+    }
+}
 
 /// Changes all UPPERCASE rules to named tokens, and all literal tokens to
 /// named tokens as well.
-fn assign_token_names(pat: Pat) -> Pat {
+fn assign_token_names(pat: Pat, symbols: &Symbols) -> Pat {
     use grammar::Pat::*;
+    let named = |name: &str| {
+        let id = symbols.token_id(name).expect("Token missing from the symbol table");
+        GrammarToken::Named(id, symbols.token_name(id).clone())
+    };
     match pat {
         Rule(name) => {
             if is_token_id(&name) {
-                Token(GrammarToken::Named(Rc::new(name)))
+                Token(named(&name))
             } else {
                 Rule(name)
             }
         }
           Token(GrammarToken::Str(s)) 
         | Token(GrammarToken::Re(s)) => {
-            Token(GrammarToken::Named(Rc::new(s)))
+            Token(named(&s))
         }
-        Token(GrammarToken::Named(_)) => {
+        Token(GrammarToken::Named(..)) => {
             pat // is it an err to be silent? I mean, the result is correct :p
         }
         AnyOf(pats) => {
-            AnyOf(pats.into_iter().map(assign_token_names).collect())
+            AnyOf(pats.into_iter().map(|pat| assign_token_names(pat, symbols)).collect())
         }
         Seq(pats) => {
-            Seq(pats.into_iter().map(assign_token_names).collect())
+            Seq(pats.into_iter().map(|pat| assign_token_names(pat, symbols)).collect())
         }
-        Opt(ipat) => Opt(Box::new(assign_token_names(*ipat))),
-        Cap(cap, ipat) => Cap(cap, Box::new(assign_token_names(*ipat))),
-        ZeroPlus(ipat) => ZeroPlus(Box::new(assign_token_names(*ipat))),
-        OnePlus(ipat) => OnePlus(Box::new(assign_token_names(*ipat))),
-        Loop(ipat) => Loop(Box::new(assign_token_names(*ipat))),
+        Opt(ipat) => Opt(Box::new(assign_token_names(*ipat, symbols))),
+        Cap(cap, ipat) => Cap(cap, Box::new(assign_token_names(*ipat, symbols))),
+        ZeroPlus(ipat) => ZeroPlus(Box::new(assign_token_names(*ipat, symbols))),
+        OnePlus(ipat) => OnePlus(Box::new(assign_token_names(*ipat, symbols))),
+        Loop(ipat) => Loop(Box::new(assign_token_names(*ipat, symbols))),
+        SepBy(item, sep, flags) => SepBy(
+            Box::new(assign_token_names(*item, symbols)),
+            Box::new(assign_token_names(*sep, symbols)),
+            flags,
+        ),
           BreakOnToken(GrammarToken::Str(s)) 
         | BreakOnToken(GrammarToken::Re(s)) => {
-            BreakOnToken(GrammarToken::Named(Rc::new(s)))
+            BreakOnToken(named(&s))
         }
-        BreakOnToken(GrammarToken::Named(_)) => {
+        BreakOnToken(GrammarToken::Named(..)) => {
             pat
         }
+        Call(..) => unreachable!(),
     }
 }
 
 /// Finds and parses the parser rules in the given set of raw rules.
 pub fn find_parser_rules(rules: &RawRules) -> ParserRules {
-    let mut parser_rules = HashMap::new();
+    let symbols = Symbols::new(rules);
+    let templates = find_templates(rules);
+    let mut parser_rules: Vec<ParserRule> = Vec::new();
     for (name, rule) in rules.iter().filter_map(|&(ref k, ref v)| {
-        if ! is_token_id(k) {
+        // Parameterized rules only exist in the rules that use them.
+        if ! is_token_id(k) && v.params.is_empty() {
             Some((k.clone(), v.clone()))
         } else {
             None
@@ -73,7 +211,8 @@ pub fn find_parser_rules(rules: &RawRules) -> ParserRules {
     }) {
         //println!("Assigning rule {:?}...", name);
         //println!("  {}", rule.pat.fmt());
-        let (captures, pat_with_captures) = find_and_assign_captures(rule.pat);
+        let (pat, spans) = expand_calls(&rule.pat, &rule.spans, &templates);
+        let (captures, pat_with_captures) = find_and_assign_captures(pat);
         let mut caps = Vec::new();
         // Add the names, but don't validate yet.
         for (i, cap) in captures.into_iter().enumerate() {
@@ -81,19 +220,123 @@ pub fn find_parser_rules(rules: &RawRules) -> ParserRules {
             caps.push((rule.capture_names.get(i).map(|s| s.clone()), cap));
         }
         // Clean up the pat by changing tokens to named tokens, and
-        let pat_with_tokens = assign_token_names(pat_with_captures);
+        let pat_with_tokens = assign_token_names(pat_with_captures, &symbols);
         // TOKEN rules to named tokens as well.
+        let id = symbols.rule_id(&name).expect("Rule missing from the symbol table");
         let rule = ParserRule {
-            name: Rc::new(name.clone()),
+            name: symbols.rule_name(id).clone(),
             pat: pat_with_tokens,
             captures: caps,
+            span: rule.span,
+            spans,
+            operators: Vec::new(),
+            prefix_operands: Vec::new(),
+            sets: Vec::new(),
+            doc: rule.doc,
         };
-        parser_rules.insert(name, rule);
+        // Rules are numbered in definition order, so a rule that is defined
+        // more than once replaces its earlier definition, as reported by the
+        // validator.
+        if id.index() < parser_rules.len() {
+            parser_rules[id.index()] = rule;
+        } else {
+            parser_rules.push(rule);
+        }
         //println!("");
     }
+    let mut sync_tokens = Vec::new();
+    for directive in rules.directives_named("sync") {
+        for arg in &directive.args {
+            // Rules are reported by the validator, and left out here.
+            if let Pat::Token(GrammarToken::Named(id, _)) = assign_token_names(arg.clone(), &symbols) {
+                sync_tokens.push((id, directive.span));
+            }
+        }
+    }
+    // Operators declared later bind tighter than those declared before them.
+    let operator_directives = rules.directives.iter().filter(|directive| {
+        directive.name == "left" || directive.name == "right"
+    });
+    for (precedence, directive) in operator_directives.enumerate() {
+        let assoc = if directive.name == "left" { Assoc::Left } else { Assoc::Right };
+        // Malformed directives are reported by the validator, and left out here.
+        let rule = match directive.args.first() {
+            Some(&Pat::Rule(ref name)) => {
+                symbols.rule_id(name).map(|id| &mut parser_rules[id.index()])
+            }
+            _ => None,
+        };
+        if let Some(rule) = rule {
+            for arg in &directive.args[1..] {
+                if let Pat::Token(GrammarToken::Named(token, _)) = assign_token_names(arg.clone(), &symbols) {
+                    rule.operators.push(Operator { 
+                        token, precedence, assoc, span: directive.span 
+                    });
+                }
+            }
+        }
+    }
+    let mut start_rules = Vec::new();
+    for directive in rules.directives_named("start") {
+        for arg in &directive.args {
+            // Tokens and unbound names are reported by the validator.
+            if let Pat::Rule(ref name) = *arg {
+                if let Some(id) = symbols.rule_id(name) {
+                    start_rules.push(symbols.rule_name(id).clone());
+                }
+            }
+        }
+    }
+    let mut parser_rules = ParserRules { rules: parser_rules, sync_tokens, symbols, start_rules };
+    if parser_rules.start_rules.is_empty() {
+        parser_rules.start_rules = find_unused_rules(&parser_rules);
+    }
+    let sets = find_pat_sets(&parser_rules);
+    for (rule, sets) in parser_rules.rules.iter_mut().zip(sets) {
+        rule.sets = sets;
+        if ! rule.operators.is_empty() {
+            rule.prefix_operands = find_prefix_operands(rule);
+        }
+    }
     parser_rules
 }
 
+/// Finds the nodes of the operands of the prefix operations of the rule. A 
+/// prefix operation is an alternative of the rule that is a sequence of 
+/// tokens followed by the rule itself, ie: '"-" $expr'.
+fn find_prefix_operands(rule: &ParserRule) -> Vec<usize> {
+    let alternatives = match rule.pat {
+        Pat::AnyOf(ref pats) => pats.iter().zip(child_nodes(&rule.sets, 0, pats.len())).collect(),
+        ref pat => vec![(pat, 0)],
+    };
+    let mut operands = Vec::new();
+    for (alternative, node) in alternatives {
+        let pats = match *alternative {
+            Pat::Seq(ref pats) if pats.len() > 1 => pats,
+            _ => continue,
+        };
+        let is_prefix = pats[..pats.len() - 1].iter().all(|pat| match *pat {
+            Pat::Token(_) => true,
+            _ => false,
+        });
+        if ! is_prefix {
+            continue;
+        }
+        let mut last = child_nodes(&rule.sets, node, pats.len())[pats.len() - 1];
+        let mut operand = &pats[pats.len() - 1];
+        if let Pat::Cap(CaptureInfo::Assigned(_), ref inner) = *operand {
+            operand = inner;
+            last += 1;
+        }
+        if let Pat::Rule(ref name) = *operand {
+            if *name == *rule.name {
+                operands.push(last);
+            }
+        }
+    }
+    operands
+}
+
 /// Describes the value of a Rule or Token matched and captured by a '$' capture pattern.
 #[derive(Debug, Clone)]
 pub enum Capture {
@@ -137,12 +380,36 @@ pub struct Match {
     pub rule: Rc<String>,
     /// The values that were captured.
     pub captures: Vec<Capture>,
+    /// The starting byte index of this match in the source text.
+    pub start: usize,
+    /// The ending (excl) byte index of this match in the source text.
+    pub end: usize,
+    /// Whether the rule failed to parse, and the parser recovered by skipping
+    /// to a synchronization token. The captures of an error match are only
+    /// partially assigned.
+    pub is_error: bool,
+    /// The operator token, if this is a binary operation of a rule with 
+    /// operators ('@left' or '@right'). The operands are then captured as
+    /// the single values 0 and 1, instead of the captures of the rule.
+    pub operator: Option<Token>,
+    /// The skipped tokens before this match that belong to it, ie: comments
+    /// on the lines above it. Only 'parse_lossless' finds the trivia.
+    pub leading_trivia: Vec<Token>,
+    /// The skipped tokens after this match that belong to it, ie: a comment
+    /// at the end of its last line.
+    pub trailing_trivia: Vec<Token>,
 }
 impl Match {
     /// Creates a match with empty captures from a rule.
     pub fn new(rule: &ParserRule) -> Match {
         Match {
             rule: rule.name.clone(),
+            start: 0,
+            end: 0,
+            is_error: false,
+            operator: None,
+            leading_trivia: Vec::new(),
+            trailing_trivia: Vec::new(),
             captures: rule.captures.iter().map(|&(_, ref ct)| {
                 use captures::CaptureType::*;
                 match *ct {
@@ -152,6 +419,12 @@ impl Match {
                         Capture::Single(Box::new(Match {
                             rule: Rc::new("".to_string()),
                             captures: Vec::new(),
+                            start: 0,
+                            end: 0,
+                            is_error: false,
+                            operator: None,
+                            leading_trivia: Vec::new(),
+                            trailing_trivia: Vec::new(),
                         }))
                     }
                     Optional => {
@@ -218,6 +491,29 @@ impl Match {
         self.single(index).and_then(|m| m.token())
     }
     
+    /// Returns the slice of the source text that this match spans.
+    pub fn slice<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.start..self.end]
+    }
+    
+    /// Returns the byte range of this match in the source text, including 
+    /// its trivia.
+    pub fn full_span(&self) -> (usize, usize) {
+        let start = self.leading_trivia.first().map_or(self.start, |token| token.start.min(self.start));
+        let end = self.trailing_trivia.last().map_or(self.end, |token| token.end.max(self.end));
+        (start, end)
+    }
+    
+    /// Returns the 1-indexed line/column pair where this match starts.
+    pub fn start_position(&self, source: &str) -> (usize, usize) {
+        get_position(source, self.start)
+    }
+    
+    /// Returns the 1-indexed line/column pair where this match ends.
+    pub fn end_position(&self, source: &str) -> (usize, usize) {
+        get_position(source, self.end)
+    }
+    
     /// Formats the match for pretty-printing.
     pub fn fmt(&self, source: &str) -> String {
         let mut s = String::new();
@@ -236,6 +532,11 @@ impl Match {
         fn is_token(mtc: &Match) -> bool {
             mtc.rule.chars().all(|ch| ch.is_uppercase())
         }
+        if self.rule.is_empty() {
+            // The dummy value of a capture that an error stopped from being assigned.
+            s.push_str("Missing");
+            return;
+        }
         if is_token(self) {
             s.push_str("Token<");
             s.push_str(&self.rule);
@@ -245,10 +546,14 @@ impl Match {
             return;
         }
         
-        s.push_str("Match<");
+        s.push_str(if self.is_error { "Error<" } else { "Match<" });
         s.push_str(&self.rule);
         s.push_str(">{\n");
         indent += 2;
+        if let Some(ref token) = self.operator {
+            pad(s, indent);
+            s.push_str(&format!("op {:?}\n", token.slice(source)));
+        }
         for (i, cap) in self.captures.iter().enumerate() {
             match *cap {
                 Single(ref mtc) => {
@@ -304,317 +609,855 @@ impl Match {
 struct ErrContext<'a> {
     scope: Vec<Rc<String>>,
     source_text: &'a str,
+    /// The end of the last consumed token, used to find the span of matches.
+    last_end: usize,
+    /// Optional patterns that were skipped since the last consumed token, 
+    /// whose tokens would also have been accepted at the current position.
+    skipped: Vec<&'a Pat>,
+    /// Whether to recover from errors by skipping to a synchronization token.
+    recover: bool,
+    /// The errors that were recovered from.
+    errors: Vec<ParseError>,
+    /// The number of tokens consumed, used to check that the parse advances.
+    consumed: usize,
+    /// The value of 'consumed' when the parser last recovered from an error.
+    recovered_at: Option<usize>,
 }
+impl<'a> ErrContext<'a> {
+    fn new(source_text: &'a str, recover: bool) -> ErrContext<'a> {
+        ErrContext {
+            scope: Vec::new(),
+            source_text,
+            last_end: 0,
+            skipped: Vec::new(),
+            recover,
+            errors: Vec::new(),
+            consumed: 0,
+            recovered_at: None,
+        }
+    }
+    
+    /// Records that the given token was consumed by the parser.
+    fn consume(&mut self, token: &Token) {
+        self.last_end = token.end;
+        self.consumed += 1;
+        self.skipped.clear();
+    }
+    
+    /// Returns whether a recovered error stopped the parse from advancing
+    /// since the given number of tokens were consumed, meaning that a
+    /// repetition must stop to avoid looping forever.
+    fn stalled_since(&self, consumed: usize) -> bool {
+        self.recover && self.consumed == consumed
+    }
+    
+    /// Records the error and skips tokens until a synchronization token or
+    /// EOF is found, so that the parse can continue. The synchronization 
+    /// token is skipped as well if it can't follow the failed rule, since 
+    /// the rules around it couldn't parse it either.
+    fn recover(&mut self, err: ParseError, follow: &TokenSet, rules: &ParserRules, tokens: &mut Tokens) {
+        // An error found before any tokens were read since the last recovery 
+        // is most likely caused by the previous error, so don't report it.
+        if self.recovered_at != Some(self.consumed) {
+            self.errors.push(err);
+        }
+        loop {
+            let is_sync = match tokens.peek() {
+                Some(peek) => {
+                    peek.id == TokenId::EOF 
+                    || rules.sync_tokens.iter().any(|&(id, _)| id == peek.id)
+                }
+                None => true,
+            };
+            if is_sync {
+                break;
+            }
+            let token = tokens.next().unwrap();
+            self.consume(&token);
+        }
+        let skip_sync = tokens.peek().map_or(false, |peek| {
+            peek.id != TokenId::EOF && ! follow.contains(peek.id)
+        });
+        if skip_sync {
+            let token = tokens.next().unwrap();
+            self.consume(&token);
+        }
+        self.recovered_at = Some(self.consumed);
+    }
+    
 
-/// A peekable token iterator.
-pub type Tokens = Peekable<vec::IntoIter<Token>>;
-
-/// The result of a parse.
-pub type ParseResult<T> = Result<T, String>;
-
-/// Signals that the current loop or repetition should be broken out of.
-#[derive(Debug)]
-struct Break;
-
-/// How a pattern would match a given token.
-#[derive(Debug)]
-enum ParseAction {
-    /// The pattern matches and consumes the pattern in one of its branches.
-    MatchesToken,
-    /// The pattern doesn't match, but is optional, so the parse can continue.
-    IgnoresToken,
-    /// All branches of the pattern expect a different token, so the parse cannot continue.
-    CannotParse,
+    /// Creates an 'unexpected token' error, expecting the tokens that can 
+    /// start the given pattern, or any of the patterns skipped before it.
+    fn unexpected(&self, found: Token, pat: &'a Pat, rules: &'a ParserRules) -> ParseError {
+        let (line, col) = get_position(self.source_text, found.start);
+        let found_name = rules.symbols.token_name(found.id).clone();
+        ParseError::UnexpectedToken { 
+            found, found_name, line, col, 
+            scope: self.scope.clone(), 
+            expected: self.expected(pat, rules),
+        }
+    }
+    
+    /// Returns the tokens that the given pattern, or any of the patterns
+    /// skipped before it, would accept.
+    fn expected(&self, pat: &'a Pat, rules: &'a ParserRules) -> Vec<Rc<String>> {
+        let mut expected = Vec::new();
+        let mut visited = HashMap::new();
+        for skipped in &self.skipped {
+            find_expected_tokens(skipped, rules, &mut expected, &mut visited);
+        }
+        find_expected_tokens(pat, rules, &mut expected, &mut visited);
+        expected
+    }
 }
 
-/// Returns how the pattern would match a given token.
-fn action_when_parsed<'a>(pat: &'a Pat, token: &Token, rules: &'a ParserRules, 
-    indent: usize) -> ParseAction 
+/// Adds the names of the tokens that the given pattern can start with to 
+/// 'expected', and returns whether the pattern can be passed without reading
+/// any tokens.
+pub(crate) fn find_expected_tokens<'a>(pat: &'a Pat, rules: &'a ParserRules, 
+    expected: &mut Vec<Rc<String>>, visited: &mut HashMap<&'a str, Option<bool>>) -> bool 
 {
     use grammar::Pat::*;
-    use self::ParseAction::*;
-    #[cfg(feature="debug")]
-    macro_rules! prindent {
-        ($($e:expr),*) => {{
-            let pad = iter::repeat(" ").take(indent).collect::<String>();
-            println!("{}{}", pad, format!($($e),*));
-        }}
-    }
-    #[cfg(not(feature="debug"))]
-    macro_rules! prindent {
-        ($($e:expr),*) => {}
-    }
-
-    prindent!("<{}> => {})", token.name, pat.fmt());
     match *pat {
-        Token(GrammarToken::Named(ref name)) => {
-            let res = if &token.name == name {
-                MatchesToken
-            } else {
-                CannotParse
-            };
-            prindent!("-> {:?}", res);
-            res
-        }
-        Token(_) => { 
-            panic!("Attempted parse without assigning token names"); 
+        Token(GrammarToken::Named(_, ref name)) => {
+            if ! expected.contains(name) {
+                expected.push(name.clone());
+            }
+            false
         }
-        BreakOnToken(GrammarToken::Named(ref name)) => {
-            let res = if &token.name == name {
-                MatchesToken
-            } else {
-                IgnoresToken
-            };
-            prindent!("-> {:?}", res);
-            res
+        BreakOnToken(GrammarToken::Named(_, ref name)) => {
+            if ! expected.contains(name) {
+                expected.push(name.clone());
+            }
+            true
         }
-        BreakOnToken(_) => {
-            panic!("Attempted parse without assigning token names");
+        Token(_) | BreakOnToken(_) => { 
+            panic!("Attempted parse without assigning token names"); 
         }
         Rule(ref name) => {
-            let pat = &rules.get(name).expect("Rule not found!").pat;
-            let res = action_when_parsed(pat, token, rules, indent + 2);
-            prindent!("-> {:?}", res);
-            res
+            // Rules that are currently being searched count as not optional.
+            if let Some(&skippable) = visited.get(name.as_str()) {
+                return skippable.unwrap_or(false);
+            }
+            visited.insert(name, None);
+            let skippable = match rules.get(name) {
+                Some(rule) => find_expected_tokens(&rule.pat, rules, expected, visited),
+                None => false,
+            };
+            visited.insert(name, Some(skippable));
+            skippable
         }
         Seq(ref pats) => {
             for pat in pats {
-                match action_when_parsed(pat, token, rules, indent+2) {
-                    MatchesToken => {
-                        prindent!("-> MatchesToken");
-                        return MatchesToken;
-                    }
-                    IgnoresToken => {}
-                    CannotParse => {
-                        prindent!("-> CannotParse");
-                        return CannotParse;
-                    }
+                if ! find_expected_tokens(pat, rules, expected, visited) {
+                    return false;
                 }
             }
-            prindent!("-> IsOptional");
-            IgnoresToken
+            true
         }
         AnyOf(ref pats) => {
-            let mut opt_found = false;
+            let mut skippable = false;
             for pat in pats {
-                match action_when_parsed(pat, token, rules, indent+2) {
-                    MatchesToken => {
-                        prindent!("-> MatchesToken");
-                        return MatchesToken;
-                    }
-                    // This is the correct semantics, ignoring a branch that 
-                    // matches the token, if an optional branch is found first
-                    IgnoresToken => opt_found = true,
-                    CannotParse => {}
+                if find_expected_tokens(pat, rules, expected, visited) {
+                    skippable = true;
                 }
             }
-            let res = if opt_found {
-                IgnoresToken
-            } else {
-                CannotParse
-            };
-            prindent!("-> {:?}", res);
-            res
+            skippable
         }
-        Opt(ref optpat) | ZeroPlus(ref optpat) => {
-            let res = if let MatchesToken = action_when_parsed(optpat, token, rules, indent+2) {
-                MatchesToken
-            } else {
-                IgnoresToken
-            };
-            prindent!("-> {:?}", res);
-            res
+        Opt(ref pat) | ZeroPlus(ref pat) => {
+            find_expected_tokens(pat, rules, expected, visited);
+            true
         }
-        OnePlus(ref ipat) | Cap(_, ref ipat) | Loop(ref ipat) => {
-            let res = action_when_parsed(ipat, token, rules, indent + 2);
-            prindent!("-> {:?}", res);
-            res
+        OnePlus(ref pat) | Cap(_, ref pat) | Loop(ref pat) => {
+            find_expected_tokens(pat, rules, expected, visited)
         }
+        SepBy(ref item, _, flags) => {
+            find_expected_tokens(item, rules, expected, visited) || flags.empty
+        }
+        Call(..) => unreachable!(),
+    }
+}
+
+/// A peekable token iterator.
+pub type Tokens<'t> = Peekable<&'t mut dyn Iterator<Item=Token>>;
+
+/// Reads the tokens of a token source with the ids of the grammar, and 
+/// ends them with an EOF token. If the source fails, the tokens end early, 
+/// and the error is kept to be returned instead of the parse result.
+struct SourceTokens<'s, S: TokenSource> {
+    source: S,
+    symbols: &'s Symbols,
+    source_text: &'s str,
+    eof: Option<Token>,
+    error: Option<ParseError>,
+}
+impl<'s, S: TokenSource> SourceTokens<'s, S> {
+    fn new(source: S, symbols: &'s Symbols, source_text: &'s str) -> SourceTokens<'s, S> {
+        let eof = Token::new(TokenId::EOF, source_text.len(), source_text.len());
+        SourceTokens { source, symbols, source_text, eof: Some(eof), error: None }
+    }
+}
+impl<'s, S: TokenSource> Iterator for SourceTokens<'s, S> {
+    type Item = Token;
+    
+    fn next(&mut self) -> Option<Token> {
+        if self.eof.is_none() {
+            return None;
+        }
+        if self.error.is_none() {
+            match self.source.next_token() {
+                Some(Ok((kind, start, end))) => {
+                    if let Some(id) = self.source.token_id(&kind, self.symbols) {
+                        return Some(Token::new(id, start, end));
+                    }
+                    // The grammar has no token that this one could be parsed as.
+                    let (line, col) = get_position(self.source_text, start);
+                    self.error = Some(ParseError::Lex { pos: start, line, col });
+                }
+                Some(Err(err)) => self.error = Some(err),
+                None => {}
+            }
+        }
+        self.eof.take()
+    }
+}
+
+/// The result of a parse.
+pub type ParseResult<T> = Result<T, ParseError>;
+
+/// Signals that the current loop or repetition should be broken out of.
+#[derive(Debug)]
+struct Break;
+
+/// A node of the pattern of a rule, with the sets of every node of the 
+/// pattern.
+#[derive(Clone, Copy)]
+struct Node<'a> {
+    sets: &'a [PatSets],
+    index: usize,
+}
+impl<'a> Node<'a> {
+    /// Returns the node at the given index of the same pattern.
+    fn at(self, index: usize) -> Node<'a> {
+        Node { sets: self.sets, index }
+    }
+    
+    /// Returns the node of the inner pattern of this one, ie: 'A' in 'A?'.
+    fn inner(self) -> Node<'a> {
+        self.at(self.index + 1)
+    }
+    
+    /// Returns the tokens that decide how this node is parsed.
+    fn sets(self) -> &'a PatSets {
+        &self.sets[self.index]
     }
 }
 
 /// Parses the given token using the given pattern, with an optional index of a capture in the capture list to assign parsed matches to.
-fn parse_with_pattern<'a>(mut pat: &Pat, mut cap_idx: Option<usize>, caps: &mut Vec<Capture>, 
-    rules: &ParserRules, tokens: &mut Tokens, ctx: &mut ErrContext<'a>) -> ParseResult<Option<Break>> 
+/// The pattern is at the given node of the pattern of its rule.
+fn parse_with_pattern<'a>(mut pat: &'a Pat, mut node: Node<'a>, mut cap_idx: Option<usize>, 
+    caps: &mut Vec<Capture>, rules: &'a ParserRules, tokens: &mut Tokens, 
+    ctx: &mut ErrContext<'a>) -> ParseResult<Option<Break>> 
 {
     use grammar::Pat::*;
-    use self::ParseAction::*;
     if let &Cap(CaptureInfo::Assigned(idx), ref inner_pat) = pat {
         cap_idx = Some(idx);
         pat = inner_pat;
+        node = node.inner();
     }
     
     #[inline]
-    fn advance(tokens: &mut Tokens) -> ParseResult<lexer::Token> {
+    fn advance<'a>(pat: &'a Pat, rules: &'a ParserRules, tokens: &mut Tokens, 
+        ctx: &mut ErrContext<'a>) -> ParseResult<lexer::Token> 
+    {
         if let Some(token) = tokens.next() {
+            ctx.consume(&token);
             Ok(token)
         } else {
-            Err(format!("Unexpected EOF"))
+            Err(ParseError::UnexpectedEof { 
+                scope: ctx.scope.clone(), 
+                expected: ctx.expected(pat, rules),
+            })
         }
     }
     
-    fn error<'a>(expected: &str, found: lexer::Token, ctx: &ErrContext<'a>) -> ParseResult<Option<Break>> {
-        let (line, col) = get_position(ctx.source_text, found.start);
-        let scope = ctx.scope.iter().flat_map(|s| Some('.').into_iter().chain(s.chars())).skip(1).collect::<String>();
-        Err(format!("{}:{}:{}: Expected {}, found {}", scope, line, col, expected, found.name))
-    }
-    
-    fn can_consume(pat: &Pat, tokens: &mut Tokens, rules: &ParserRules) -> bool {
-        use self::ParseAction::*;
-        if let Some(ref peek) = tokens.peek() {
-            match action_when_parsed(pat, peek, rules, 0) {
-                MatchesToken => true,
-                IgnoresToken | CannotParse => false,
-            }
-        } else {
-            false
+    fn can_consume<'a>(pat: &'a Pat, sets: &PatSets, tokens: &mut Tokens, 
+        ctx: &mut ErrContext<'a>) -> bool 
+    {
+        let consumes = tokens.peek().map_or(false, |peek| sets.first.contains(peek.id));
+        if ! consumes {
+            ctx.skipped.push(pat);
         }
+        consumes
     }
     
     match *pat {
         Rule(ref name) => {
-            let mtc = parse_with_rule(name, rules, tokens, ctx)?;
+            // The operand of a prefix operation of the rule that is being
+            // parsed doesn't take binary operators, so '-1 + 2' is parsed as
+            // '(-1) + 2'.
+            let prefix_operand = match ctx.scope.last() {
+                Some(scope) if **scope == *name => {
+                    rules.get(name).filter(|rule| rule.prefix_operands.contains(&node.index))
+                }
+                _ => None,
+            };
+            let mtc = match prefix_operand {
+                Some(rule) => parse_operand(rule, rules, tokens, ctx)?,
+                None => parse_with_rule(name, rules, tokens, ctx)?,
+            };
             if let Some(idx) = cap_idx {
                 caps[idx].assign(mtc);
             }
         },
         // This could technically conflict since the same namespace is used for
         // unnamed str and unnamed regex patterns.
-        Token(GrammarToken::Named(ref name)) => {
-            let token = advance(tokens)?;
-            if &token.name == name {
-                if let Some(idx) = cap_idx {
-                    let mut captures = Vec::with_capacity(1);
-                    captures.push(Capture::Token(token.clone()));
-                    let mtc = Match { 
-                        rule: name.clone(), 
-                        captures
-                    };
-                    caps[idx].assign(mtc);
-                }
-            } else {
-                return error(&format!("token <{}>", name), token, ctx);
+        Token(GrammarToken::Named(id, ref name)) => {
+            if tokens.peek().map_or(false, |peek| peek.id != id) {
+                let found = tokens.peek().unwrap().clone();
+                return Err(ctx.unexpected(found, pat, rules));
+            }
+            let token = advance(pat, rules, tokens, ctx)?;
+            if let Some(idx) = cap_idx {
+                let mut captures = Vec::with_capacity(1);
+                captures.push(Capture::Token(token.clone()));
+                let mtc = Match { 
+                    rule: name.clone(), 
+                    captures,
+                    start: token.start,
+                    end: token.end,
+                    is_error: false,
+                    operator: None,
+                    leading_trivia: Vec::new(),
+                    trailing_trivia: Vec::new(),
+                };
+                caps[idx].assign(mtc);
             }
         }
         Token(_) => { 
             panic!("Attempted parse without assigning token names"); 
         }
         Seq(ref pats) => {
-            for pat in pats {
-                if let Some(Break) = parse_with_pattern(pat, cap_idx, caps, rules, tokens, ctx)? {
+            for (pat, child) in pats.iter().zip(children(node.sets, node.index)) {
+                if let Some(Break) = parse_with_pattern(pat, node.at(child), cap_idx, caps, rules, tokens, ctx)? {
                     return Ok(Some(Break));
                 }
             }
         }
         Opt(ref pat) => {
-            if can_consume(pat, tokens, rules) {
-                parse_with_pattern(pat, cap_idx, caps, rules, tokens, ctx)?;
+            if can_consume(pat, node.inner().sets(), tokens, ctx) {
+                parse_with_pattern(pat, node.inner(), cap_idx, caps, rules, tokens, ctx)?;
             }
         }
         ZeroPlus(ref pat) => {
-            while can_consume(pat, tokens, rules) {
-                if let Some(Break) = parse_with_pattern(pat, cap_idx, caps, rules, tokens, ctx)? {
+            while can_consume(pat, node.inner().sets(), tokens, ctx) {
+                let consumed = ctx.consumed;
+                if let Some(Break) = parse_with_pattern(pat, node.inner(), cap_idx, caps, rules, tokens, ctx)? {
                     return Ok(Some(Break));
                 }
+                if ctx.stalled_since(consumed) {
+                    break;
+                }
             }
         }
         OnePlus(ref pat) => {
-            if let Some(Break) = parse_with_pattern(pat, cap_idx, caps, rules, tokens, ctx)? {
+            if let Some(Break) = parse_with_pattern(pat, node.inner(), cap_idx, caps, rules, tokens, ctx)? {
                 return Ok(Some(Break));
             }
-            while can_consume(pat, tokens, rules) {
-                if let Some(Break) = parse_with_pattern(pat, cap_idx, caps, rules, tokens, ctx)? {
+            while can_consume(pat, node.inner().sets(), tokens, ctx) {
+                let consumed = ctx.consumed;
+                if let Some(Break) = parse_with_pattern(pat, node.inner(), cap_idx, caps, rules, tokens, ctx)? {
                     return Ok(Some(Break));
                 }
+                if ctx.stalled_since(consumed) {
+                    break;
+                }
+            }
+        }
+        SepBy(ref item, ref sep, flags) => {
+            let mut children = children(node.sets, node.index).map(|child| node.at(child));
+            let (item_node, sep_node) = (children.next().unwrap(), children.next().unwrap());
+            if flags.empty && ! can_consume(item, item_node.sets(), tokens, ctx) {
+                return Ok(None);
+            }
+            loop {
+                let consumed = ctx.consumed;
+                if let Some(Break) = parse_with_pattern(item, item_node, cap_idx, caps, rules, tokens, ctx)? {
+                    return Ok(Some(Break));
+                }
+                if ! can_consume(sep, sep_node.sets(), tokens, ctx) {
+                    break;
+                }
+                // The separators are not part of the captured items.
+                if let Some(Break) = parse_with_pattern(sep, sep_node, None, caps, rules, tokens, ctx)? {
+                    return Ok(Some(Break));
+                }
+                if flags.trailing && ! can_consume(item, item_node.sets(), tokens, ctx) {
+                    break;
+                }
+                if ctx.stalled_since(consumed) {
+                    break;
+                }
             }
         }
         AnyOf(ref pats) => {
             if tokens.peek().is_none() {
                 // TODO: is this correct: The any pattern could be optional?
-                return Err(format!("Unexpected EOF!")); 
+                return Err(ParseError::UnexpectedEof { 
+                    scope: ctx.scope.clone(), 
+                    expected: ctx.expected(pat, rules),
+                });
             }
-            let mut pat_found = false;
             // parse with the first branch that can consume the token.
-            for pat in pats {
-                match action_when_parsed(pat, tokens.peek().unwrap(), rules, 0) {
-                    MatchesToken => {
-                        if let Some(Break) = parse_with_pattern(pat, cap_idx, caps, rules, tokens, ctx)? {
-                            return Ok(Some(Break));
-                        }
-                        pat_found = true;
-                        break;
+            let peek = tokens.peek().unwrap().id;
+            match node.sets().choice(peek) {
+                Some(branch) => {
+                    let child = node.at(children(node.sets, node.index).nth(branch).unwrap());
+                    if let Some(Break) = parse_with_pattern(&pats[branch], child, cap_idx, caps, rules, tokens, ctx)? {
+                        return Ok(Some(Break));
                     }
-                    IgnoresToken => pat_found = true,
-                    CannotParse => {}
                 }
-            }
-            if ! pat_found {
-                let mut joined = String::new();
-                let last = pats.len() - 1;
-                for (i, pat) in pats.iter().enumerate() {
-                    joined.push_str(&pat.fmt());
-                    if i != last {
-                        joined.push_str(" or ");
-                    }
+                // An optional branch lets the parse continue without it.
+                None if node.sets().passes.contains(peek) => {}
+                None => {
+                    let found = tokens.peek().unwrap().clone();
+                    return Err(ctx.unexpected(found, pat, rules));
                 }
-                return error(&format!("either {}", joined), tokens.next().unwrap(), ctx);
             }
         }
         Loop(ref pat) => {
             if tokens.peek().is_none() {
-                return Err(format!("Unexpected EOF!"));
+                return Err(ParseError::UnexpectedEof { 
+                    scope: ctx.scope.clone(), 
+                    expected: ctx.expected(pat, rules),
+                });
             }
             let start = tokens.peek().unwrap().start;
             while tokens.peek().is_some() {
-                if let Some(Break) = parse_with_pattern(pat, cap_idx, caps, rules, tokens, ctx)? {
+                let consumed = ctx.consumed;
+                if let Some(Break) = parse_with_pattern(pat, node.inner(), cap_idx, caps, rules, tokens, ctx)? {
                     return Ok(Some(Break));
                 }
+                if ctx.stalled_since(consumed) {
+                    break;
+                }
             }
             // TODO: Is this even an error? It's probably just a grammar that 
             // should've used a repetition instead of a loop?
             let (line, col) = get_position(ctx.source_text, start);
-            let scope = ctx.scope.iter().flat_map(|s| Some('.').into_iter().chain(s.chars())).skip(1).collect::<String>();
-            return Err(format!("{}:{}:{}: Unclosed loop expression", scope, line, col));
+            return Err(ParseError::UnclosedLoop { 
+                pos: start, line, col, 
+                scope: ctx.scope.clone(),
+            });
         }
-        BreakOnToken(GrammarToken::Named(ref name)) => {
-            let should_break = tokens.peek().map_or(false, |peek| &peek.name == name);
+        BreakOnToken(GrammarToken::Named(id, _)) => {
+            let should_break = tokens.peek().map_or(false, |peek| peek.id == id);
             if should_break {
-                tokens.next().unwrap();
+                let token = tokens.next().unwrap();
+                ctx.consume(&token);
                 return Ok(Some(Break));
             } 
+            ctx.skipped.push(pat);
         }
         BreakOnToken(_) => { 
             panic!("Attempted parse without assigning token names"); 
         }
-        Cap(_, _) => return Err(format!("Found a capture inside another capture!")),
+        Cap(_, _) => panic!("Found a capture inside another capture!"),
+        Call(..) => unreachable!(),
     }
     
     Ok(None)
 }
 
 /// Parses the given tokens using the named rule.
-fn parse_with_rule<'a>(rule: &str, rules: &ParserRules, tokens: &mut Tokens, 
+fn parse_with_rule<'a>(rule: &str, rules: &'a ParserRules, tokens: &mut Tokens, 
     ctx: &mut ErrContext<'a>) -> ParseResult<Match> 
 {
     let rule = if let Some(rule) = rules.get(rule) {
         rule
     } else {
-        return Err(format!("Rule {:?} not found in the given set of rules.", rule));
+        return Err(ParseError::UnknownRule(rule.to_string()));
     };
+    if rule.operators.is_empty() {
+        parse_operand(rule, rules, tokens, ctx)
+    } else {
+        parse_with_operators(rule, 0, rules, tokens, ctx)
+    }
+}
+
+/// Parses a chain of operands and binary operators of the given rule by 
+/// precedence climbing, only combining operators that bind at least as 
+/// tightly as 'min_precedence'.
+fn parse_with_operators<'a>(rule: &'a ParserRule, min_precedence: usize, 
+    rules: &'a ParserRules, tokens: &mut Tokens, ctx: &mut ErrContext<'a>) -> ParseResult<Match> 
+{
+    let mut lhs = parse_operand(rule, rules, tokens, ctx)?;
+    loop {
+        let op = match tokens.peek().and_then(|peek| rule.operator(peek.id)) {
+            Some(op) if op.precedence >= min_precedence => op,
+            _ => break,
+        };
+        let token = tokens.next().unwrap();
+        ctx.consume(&token);
+        let rhs_precedence = match op.assoc {
+            Assoc::Left => op.precedence + 1,
+            Assoc::Right => op.precedence,
+        };
+        let rhs = parse_with_operators(rule, rhs_precedence, rules, tokens, ctx)?;
+        lhs = Match {
+            rule: rule.name.clone(),
+            start: lhs.start,
+            end: rhs.end,
+            is_error: false,
+            operator: Some(token),
+            leading_trivia: Vec::new(),
+            trailing_trivia: Vec::new(),
+            captures: vec![Capture::Single(Box::new(lhs)), Capture::Single(Box::new(rhs))],
+        };
+    }
+    Ok(lhs)
+}
+
+/// Parses the given tokens using the pattern of the given rule.
+fn parse_operand<'a>(rule: &'a ParserRule, rules: &'a ParserRules, tokens: &mut Tokens, 
+    ctx: &mut ErrContext<'a>) -> ParseResult<Match> 
+{
     let mut mtc = Match::new(&rule);
+    mtc.start = tokens.peek().map_or(ctx.last_end, |token| token.start);
     ctx.scope.push(rule.name.clone());
-    parse_with_pattern(&rule.pat, None, &mut mtc.captures, rules, tokens, ctx)?;
+    let root = Node { sets: &rule.sets, index: 0 };
+    let res = parse_with_pattern(&rule.pat, root, None, &mut mtc.captures, rules, tokens, ctx);
+    if let Err(err) = res {
+        let recoverable = match err {
+            ParseError::UnexpectedToken { .. } | ParseError::UnexpectedEof { .. } 
+            | ParseError::UnclosedLoop { .. } => true,
+            _ => false,
+        };
+        if ! (ctx.recover && recoverable) {
+            let _ = ctx.scope.pop();
+            return Err(err);
+        }
+        ctx.recover(err, &rule.sets[0].follow, rules, tokens);
+        mtc.is_error = true;
+    }
     let _ = ctx.scope.pop();
+    // A match that consumed no tokens is empty, and ends where it starts.
+    mtc.end = if ctx.last_end > mtc.start { ctx.last_end } else { mtc.start };
     Ok(mtc)
 }
 
-/// Parses the given tokens using the named 'start' rule.
-pub fn parse_with_rules(start: &str, rules: &ParserRules, mut tokens: Vec<Token>, 
+/// Parses the tokens read from the given source, or the tokens returned by 
+/// 'lex', using the named 'start' rule. The tokens are read as they are needed, so lexing errors are returned
+/// instead of the parse result.
+pub fn parse_with_rules<S: IntoTokenSource>(start: &str, rules: &ParserRules, tokens: S, 
     source_text: &str) -> ParseResult<Match> 
 {
-    let eof = Token::new(Rc::new("EOF".to_string()), source_text.len(), source_text.len());
-    tokens.push(eof);
-    let mut tokens = tokens.into_iter().peekable();
-    let mut err_ctx = ErrContext { scope: Vec::new(), source_text: source_text };
-    parse_with_rule(start, rules, &mut tokens, &mut err_ctx)
+    let mut source = SourceTokens::new(tokens.into_token_source(), &rules.symbols, source_text);
+    let mut err_ctx = ErrContext::new(source_text, false);
+    let res = {
+        let mut tokens = (&mut source as &mut dyn Iterator<Item=Token>).peekable();
+        parse_with_rule(start, rules, &mut tokens, &mut err_ctx)
+    };
+    match source.error {
+        Some(err) => Err(err),
+        None => res,
+    }
+}
+
+/// Parses the given tokens using the named 'start' rule, recovering from 
+/// errors by skipping to the next synchronization token ('@sync') instead of 
+/// stopping at the first error. Rules that failed to parse are marked as 
+/// errors in the returned match, and every error found is returned with it.
+/// The match is only missing if the parse could not start at all. A lexing
+/// error ends the tokens, and is returned with the other errors.
+pub fn parse_with_recovery<S: IntoTokenSource>(start: &str, rules: &ParserRules, tokens: S, 
+    source_text: &str) -> (Option<Match>, Vec<ParseError>) 
+{
+    let mut source = SourceTokens::new(tokens.into_token_source(), &rules.symbols, source_text);
+    let mut err_ctx = ErrContext::new(source_text, true);
+    let res = {
+        let mut tokens = (&mut source as &mut dyn Iterator<Item=Token>).peekable();
+        parse_with_rule(start, rules, &mut tokens, &mut err_ctx)
+    };
+    let mtc = match res {
+        Ok(mtc) => Some(mtc),
+        Err(err) => {
+            err_ctx.errors.push(err);
+            None
+        }
+    };
+    if let Some(err) = source.error {
+        err_ctx.errors.push(err);
+    }
+    (mtc, err_ctx.errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grammar::parse_raw_rules;
+    use lexer::{find_lexer_rules, lex};
+
+    /// Parses the text with the 'program' rule of the grammar.
+    fn parse(grammar: &str, text: &str) -> ParseResult<Match> {
+        let raw_rules = parse_raw_rules(grammar).unwrap();
+        let lexer_rules = find_lexer_rules(&raw_rules);
+        let parser_rules = find_parser_rules(&raw_rules);
+        let tokens = lex(text, &lexer_rules).unwrap();
+        parse_with_rules("program", &parser_rules, tokens, text)
+    }
+
+    /// The kinds of tokens of a lexer that doesn't use the grammar.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Kind {
+        Digit,
+        Plus,
+        Other,
+    }
+    
+    /// Lexes every char of a text as a token.
+    struct CharLexer<'a> {
+        chars: ::std::str::CharIndices<'a>,
+    }
+    impl<'a> TokenSource for CharLexer<'a> {
+        type Kind = Kind;
+        
+        fn next_token(&mut self) -> Option<lexer::SourceToken<Kind>> {
+            self.chars.next().map(|(pos, ch)| {
+                let kind = match ch {
+                    _ if ch.is_ascii_digit() => Kind::Digit,
+                    '+' => Kind::Plus,
+                    _ => Kind::Other,
+                };
+                Ok((kind, pos, pos + ch.len_utf8()))
+            })
+        }
+        
+        fn token_id(&self, kind: &Kind, symbols: &Symbols) -> Option<TokenId> {
+            match *kind {
+                Kind::Digit => symbols.token_id("DIGIT"),
+                Kind::Plus => symbols.token_id("+"),
+                Kind::Other => None,
+            }
+        }
+    }
+    
+    #[test]
+    fn parses_the_tokens_of_a_token_source() {
+        let grammar = r##"
+            program: $$DIGIT ("+" $$DIGIT)* EOF
+            DIGIT: r#"[0-9]"#
+        "##;
+        let parser_rules = find_parser_rules(&parse_raw_rules(grammar).unwrap());
+        let parse = |text: &str| {
+            parse_with_rules("program", &parser_rules, CharLexer { chars: text.char_indices() }, text)
+        };
+        let text = "1+2+3";
+        let mtc = parse(text).unwrap();
+        let digits = mtc.multiple(0).unwrap().iter().map(|digit| digit.slice(text)).collect::<Vec<_>>();
+        assert_eq!(digits, vec!["1", "2", "3"]);
+        match parse("1+x") {
+            Err(ParseError::Lex { pos, line, col }) => assert_eq!((pos, line, col), (2, 1, 3)),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+    
+    #[test]
+    fn matches_span_their_tokens() {
+        let grammar = r##"
+            program: $$pair* EOF
+            pair: $ID "=" $NUM
+            ID: r#"[a-z]+"#
+            NUM: r#"[0-9]+"#
+            _WS: r#"\s+"#
+        "##;
+        let text = " a = 1\n  bc=23 ";
+        let mtc = parse(grammar, text).unwrap();
+        // The EOF token is at the end of the text.
+        assert_eq!((mtc.start, mtc.end), (1, 15));
+        let pairs = mtc.multiple(0).unwrap();
+        assert_eq!(pairs.iter().map(|pair| pair.slice(text)).collect::<Vec<_>>(), vec!["a = 1", "bc=23"]);
+        assert_eq!(pairs[1].start_position(text), (2, 3));
+        assert_eq!(pairs[1].end_position(text), (2, 8));
+        let num = pairs[1].single(1).unwrap();
+        assert_eq!((num.start, num.end), (12, 14));
+    }
+
+    #[test]
+    fn empty_matches_start_and_end_at_the_next_token() {
+        let grammar = r##"
+            program: $list $ID $list EOF
+            list: $NUM*
+            ID: r#"[a-z]+"#
+            NUM: r#"[0-9]+"#
+            _WS: r#"\s+"#
+        "##;
+        let text = "  abc  ";
+        let mtc = parse(grammar, text).unwrap();
+        let before = mtc.single(0).unwrap();
+        assert_eq!((before.start, before.end), (2, 2));
+        let after = mtc.single(2).unwrap();
+        assert_eq!((after.start, after.end), (7, 7));
+    }
+
+    #[test]
+    fn unexpected_tokens_are_reported_with_the_expected_ones() {
+        let grammar = r##"
+            program: $$stmt* EOF
+            stmt: $ID "=" ($NUM | $ID) ";"
+            ID: r#"[a-z]+"#
+            NUM: r#"[0-9]+"#
+            _WS: r#"\s+"#
+        "##;
+        let text = "a = 1;\nb = ;";
+        let err = parse(grammar, text).unwrap_err();
+        match err {
+            ParseError::UnexpectedToken { ref found, ref found_name, line, col, .. } => {
+                assert_eq!(found.slice(text), ";");
+                assert_eq!(**found_name, ";");
+                assert_eq!((line, col), (2, 5));
+            }
+            _ => panic!("Unexpected error: {:?}", err),
+        }
+        assert_eq!(err.scope().iter().map(|rule| rule.as_str()).collect::<Vec<_>>(), vec!["program", "stmt"]);
+        assert_eq!(err.expected().iter().map(|name| name.as_str()).collect::<Vec<_>>(), vec!["NUM", "ID"]);
+        assert_eq!(err.to_string(), "program.stmt:2:5: Expected one of <NUM>, <ID>, found ;");
+    }
+
+    #[test]
+    fn running_out_of_tokens_is_an_unexpected_eof() {
+        let grammar = r##"
+            program: $ID EOF "!"
+            ID: r#"[a-z]+"#
+        "##;
+        let err = parse(grammar, "a").unwrap_err();
+        match err {
+            ParseError::UnexpectedEof { ref scope, ref expected } => {
+                assert_eq!(scope.iter().map(|rule| rule.as_str()).collect::<Vec<_>>(), vec!["program"]);
+                assert_eq!(expected.iter().map(|name| name.as_str()).collect::<Vec<_>>(), vec!["!"]);
+            }
+            _ => panic!("Unexpected error: {:?}", err),
+        }
+        assert_eq!(err.to_string(), "program: Unexpected EOF, expected <!>");
+    }
+
+    /// Parses the text with recovery, returning the rules of the statements
+    /// that were parsed, and the number of errors.
+    fn parse_statements(grammar: &str, text: &str) -> (Vec<String>, usize) {
+        let raw_rules = parse_raw_rules(grammar).unwrap();
+        let lexer_rules = find_lexer_rules(&raw_rules);
+        let parser_rules = find_parser_rules(&raw_rules);
+        let tokens = lex(text, &lexer_rules).unwrap();
+        let (mtc, errors) = parse_with_recovery("program", &parser_rules, tokens.into_iter(), text);
+        let mtc = mtc.unwrap();
+        let statements = mtc.multiple(0).unwrap().iter().map(|stmt| {
+            format!("{}{}", if stmt.is_error { "!" } else { "" }, stmt.slice(text))
+        }).collect();
+        (statements, errors.len())
+    }
+    
+    #[test]
+    fn recovery_skips_the_sync_token() {
+        let grammar = r##"
+            @sync ";"
+            program: $$stmt* EOF
+            stmt: $ID "=" $NUM ";"
+            ID: r#"[a-z]+"#
+            NUM: r#"[0-9]+"#
+            _WS: r#"\s+"#
+        "##;
+        let (statements, errors) = parse_statements(grammar, "a = 1; b = = 2; c = 3; d = x; e = 5;");
+        assert_eq!(statements, vec!["a = 1;", "!b = = 2;", "c = 3;", "!d = x;", "e = 5;"]);
+        assert_eq!(errors, 2);
+    }
+    
+    #[test]
+    fn recovery_keeps_a_sync_token_that_can_follow() {
+        let grammar = r##"
+            @sync ";"
+            program: ($$stmt ";")* EOF
+            stmt: $ID "=" $NUM
+            ID: r#"[a-z]+"#
+            NUM: r#"[0-9]+"#
+            _WS: r#"\s+"#
+        "##;
+        let (statements, errors) = parse_statements(grammar, "a = 1; b = ; c = 3; d = x;");
+        assert_eq!(statements, vec!["a = 1", "!b =", "c = 3", "!d = x"]);
+        assert_eq!(errors, 2);
+    }
+    
+    /// Formats the operations of the match with parentheses.
+    fn group(mtc: &Match, text: &str) -> String {
+        match mtc.operator {
+            Some(ref op) => format!("({} {} {})", group(mtc.single(0).unwrap(), text), 
+                &text[op.start..op.end], group(mtc.single(1).unwrap(), text)),
+            None => match mtc.single(0) {
+                Some(operand) if mtc.rule == operand.rule && operand.end == mtc.end => {
+                    format!("({}{})", &text[mtc.start..operand.start], group(operand, text))
+                }
+                _ => mtc.slice(text).to_string(),
+            },
+        }
+    }
+    
+    #[test]
+    fn prefix_operands_bind_tighter_than_binary_operators() {
+        let grammar = r##"
+            program: $expr EOF
+            expr: $NUM | "(" $expr ")" | "-" $expr
+            NUM: r#"[0-9]+"#
+            _WS: r#"\s+"#
+            @left expr "+" "-"
+            @left expr "*"
+        "##;
+        let raw_rules = parse_raw_rules(grammar).unwrap();
+        let lexer_rules = find_lexer_rules(&raw_rules);
+        let parser_rules = find_parser_rules(&raw_rules);
+        let grouped = |text: &str| {
+            let tokens = lex(text, &lexer_rules).unwrap();
+            let mtc = parse_with_rules("program", &parser_rules, tokens.into_iter(), text).unwrap();
+            group(mtc.single(0).unwrap(), text)
+        };
+        assert_eq!(grouped("-1 + 2"), "((-1) + 2)");
+        assert_eq!(grouped("1 - -2 * 3"), "(1 - ((-2) * 3))");
+        assert_eq!(grouped("-(1 + 2)"), "(-(1 + 2))");
+    }
+    
+    /// Returns the numbers in the list that the text is parsed as, or None if
+    /// it can't be parsed.
+    fn numbers(list: &str, text: &str) -> Option<Vec<String>> {
+        let grammar = format!("program: \"[\" {} \"]\" EOF\nNUM: r#\"[0-9]+\"#\n", list);
+        parse(&grammar, text).ok().map(|mtc| {
+            mtc.multiple(0).unwrap().iter().map(|num| num.slice(text).to_string()).collect()
+        })
+    }
+    
+    #[test]
+    fn separated_list_needs_an_item() {
+        let list = "{$$NUM, \",\"}";
+        assert_eq!(numbers(list, "[1]"), Some(vec!["1".to_string()]));
+        assert_eq!(numbers(list, "[1,2,3]"), Some(vec!["1".to_string(), "2".to_string(), "3".to_string()]));
+        assert_eq!(numbers(list, "[]"), None);
+        assert_eq!(numbers(list, "[1,]"), None);
+        assert_eq!(numbers(list, "[1,,2]"), None);
+    }
+    
+    #[test]
+    fn separated_list_with_a_trailing_separator() {
+        let list = "{$$NUM, \",\",}";
+        assert_eq!(numbers(list, "[1]"), Some(vec!["1".to_string()]));
+        assert_eq!(numbers(list, "[1,]"), Some(vec!["1".to_string()]));
+        assert_eq!(numbers(list, "[1,2,]"), Some(vec!["1".to_string(), "2".to_string()]));
+        assert_eq!(numbers(list, "[]"), None);
+        assert_eq!(numbers(list, "[1,,]"), None);
+    }
+    
+    #[test]
+    fn separated_list_that_can_be_empty() {
+        let list = "{$$NUM, \",\",}*";
+        assert_eq!(numbers(list, "[]"), Some(vec![]));
+        assert_eq!(numbers(list, "[1,2]"), Some(vec!["1".to_string(), "2".to_string()]));
+        assert_eq!(numbers(list, "[1,2,]"), Some(vec!["1".to_string(), "2".to_string()]));
+        assert_eq!(numbers(list, "[,]"), None);
+    }
 }
@@ -0,0 +1,208 @@
+//! A table of the token and rule names of a grammar, so that tokens and
+//! rules can be referred to by dense integer ids instead of by name.
+
+use common::*;
+use grammar::{GrammarToken, Pat, RawRules};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// The tokens that the lexer makes for the lines of an indentation-sensitive
+/// ('@indent') grammar.
+pub const LAYOUT_TOKENS: [&str; 3] = ["NEWLINE", "INDENT", "DEDENT"];
+
+/// The id of a token name in the symbol table of a grammar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TokenId(usize);
+impl TokenId {
+    /// The token that ends every token stream.
+    pub const EOF: TokenId = TokenId(0);
+
+    /// Returns the id at the given index of a symbol table.
+    pub(crate) fn from_index(index: usize) -> TokenId {
+        TokenId(index)
+    }
+
+    /// Returns the index of this token in the symbol table, which is below
+    /// the number of tokens in the table.
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// The id of a rule name in the symbol table of a grammar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RuleId(usize);
+impl RuleId {
+    /// Returns the index of this rule in the symbol table, which is below
+    /// the number of rules in the table.
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// The names of every token and rule of a grammar, numbered in the order
+/// they are first mentioned. Token 0 is always 'EOF'.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbols {
+    tokens: Vec<Rc<String>>,
+    token_ids: HashMap<String, TokenId>,
+    rules: Vec<Rc<String>>,
+    rule_ids: HashMap<String, RuleId>,
+}
+impl Symbols {
+    /// Numbers the tokens and rules of the given set of raw rules. The same
+    /// rules always give the same ids, so that tokens lexed with the lexer
+    /// rules can be read by the parser rules of the same grammar.
+    pub fn new(rules: &RawRules) -> Symbols {
+        fn add_tokens_in(pat: &Pat, symbols: &mut Symbols) {
+            use grammar::Pat::*;
+            match *pat {
+                Rule(ref name) => {
+                    if is_token_id(name) {
+                        symbols.add_token(name);
+                    }
+                }
+                  Token(GrammarToken::Str(ref name)) | Token(GrammarToken::Re(ref name))
+                | BreakOnToken(GrammarToken::Str(ref name))
+                | BreakOnToken(GrammarToken::Re(ref name)) => {
+                    symbols.add_token(name);
+                }
+                Token(GrammarToken::Named(_, ref name))
+                | BreakOnToken(GrammarToken::Named(_, ref name)) => {
+                    symbols.add_token(name);
+                }
+                Seq(ref pats) | AnyOf(ref pats) | Call(_, ref pats) => {
+                    for pat in pats {
+                        add_tokens_in(pat, symbols);
+                    }
+                }
+                Cap(_, ref pat) | Opt(ref pat) | ZeroPlus(ref pat) | OnePlus(ref pat)
+                | Loop(ref pat) => {
+                    add_tokens_in(pat, symbols);
+                }
+                SepBy(ref item, ref sep, _) => {
+                    add_tokens_in(item, symbols);
+                    add_tokens_in(sep, symbols);
+                }
+            }
+        }
+
+        let mut symbols = Symbols {
+            tokens: Vec::new(),
+            token_ids: HashMap::new(),
+            rules: Vec::new(),
+            rule_ids: HashMap::new(),
+        };
+        symbols.add_token("EOF");
+        for &(ref name, ref rule) in rules.iter() {
+            if is_token_id(name) {
+                symbols.add_token(name);
+            } else {
+                // Parameterized rules are expanded into the rules that use 
+                // them, but the tokens in them are still read.
+                if rule.params.is_empty() {
+                    symbols.add_rule(name);
+                }
+                add_tokens_in(&rule.pat, &mut symbols);
+            }
+        }
+        for directive in &rules.directives {
+            for arg in &directive.args {
+                add_tokens_in(arg, &mut symbols);
+            }
+        }
+        // The lexer of an indentation-sensitive grammar makes these tokens,
+        // even if no rule mentions them.
+        if ! rules.directives_named("indent").is_empty() {
+            for &name in &LAYOUT_TOKENS {
+                symbols.add_token(name);
+            }
+        }
+        symbols
+    }
+
+    fn add_token(&mut self, name: &str) {
+        if ! self.token_ids.contains_key(name) {
+            self.token_ids.insert(name.to_string(), TokenId(self.tokens.len()));
+            self.tokens.push(Rc::new(name.to_string()));
+        }
+    }
+
+    fn add_rule(&mut self, name: &str) {
+        if ! self.rule_ids.contains_key(name) {
+            self.rule_ids.insert(name.to_string(), RuleId(self.rules.len()));
+            self.rules.push(Rc::new(name.to_string()));
+        }
+    }
+
+    /// Returns the id of the token with the given name.
+    pub fn token_id(&self, name: &str) -> Option<TokenId> {
+        self.token_ids.get(name).cloned()
+    }
+
+    /// Returns the name of the token with the given id.
+    pub fn token_name(&self, id: TokenId) -> &Rc<String> {
+        &self.tokens[id.0]
+    }
+
+    /// Returns the number of tokens in the table.
+    pub fn token_count(&self) -> usize {
+        self.tokens.len()
+    }
+
+    /// Returns the id of the rule with the given name.
+    pub fn rule_id(&self, name: &str) -> Option<RuleId> {
+        self.rule_ids.get(name).cloned()
+    }
+
+    /// Returns the name of the rule with the given id.
+    pub fn rule_name(&self, id: RuleId) -> &Rc<String> {
+        &self.rules[id.0]
+    }
+
+    /// Returns the number of rules in the table.
+    pub fn rule_count(&self) -> usize {
+        self.rules.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grammar::parse_raw_rules;
+
+    #[test]
+    fn names_and_ids_round_trip() {
+        let raw_rules = parse_raw_rules(r##"
+            program: $$item* EOF
+            item: $ID "=" $NUM
+            ID: r#"[a-z]+"#
+            NUM: r#"[0-9]+"#
+        "##).unwrap();
+        let symbols = Symbols::new(&raw_rules);
+        assert_eq!(symbols.token_id("EOF"), Some(TokenId::EOF));
+        assert_eq!(symbols.token_count(), 4);
+        for index in 0..symbols.token_count() {
+            let id = TokenId::from_index(index);
+            assert_eq!(symbols.token_id(symbols.token_name(id)), Some(id));
+        }
+        assert_eq!(symbols.rule_count(), 2);
+        for name in &["program", "item"] {
+            let id = symbols.rule_id(name).unwrap();
+            assert_eq!(**symbols.rule_name(id), *name);
+        }
+        assert_eq!(symbols.token_id("item"), None);
+        assert_eq!(symbols.rule_id("ID"), None);
+    }
+
+    #[test]
+    fn same_rules_give_the_same_ids() {
+        let grammar = "program: A B EOF\nB: \"b\"\nA: \"a\"\n";
+        let first = Symbols::new(&parse_raw_rules(grammar).unwrap());
+        let second = Symbols::new(&parse_raw_rules(grammar).unwrap());
+        assert_eq!(first, second);
+        // Tokens are numbered in the order they are first mentioned.
+        assert_eq!(first.token_id("A").map(TokenId::index), Some(1));
+        assert_eq!(first.token_id("B").map(TokenId::index), Some(2));
+    }
+}
@@ -0,0 +1,221 @@
+//! Functions to parse a text into a lossless syntax tree, where the skipped
+//! tokens of the text are kept as the trivia of the matches next to them.
+
+use lexer::{LexerRules, Token, lex_with_trivia};
+use parser::{Match, Capture, ParserRules, parse_with_rules};
+use error::ParseError;
+use std::collections::{HashMap, HashSet};
+
+/// A place that trivia can be attached to: before the matches that start at
+/// a byte index, or after the matches that end at one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Boundary {
+    Start(usize),
+    End(usize),
+}
+
+/// A skipped token, with the boundaries it is attached to, best first.
+#[derive(Debug)]
+struct Trivia {
+    token: Token,
+    boundaries: Vec<Boundary>,
+    /// Where the line of the token starts. Trailing trivia is first attached
+    /// to a match that starts on the same line, so that a comment after a
+    /// statement belongs to the statement.
+    line_start: usize,
+    attached: bool,
+}
+
+/// Finds the boundaries of each trivia token, by the tokens around it. A
+/// token on the same line as the token before it trails the matches ending
+/// there, and other tokens lead the matches starting at the next token that
+/// starts one. Uncaptured tokens in between, like line breaks, are passed
+/// over, but not a token that ends a match.
+fn find_boundaries(tokens: &[Token], trivia: Vec<Token>, text: &str, mtc: &Match) -> Vec<Trivia> {
+    let mut starts = HashSet::new();
+    let mut ends = HashSet::new();
+    find_match_sides(mtc, &mut starts, &mut ends);
+    let mut next = 0;
+    trivia.into_iter().map(|token| {
+        while next < tokens.len() && tokens[next].start < token.end {
+            next += 1;
+        }
+        // Layout tokens can overlap the trivia, so look past them.
+        let prev = tokens[..next].iter().rev().find(|prev| prev.end <= token.start);
+        let after_prev = prev.map(|prev| Boundary::End(prev.end));
+        let mut before_next = None;
+        for next in &tokens[next..] {
+            if starts.contains(&next.start) {
+                before_next = Some(Boundary::Start(next.start));
+                break;
+            }
+            if ends.contains(&next.end) {
+                break;
+            }
+        }
+        let line_start = text[..token.start].rfind('\n').map_or(0, |index| index + 1);
+        // A token that ends a line, like a NEWLINE, ends before the line of
+        // the trivia starts.
+        let same_line = prev.map_or(false, |prev| prev.end > line_start);
+        let boundaries = if same_line {
+            after_prev.into_iter().chain(before_next).collect()
+        } else {
+            before_next.into_iter().chain(after_prev).collect()
+        };
+        Trivia { token, boundaries, line_start, attached: false }
+    }).collect()
+}
+
+/// Finds where the matches with tokens start and end.
+fn find_match_sides(mtc: &Match, starts: &mut HashSet<usize>, ends: &mut HashSet<usize>) {
+    if mtc.start < mtc.end {
+        starts.insert(mtc.start);
+        ends.insert(mtc.end);
+    }
+    for capture in &mtc.captures {
+        match *capture {
+            Capture::Single(ref child) | Capture::Optional(Some(ref child)) => find_match_sides(child, starts, ends),
+            Capture::Multiple(ref children) => {
+                for child in children {
+                    find_match_sides(child, starts, ends);
+                }
+            }
+            Capture::Optional(None) | Capture::Token(_) => {}
+        }
+    }
+}
+
+/// Calls the function with each match captured by the given match.
+fn for_each_child<F: FnMut(&mut Match)>(mtc: &mut Match, mut f: F) {
+    for capture in &mut mtc.captures {
+        match *capture {
+            Capture::Single(ref mut child) | Capture::Optional(Some(ref mut child)) => f(child),
+            Capture::Multiple(ref mut children) => {
+                for child in children {
+                    f(child);
+                }
+            }
+            Capture::Optional(None) | Capture::Token(_) => {}
+        }
+    }
+}
+
+/// Attaches the waiting trivia to the outermost matches at their boundaries.
+fn attach_at_boundaries(mtc: &mut Match, trivia: &mut [Trivia], waiting: &mut HashMap<Boundary, Vec<usize>>) {
+    // Matches without tokens have no side to attach trivia to.
+    if mtc.start < mtc.end {
+        if let Some(indices) = waiting.remove(&Boundary::Start(mtc.start)) {
+            for index in indices {
+                mtc.leading_trivia.push(trivia[index].token.clone());
+                trivia[index].attached = true;
+            }
+        }
+        if let Some(indices) = waiting.get_mut(&Boundary::End(mtc.end)) {
+            let start = mtc.start;
+            let trailing_trivia = &mut mtc.trailing_trivia;
+            indices.retain(|&index| {
+                if start < trivia[index].line_start {
+                    return true;
+                }
+                trailing_trivia.push(trivia[index].token.clone());
+                trivia[index].attached = true;
+                false
+            });
+        }
+    }
+    for_each_child(mtc, |child| attach_at_boundaries(child, trivia, waiting));
+}
+
+/// Attaches the token to the innermost match that contains it.
+fn attach_within(mtc: &mut Match, token: Token) {
+    let mut token = Some(token);
+    for_each_child(mtc, |child| {
+        let contains = token.as_ref().map_or(false, |token| {
+            child.start < child.end && child.start <= token.start && token.end <= child.end
+        });
+        if contains {
+            attach_within(child, token.take().unwrap());
+        }
+    });
+    if let Some(token) = token {
+        if token.end <= mtc.start {
+            mtc.leading_trivia.push(token);
+        } else {
+            mtc.trailing_trivia.push(token);
+        }
+    }
+}
+
+/// Keeps the trivia of each match in the order of the text.
+fn sort_trivia(mtc: &mut Match) {
+    mtc.leading_trivia.sort_by_key(|token| token.start);
+    mtc.trailing_trivia.sort_by_key(|token| token.start);
+    for_each_child(mtc, sort_trivia);
+}
+
+/// Attaches each trivia token to the match that it belongs to. Tokens are
+/// attached to the outermost match at their best boundary, then at their
+/// other boundary, and otherwise to the innermost match that contains them,
+/// which only happens between tokens that aren't captured.
+fn attach_trivia(mtc: &mut Match, mut trivia: Vec<Trivia>) {
+    for pass in 0..2 {
+        let mut waiting: HashMap<Boundary, Vec<usize>> = HashMap::new();
+        for (index, trivia) in trivia.iter_mut().enumerate() {
+            if trivia.attached || trivia.boundaries.len() <= pass {
+                continue;
+            }
+            if pass > 0 {
+                trivia.line_start = 0;
+            }
+            waiting.entry(trivia.boundaries[pass]).or_insert_with(Vec::new).push(index);
+        }
+        attach_at_boundaries(mtc, &mut trivia, &mut waiting);
+    }
+    for trivia in trivia.into_iter().filter(|trivia| ! trivia.attached) {
+        attach_within(mtc, trivia.token);
+    }
+    sort_trivia(mtc);
+}
+
+/// Lexes and parses the text like 'lex' and 'parse_with_rules', but keeps
+/// every skipped token of the text (those whose names start with '_') as the
+/// leading or trailing trivia of a match, so that whitespace and comments can
+/// be found in the tree, ie: to format the text. The trivia on the line of a
+/// token goes to the outermost match that ends with the token and starts on
+/// the same line, and other trivia goes to the outermost match that starts
+/// with the next token.
+pub fn parse_lossless(start: &str, lexer_rules: &LexerRules, parser_rules: &ParserRules,
+    text: &str) -> Result<Match, ParseError>
+{
+    let (tokens, trivia) = lex_with_trivia(text, lexer_rules)?;
+    let mut mtc = parse_with_rules(start, parser_rules, tokens.clone().into_iter(), text)?;
+    let trivia = find_boundaries(&tokens, trivia, text, &mtc);
+    attach_trivia(&mut mtc, trivia);
+    Ok(mtc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grammar::parse_raw_rules;
+    use lexer::find_lexer_rules;
+    use parser::find_parser_rules;
+    
+    fn slices(tokens: &[Token], text: &str) -> Vec<String> {
+        tokens.iter().map(|token| text[token.start..token.end].to_string()).collect()
+    }
+    
+    #[test]
+    fn comment_line_leads_the_next_entry() {
+        let raw_rules = parse_raw_rules(include_str!("../examples/toml.heck")).unwrap();
+        let lexer_rules = find_lexer_rules(&raw_rules);
+        let parser_rules = find_parser_rules(&raw_rules);
+        let text = "a = +1 # one\n# about b\nb = [ +1 ]";
+        let document = parse_lossless("document", &lexer_rules, &parser_rules, text).unwrap();
+        assert!(document.leading_trivia.is_empty());
+        assert!(document.trailing_trivia.is_empty());
+        let entries = document.multiple(0).unwrap();
+        assert_eq!(slices(&entries[0].trailing_trivia, text), vec![" ", "# one"]);
+        assert_eq!(slices(&entries[1].leading_trivia, text), vec!["# about b"]);
+    }
+}
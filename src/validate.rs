@@ -1,32 +1,36 @@
 //! Functions to validate that a grammar is logically sound.
 
-use parser::ParserRules;
-use lexer::LexerRules;
+use parser::{ParserRules, ParserRule};
+use lexer::{LexerRules, DEFAULT_MODE};
 use std::rc::Rc;
-use std::collections::{HashSet, HashMap};
+use std::collections::{HashSet, HashMap, VecDeque};
 use std::ops::Deref;
-use grammar::{RawRules, Pat, GrammarToken};
-
-pub struct GrammarError {
-    pub pos: usize, // useless atm.
-    pub line: usize, // useless atm.
-    pub col: usize, // useless atm.
-    pub message: String,
-}
-impl GrammarError {
-    pub fn new(pos: usize, message: String) -> GrammarError {
-        GrammarError { pos, message, line: 0, col: 0 }
-    }
-}
+use grammar::{RawRules, GrammarRule, Directive, Pat, GrammarToken, next_span};
+use common::{is_token_id, Span};
+use error::GrammarError;
+use analysis::{find_nullable_rules, find_left_rules, find_used_rules, is_nullable, child_nodes};
+use symbols::{Symbols, TokenId, LAYOUT_TOKENS};
+use dfa::START;
 
 /// Runs all the various validators on the given rules.
 pub fn validate_rules(raw_rules: &RawRules, lexer_rules: &LexerRules, parser_rules: &ParserRules) -> Vec<GrammarError> {
     let mut lints = Vec::new();
+    validate_params_with(raw_rules, &mut |error| {
+        lints.push(error);
+    });
+    let misused_templates = ! lints.is_empty();
     //eprintln!("heck: Validating whether the grammar is closed in...");
     validate_closed_in_with(parser_rules, lexer_rules, &mut |error| {
         lints.push(error);
     });
-    validate_unused_tokens_with(parser_rules, lexer_rules, &mut |error| {
+    // The tokens that a misused template would use are unknown, so they are
+    // only reported once the templates are used correctly.
+    if ! misused_templates {
+        validate_unused_tokens_with(parser_rules, lexer_rules, &mut |error| {
+            lints.push(error);
+        });
+    }
+    validate_tokens_with(lexer_rules, &mut |error| {
         lints.push(error);
     });
     validate_all_groups_named_with(parser_rules, &mut |error| {
@@ -35,23 +39,363 @@ pub fn validate_rules(raw_rules: &RawRules, lexer_rules: &LexerRules, parser_rul
     validate_no_duplicate_rule_names(raw_rules, &mut |error| {
         lints.push(error);
     });
+    validate_directives_with(raw_rules, &mut |error| {
+        lints.push(error);
+    });
+    validate_imports_with(raw_rules, &mut |error| {
+        lints.push(error);
+    });
+    validate_operators_with(parser_rules, &mut |error| {
+        lints.push(error);
+    });
+    validate_unreachable_with(parser_rules, &mut |error| {
+        lints.push(error);
+    });
+    validate_ll1_conflicts_with(parser_rules, &mut |error| {
+        lints.push(error);
+    });
     validate_endless_loops_into(parser_rules, &mut lints);
     validate_left_recursion_into(parser_rules, &mut lints);
+    for lint in lints.iter_mut() {
+        raw_rules.locate_error(lint);
+    }
     lints
 }
 
+/// Validates that parameterized rules are declared with rule names as their
+/// parameters, don't use themselves, and are used with as many arguments as 
+/// they have parameters. Normal rules can't be given arguments.
+pub fn validate_params_with<F: FnMut(GrammarError)>(raw_rules: &RawRules, send_error: &mut F) {
+    fn find_calls<'a, F: FnMut(GrammarError)>(pat: &'a Pat, spans: &[Span], index: &mut usize,
+        rule: &str, templates: &HashMap<&str, &'a GrammarRule>, calls: &mut Vec<&'a str>,
+        send_error: &mut F)
+    {
+        use grammar::Pat::*;
+        let span = next_span(spans, index);
+        match *pat {
+            Rule(ref name) => {
+                if templates.contains_key(name.as_str()) {
+                    send_error(GrammarError::at(span, format!(
+                        "{}: '{}' has parameters, so it can only be used with arguments, ie: {}<...>",
+                        rule, name, name
+                    )));
+                }
+            }
+            Token(_) | BreakOnToken(_) => {}
+            Seq(ref pats) | AnyOf(ref pats) => {
+                for pat in pats {
+                    find_calls(pat, spans, index, rule, templates, calls, send_error);
+                }
+            }
+            Cap(_, ref inner) | Opt(ref inner) |
+            ZeroPlus(ref inner) | OnePlus(ref inner) | Loop(ref inner) => {
+                find_calls(inner, spans, index, rule, templates, calls, send_error);
+            }
+            SepBy(ref item, ref sep, _) => {
+                find_calls(item, spans, index, rule, templates, calls, send_error);
+                find_calls(sep, spans, index, rule, templates, calls, send_error);
+            }
+            Call(ref name, ref args) => {
+                match templates.get(name.as_str()) {
+                    Some(template) => {
+                        if template.params.len() != args.len() {
+                            send_error(GrammarError::at(span, format!(
+                                "{}: '{}' takes {} argument(s), but is given {}",
+                                rule, name, template.params.len(), args.len()
+                            )));
+                        }
+                        calls.push(name);
+                    }
+                    None => {
+                        send_error(GrammarError::at(span, format!(
+                            "{}: '{}' is not a parameterized rule, so it can't be given arguments", rule, name
+                        )));
+                    }
+                }
+                for arg in args {
+                    find_calls(arg, spans, index, rule, templates, calls, send_error);
+                }
+            }
+        }
+    }
+    
+    let templates = raw_rules.iter()
+        .filter(|&&(_, ref rule)| ! rule.params.is_empty())
+        .map(|&(ref name, ref rule)| (name.as_str(), rule))
+        .collect::<HashMap<_, _>>();
+    let mut uses: HashMap<&str, Vec<&str>> = HashMap::new();
+    for &(ref name, ref rule) in raw_rules {
+        if rule.params.is_empty() {
+            find_calls(&rule.pat, &rule.spans, &mut 0, name, &templates, &mut Vec::new(), send_error);
+            continue;
+        }
+        if is_token_id(name) {
+            send_error(GrammarError::at(rule.span, format!("{}: Tokens can't have parameters", name)));
+        }
+        for (i, param) in rule.params.iter().enumerate() {
+            if is_token_id(param) {
+                send_error(GrammarError::at(rule.span, format!(
+                    "{}: The parameter '{}' can't have the name of a token", name, param
+                )));
+            } else if rule.params[..i].contains(param) {
+                send_error(GrammarError::at(rule.span, format!(
+                    "{}: The parameter '{}' is declared more than once", name, param
+                )));
+            }
+        }
+        // The parameters of a rule shadow the rules with the same names.
+        let mut visible = templates.clone();
+        for param in &rule.params {
+            visible.remove(param.as_str());
+        }
+        let mut calls = Vec::new();
+        find_calls(&rule.pat, &rule.spans, &mut 0, name, &visible, &mut calls, send_error);
+        uses.insert(name, calls);
+    }
+    
+    // Expanding a rule that uses itself would never end.
+    for &(ref name, ref rule) in raw_rules {
+        if rule.params.is_empty() {
+            continue;
+        }
+        let mut visited = HashSet::new();
+        let mut queue = uses[name.as_str()].clone();
+        while let Some(used) = queue.pop() {
+            if used == name {
+                send_error(GrammarError::at(rule.span, format!(
+                    "{}: Parameterized rules can't use themselves", name
+                )));
+                break;
+            }
+            if visited.insert(used) {
+                queue.extend(uses.get(used).into_iter().flat_map(|calls| calls.iter().cloned()));
+            }
+        }
+    }
+}
+
+/// Validates that the imports of the grammar have been resolved, which only
+/// happens when the grammar is read with 'load_grammar'.
+pub fn validate_imports_with<F: FnMut(GrammarError)>(raw_rules: &RawRules, send_error: &mut F) {
+    if ! raw_rules.files.is_empty() {
+        return;
+    }
+    for import in &raw_rules.imports {
+        send_error(GrammarError::at(import.span, format!(
+            "Can't import '{}' here; grammars with imports have to be read with 'load_grammar'", import.path
+        )));
+    }
+}
+
+/// Validates that no rule is defined more than once, and points out every
+/// definition after the first.
 pub fn validate_no_duplicate_rule_names<F: FnMut(GrammarError)>(raw_rules: &RawRules, send_error: &mut F ) {
-    let mut definition_count = HashMap::new();
+    let mut definitions: HashMap<&str, Vec<Span>> = HashMap::new();
+    for &(ref name, ref rule) in raw_rules {
+        let spans = definitions.entry(name).or_insert_with(Vec::new);
+        // Definitions in different files can start at the same offset.
+        if ! spans.iter().any(|span| span.file == rule.span.file && span.start == rule.span.start) {
+            spans.push(rule.span);
+        }
+    }
     for &(ref name, _) in raw_rules {
-        let count = definition_count.entry(name.clone()).or_insert(0);
-        *count += 1;
+        let spans = &definitions[name.as_str()];
+        if spans.len() > 1 {
+            for span in &spans[1..] {
+                send_error(GrammarError::at(*span, format!(
+                    "Rule with name '{}' defined {} times!", name, spans.len()
+                )));
+            }
+            // Only report each name once.
+            definitions.insert(name, vec![spans[0]]);
+        }
     }
-    for (name, count) in definition_count {
-        if count > 1 {
-            send_error(GrammarError::new(0, format!(
-                "Rule with name '{}' defined {} times!", name, count
+}
+
+/// Validates that all directives are known, and are given the right kind of
+/// arguments.
+pub fn validate_directives_with<F: FnMut(GrammarError)>(raw_rules: &RawRules, send_error: &mut F) {
+    fn expect_tokens<F: FnMut(GrammarError)>(directive: &Directive, args: &[Pat], send_error: &mut F) {
+        if args.is_empty() {
+            send_error(GrammarError::at(directive.span, format!(
+                "@{}: Expected at least one token", directive.name
             )));
         }
+        for arg in args {
+            if let Pat::Rule(ref name) = *arg {
+                if ! is_token_id(name) {
+                    send_error(GrammarError::at(directive.span, format!(
+                        "@{}: '{}' is a rule, but only tokens change lexer modes", directive.name, name
+                    )));
+                }
+            }
+        }
+    }
+    
+    let mut modes = vec![DEFAULT_MODE];
+    for directive in raw_rules.directives_named("mode") {
+        if let Some(&Pat::Rule(ref name)) = directive.args.first() {
+            modes.push(name);
+        }
+    }
+    // Each token can change the mode in one way.
+    let mut mode_changes = HashSet::new();
+    for directive in &raw_rules.directives {
+        match directive.name.as_str() {
+            "mode" => {
+                match directive.args.first() {
+                    Some(&Pat::Rule(ref name)) if ! is_token_id(name) => {
+                        if directive.args.len() < 2 {
+                            send_error(GrammarError::at(directive.span, format!(
+                                "@mode: Expected at least one token to lex in '{}'", name
+                            )));
+                        }
+                    }
+                    _ => {
+                        send_error(GrammarError::at(directive.span,
+                            "@mode: Expected the name of a mode, followed by its tokens".to_string()
+                        ));
+                    }
+                }
+                for arg in directive.args.iter().skip(1) {
+                    if let Pat::Rule(ref name) = *arg {
+                        if ! is_token_id(name) {
+                            send_error(GrammarError::at(directive.span, format!(
+                                "@mode: '{}' is a rule, but only tokens can be lexed", name
+                            )));
+                        }
+                    }
+                }
+            }
+            "push" | "pop" => {
+                let tokens = if directive.name == "pop" {
+                    &directive.args[..]
+                } else {
+                    match directive.args.first() {
+                        Some(&Pat::Rule(ref name)) if ! is_token_id(name) => {
+                            if ! modes.contains(&name.as_str()) {
+                                send_error(GrammarError::at(directive.span, format!(
+                                    "@push: Unknown lexer mode '{}'", name
+                                )));
+                            }
+                        }
+                        _ => {
+                            send_error(GrammarError::at(directive.span,
+                                "@push: Expected the name of a mode, followed by the tokens that enter it".to_string()
+                            ));
+                        }
+                    }
+                    directive.args.get(1..).unwrap_or(&[])
+                };
+                expect_tokens(directive, tokens, send_error);
+                for arg in tokens {
+                    let name = match *arg {
+                        Pat::Rule(ref name) => name,
+                        Pat::Token(GrammarToken::Str(ref name)) | Pat::Token(GrammarToken::Re(ref name)) => name,
+                        _ => continue,
+                    };
+                    if ! mode_changes.insert(name) {
+                        send_error(GrammarError::at(directive.span, format!(
+                            "@{}: '{}' already changes the lexer mode", directive.name, name
+                        )));
+                    }
+                }
+            }
+            "sync" => {
+                if directive.args.is_empty() {
+                    send_error(GrammarError::at(directive.span,
+                        "@sync: Expected at least one token".to_string()
+                    ));
+                }
+                for arg in &directive.args {
+                    if let Pat::Rule(ref name) = *arg {
+                        if ! is_token_id(name) {
+                            send_error(GrammarError::at(directive.span, format!(
+                                "@sync: '{}' is a rule, but only tokens can be synchronized to", name
+                            )));
+                        }
+                    }
+                }
+            }
+            "left" | "right" => {
+                let name = &directive.name;
+                match directive.args.first() {
+                    Some(&Pat::Rule(ref rule)) if ! is_token_id(rule) => {
+                        if ! raw_rules.iter().any(|&(ref defined, _)| defined == rule) {
+                            send_error(GrammarError::at(directive.span, format!(
+                                "@{}: Unbound name '{}'", name, rule
+                            )));
+                        }
+                        if directive.args.len() < 2 {
+                            send_error(GrammarError::at(directive.span, format!(
+                                "@{}: Expected at least one operator token", name
+                            )));
+                        }
+                    }
+                    _ => {
+                        send_error(GrammarError::at(directive.span, format!(
+                            "@{}: Expected the name of a rule, followed by its operators", name
+                        )));
+                    }
+                }
+                for arg in directive.args.iter().skip(1) {
+                    if let Pat::Rule(ref rule) = *arg {
+                        if ! is_token_id(rule) {
+                            send_error(GrammarError::at(directive.span, format!(
+                                "@{}: '{}' is a rule, but only tokens can be operators", name, rule
+                            )));
+                        }
+                    }
+                }
+            }
+            "indent" => {
+                if ! directive.args.is_empty() {
+                    send_error(GrammarError::at(directive.span,
+                        "@indent: Expected no arguments".to_string()
+                    ));
+                }
+                for &(ref name, ref rule) in raw_rules {
+                    if LAYOUT_TOKENS.contains(&name.as_str()) {
+                        send_error(GrammarError::at(rule.span, format!(
+                            "{}: The token is made by the lexer of an indented grammar ('@indent'), so it can't be defined", name
+                        )));
+                    }
+                }
+            }
+            "start" => {
+                if directive.args.is_empty() {
+                    send_error(GrammarError::at(directive.span,
+                        "@start: Expected at least one rule".to_string()
+                    ));
+                }
+                for arg in &directive.args {
+                    match *arg {
+                        Pat::Rule(ref name) if is_token_id(name) => {
+                            send_error(GrammarError::at(directive.span, format!(
+                                "@start: '{}' is a token, but only rules can be started with", name
+                            )));
+                        }
+                        Pat::Rule(ref name) => {
+                            if ! raw_rules.iter().any(|&(ref defined, _)| defined == name) {
+                                send_error(GrammarError::at(directive.span, format!(
+                                    "@start: Unbound name '{}'", name
+                                )));
+                            }
+                        }
+                        _ => {
+                            send_error(GrammarError::at(directive.span,
+                                "@start: Expected the names of rules".to_string()
+                            ));
+                        }
+                    }
+                }
+            }
+            _ => {
+                send_error(GrammarError::at(directive.span, format!(
+                    "Unknown directive '@{}'", directive.name
+                )));
+            }
+        }
     }
 }
 
@@ -68,7 +412,7 @@ pub fn validate_all_groups_named_with<F: FnMut(GrammarError)>(parser_rules: &Par
                 name.is_some()
             }).count();
             if nof_names != rule.captures.len() {
-                send_error(GrammarError::new(0, 
+                send_error(GrammarError::at(rule.span, 
                     format!("{}: Rule has {} capture groups, but names only {}.", 
                     rule.name, rule.captures.len(), nof_names)));
             }
@@ -76,9 +420,6 @@ pub fn validate_all_groups_named_with<F: FnMut(GrammarError)>(parser_rules: &Par
     }
 }
 
-// TODO: Keep track of the source of the various rules, so that I can point
-// out the location of errors.
-
 /// Validates that all rules and TOKENS mentioned in the given set of parser
 /// rules are actually defined, and errors if they are not.
 pub fn validate_closed_in_with<F: FnMut(GrammarError)>(parser_rules: &ParserRules, lexer_rules: &LexerRules, send_error: &mut F) {
@@ -86,20 +427,23 @@ pub fn validate_closed_in_with<F: FnMut(GrammarError)>(parser_rules: &ParserRule
     use grammar::GrammarToken::*;
     let mut bound_names: HashSet<String> = HashSet::new();
     bound_names.insert("EOF".to_string()); // EOF is always defined
+    if lexer_rules.tracks_indentation() {
+        bound_names.extend(LAYOUT_TOKENS.iter().map(|name| name.to_string()));
+    }
     for (name, _) in parser_rules {
         bound_names.insert(name.clone());
     }
     for tokendef in lexer_rules {
         match *tokendef {
-            TokenDef::Named(ref name, _) => {
+            TokenDef::Named(ref name, _, _) => {
                 bound_names.insert(name.clone());
             }
-            TokenDef::Unnamed(ref grammar_token) => {
+            TokenDef::Unnamed(ref grammar_token, _) => {
                 match *grammar_token {
                     Str(ref strpat) | Re(ref strpat) => {
                         bound_names.insert(strpat.clone());
                     }
-                    Named(ref name) => {
+                    Named(_, ref name) => {
                         bound_names.insert(name.deref().clone());
                     },
                 }
@@ -107,19 +451,22 @@ pub fn validate_closed_in_with<F: FnMut(GrammarError)>(parser_rules: &ParserRule
         }
     }
 
-    fn validate_pat<F: FnMut(GrammarError)>(pat: &Pat, rule: &Rc<String>, bound: &HashSet<String>, send_error: &mut F) {
+    fn validate_pat<F: FnMut(GrammarError)>(pat: &Pat, spans: &[Span], index: &mut usize, 
+        rule: &Rc<String>, bound: &HashSet<String>, send_error: &mut F) 
+    {
         use grammar::Pat::*;
+        let span = next_span(spans, index);
         match *pat {
             Rule(ref name) => {
                 if ! bound.contains(name) {
-                    send_error(GrammarError::new(0, format!("{}: Unbound name '{}'", rule, name)));
+                    send_error(GrammarError::at(span, format!("{}: Unbound name '{}'", rule, name)));
                 }
             },
             Token(ref token) | BreakOnToken(ref token) => {
                 match *token {
-                    GrammarToken::Named(ref name) => {
+                    GrammarToken::Named(_, ref name) => {
                         if ! bound.contains(name.deref()) {
-                            send_error(GrammarError::new(0, format!(
+                            send_error(GrammarError::at(span, format!(
                                 "{}: Unbound name '{}'", rule, name
                             )));
                         }
@@ -129,40 +476,80 @@ pub fn validate_closed_in_with<F: FnMut(GrammarError)>(parser_rules: &ParserRule
             },
             Seq(ref pats) | AnyOf(ref pats) => {
                 for pat in pats {
-                    validate_pat(pat, rule, bound, send_error)
+                    validate_pat(pat, spans, index, rule, bound, send_error)
                 }
             },
             Cap(_, ref inner) | Opt(ref inner) |
             ZeroPlus(ref inner) | OnePlus(ref inner) | Loop(ref inner) => {
-                validate_pat(inner, rule, bound, send_error);
+                validate_pat(inner, spans, index, rule, bound, send_error);
             }
+            SepBy(ref item, ref sep, _) => {
+                validate_pat(item, spans, index, rule, bound, send_error);
+                validate_pat(sep, spans, index, rule, bound, send_error);
+            }
+            Call(..) => unreachable!(),
         }
     }
     for (_, rule) in parser_rules {
-        validate_pat(&rule.pat, &rule.name, &bound_names, send_error);
+        validate_pat(&rule.pat, &rule.spans, &mut 0, &rule.name, &bound_names, send_error);
+    }
+    for &(id, span) in &parser_rules.sync_tokens {
+        let name = parser_rules.symbols.token_name(id);
+        if ! bound_names.contains(name.deref()) {
+            send_error(GrammarError::at(span, format!("@sync: Unbound name '{}'", name)));
+        }
+    }
+    for (_, rule) in parser_rules {
+        for op in &rule.operators {
+            let name = parser_rules.symbols.token_name(op.token);
+            if ! bound_names.contains(name.deref()) {
+                send_error(GrammarError::at(op.span, format!(
+                    "{}: Unbound name '{}'", rule.name, name
+                )));
+            }
+        }
+    }
+}
+
+/// Validates that no operator is declared more than once for the same rule,
+/// since it could only be parsed with one precedence.
+pub fn validate_operators_with<F: FnMut(GrammarError)>(parser_rules: &ParserRules, send_error: &mut F) {
+    for (_, rule) in parser_rules {
+        for (i, op) in rule.operators.iter().enumerate() {
+            if rule.operators[..i].iter().any(|other| other.token == op.token) {
+                send_error(GrammarError::at(op.span, format!(
+                    "{}: Operator <{}> is declared more than once", 
+                    rule.name, parser_rules.symbols.token_name(op.token)
+                )));
+            }
+        }
     }
 }
 
 
-// TODO: Should I try to check rules for being unused too, and only allow
-// a single 'entry point' in the grammar?
-/// Validates that all named tokens are referenced by a rule.
+/// Validates that all named tokens are referenced by a rule. Tokens from
+/// imported files are shared by several grammars, so those may go unused.
 pub fn validate_unused_tokens_with<F: FnMut(GrammarError)>(parser_rules: &ParserRules, lexer_rules: &LexerRules, send_error: &mut F) {
     use lexer::TokenDef;
-    let mut tokens: HashSet<String> = HashSet::new();
-    tokens.insert("EOF".to_string()); // EOF should be referenced somewhere
+    let mut tokens: HashMap<String, Span> = HashMap::new();
+    // EOF should be referenced somewhere, so it is reported at the rule that
+    // texts are parsed with.
+    let start_rule = parser_rules.start_rules.first().and_then(|name| parser_rules.get(name));
+    if let Some(rule) = start_rule {
+        tokens.insert("EOF".to_string(), rule.span);
+    }
     for tokendef in lexer_rules {
         match *tokendef {
-            TokenDef::Named(ref name, _) => {
-                if ! name.starts_with("_") {
-                    tokens.insert(name.clone());
+            TokenDef::Named(ref name, _, span) => {
+                if ! name.starts_with("_") && span.file == 0 {
+                    tokens.entry(name.clone()).or_insert(span);
                 }
             }
-            TokenDef::Unnamed(_) => {}
+            TokenDef::Unnamed(_, _) => {}
         }
     }
 
-    fn look_for_tokens(pat: &Pat, rule: &Rc<String>, mut tokens: &mut HashSet<String>) {
+    fn look_for_tokens(pat: &Pat, tokens: &mut HashMap<String, Span>) {
         use grammar::Pat::*;
         match *pat {
             Rule(ref name) => {
@@ -170,7 +557,7 @@ pub fn validate_unused_tokens_with<F: FnMut(GrammarError)>(parser_rules: &Parser
             },
             Token(ref token) | BreakOnToken(ref token) => {
                 match *token {
-                    GrammarToken::Named(ref name) => {
+                    GrammarToken::Named(_, ref name) => {
                         tokens.remove(name.deref());
                     }
                     _ => unreachable!("Compiled parser rules should only contain named tokens"),
@@ -178,21 +565,150 @@ pub fn validate_unused_tokens_with<F: FnMut(GrammarError)>(parser_rules: &Parser
             },
             Seq(ref pats) | AnyOf(ref pats) => {
                 for pat in pats {
-                    look_for_tokens(pat, rule, &mut tokens)
+                    look_for_tokens(pat, tokens)
                 }
             },
             Cap(_, ref inner) | Opt(ref inner) |
             ZeroPlus(ref inner) | OnePlus(ref inner) | Loop(ref inner) => {
-                look_for_tokens(inner, rule, &mut tokens);
+                look_for_tokens(inner, tokens);
+            }
+            SepBy(ref item, ref sep, _) => {
+                look_for_tokens(item, tokens);
+                look_for_tokens(sep, tokens);
             }
+            Call(..) => unreachable!(),
         }
     }
     for (_, rule) in parser_rules {
-        look_for_tokens(&rule.pat, &rule.name, &mut tokens);
+        look_for_tokens(&rule.pat, &mut tokens);
+        for op in &rule.operators {
+            tokens.remove(parser_rules.symbols.token_name(op.token).as_str());
+        }
     }
     if ! tokens.is_empty() {
-        for token in tokens {
-            send_error(GrammarError::new(0, format!("Unused token: <{}>", token)));
+        for (token, span) in tokens {
+            send_error(GrammarError::at(span, format!("Unused token: <{}>", token)));
+        }
+    }
+}
+
+/// Validates that every token can be lexed: token rules must be literals or
+/// regexes, regexes must compile, tokens must not match the empty text, and
+/// the tokens preferred over a token must not match all of its text. Regexes that match the text of another regex,
+/// or of a literal that isn't a keyword, are reported as well, since only one
+/// of the tokens is ever lexed from that text.
+pub fn validate_tokens_with<F: FnMut(GrammarError)>(lexer_rules: &LexerRules, send_error: &mut F) {
+    use lexer::TokenDef;
+    for &(ref name, span) in &lexer_rules.invalid_rules {
+        send_error(GrammarError::at(span, format!(
+            "{}: Tokens can only be a literal or a regex, or alternatives of those", name
+        )));
+    }
+    let symbols = lexer_rules.symbols();
+    let mut reported = HashSet::new();
+    let mut overlaps = HashSet::new();
+    for (mode, mode_lexer) in lexer_rules.modes().iter().zip(&lexer_rules.mode_lexers) {
+        let dfa = &mode_lexer.dfa;
+        let in_mode = if lexer_rules.modes().len() > 1 {
+            format!(" in the '{}' lexer mode", mode.name())
+        } else {
+            String::new()
+        };
+        let name = |pattern: usize| symbols.token_name(mode_lexer.tokens[pattern].0);
+        let tokendef = |pattern: usize| &lexer_rules.tokendefs[mode_lexer.tokendefs[pattern]];
+        let is_regex = |pattern: usize| match *tokendef(pattern) {
+            TokenDef::Named(_, GrammarToken::Re(_), _) | TokenDef::Unnamed(GrammarToken::Re(_), _) => true,
+            _ => false,
+        };
+        let mut invalid = HashSet::new();
+        for &(pattern, ref err) in &mode_lexer.errors {
+            invalid.insert(pattern);
+            if reported.insert(mode_lexer.tokendefs[pattern]) {
+                send_error(GrammarError::at(tokendef(pattern).span(), format!(
+                    "The regex of <{}> could not be compiled: {}", name(pattern), err
+                )));
+            }
+        }
+        for &pattern in dfa.empty_matches() {
+            invalid.insert(pattern);
+            if reported.insert(mode_lexer.tokendefs[pattern]) {
+                send_error(GrammarError::at(tokendef(pattern).span(), format!(
+                    "<{}> matches the empty text, which is never lexed", name(pattern)
+                )));
+            }
+        }
+        
+        // Find the tokens that are lexed from the text of each state, and
+        // those that are matched, but not lexed.
+        let mut lexed = HashSet::new();
+        let mut preferred: HashMap<TokenId, Vec<TokenId>> = HashMap::new();
+        for (state, accepts) in dfa.accepts().iter().enumerate() {
+            if state == START || accepts.is_empty() {
+                continue;
+            }
+            // Keywords aren't lexed when a letter follows them, and then the
+            // best pattern that isn't a keyword is.
+            let mut winners = vec![accepts[0]];
+            winners.extend(accepts.iter().cloned().find(|&pattern| ! dfa.is_keyword(pattern)));
+            let winner_ids = winners.iter().map(|&pattern| mode_lexer.tokens[pattern].0).collect::<Vec<_>>();
+            for &pattern in accepts {
+                let id = mode_lexer.tokens[pattern].0;
+                if winner_ids.contains(&id) {
+                    lexed.insert(id);
+                } else {
+                    // A keyword would only be lexed when no letter follows.
+                    let beaten_by = if dfa.is_keyword(pattern) { &winner_ids[..1] } else { &winner_ids[..] };
+                    let preferred = preferred.entry(id).or_insert_with(Vec::new);
+                    for &winner in beaten_by {
+                        if ! preferred.contains(&winner) {
+                            preferred.push(winner);
+                        }
+                    }
+                }
+            }
+        }
+        for pattern in 0..mode_lexer.tokens.len() {
+            let id = mode_lexer.tokens[pattern].0;
+            if invalid.contains(&pattern) || lexed.contains(&id) || ! preferred.contains_key(&id) {
+                continue;
+            }
+            if reported.insert(mode_lexer.tokendefs[pattern]) {
+                let names = preferred[&id].iter().map(|&id| format!("<{}>", symbols.token_name(id))).collect::<Vec<_>>();
+                send_error(GrammarError::at(tokendef(pattern).span(), format!(
+                    "<{}> is never lexed{}, since {} {} preferred for all of its text",
+                    name(pattern), in_mode, names.join(", "), if names.len() == 1 { "is" } else { "are" }
+                )));
+            }
+        }
+        
+        let mut examples = None;
+        for (state, accepts) in dfa.accepts().iter().enumerate() {
+            if state == START {
+                continue;
+            }
+            // Keywords are meant to be matched by the regexes of names too.
+            let first = match accepts.first() {
+                Some(&first) if ! dfa.is_keyword(first) => first,
+                _ => continue,
+            };
+            for &second in &accepts[1..] {
+                let (first_id, second_id) = (mode_lexer.tokens[first].0, mode_lexer.tokens[second].0);
+                if first_id == second_id || ! is_regex(second) || ! lexed.contains(&second_id)
+                    || ! overlaps.insert((first_id, second_id)) 
+                {
+                    continue;
+                }
+                let examples = examples.get_or_insert_with(|| dfa.examples());
+                let example = examples[state].clone().unwrap_or_default();
+                let message = if is_regex(first) {
+                    format!("<{}> and <{}> both match {:?}, which is lexed as <{}>{}, since it is defined first", 
+                        name(first), name(second), example, name(first), in_mode)
+                } else {
+                    format!("<{}> matches {:?}, which is lexed as the literal <{}>{}", 
+                        name(second), example, name(first), in_mode)
+                };
+                send_error(GrammarError::at(tokendef(second).span(), message));
+            }
         }
     }
 }
@@ -200,16 +716,542 @@ pub fn validate_unused_tokens_with<F: FnMut(GrammarError)>(parser_rules: &Parser
 /// Validates that uses of the 'endless loop' ('%') operator only contain
 /// patterns that have at least one mandatory token read in them, meaning
 /// that they always advance the token stream and thus won't loop forever.
-pub fn validate_endless_loops_into(_parser_rules: &ParserRules, _lints: &mut Vec<GrammarError>) {
+pub fn validate_endless_loops_into(parser_rules: &ParserRules, lints: &mut Vec<GrammarError>) {
     // Find the endless loops, and validate their sequence
+    fn has_break(pat: &Pat) -> bool {
+        use grammar::Pat::*;
+        match *pat {
+            BreakOnToken(_) => true,
+            // Breaks don't propagate out of the rule they are in.
+            Token(_) | Rule(_) => false,
+            Seq(ref pats) | AnyOf(ref pats) => pats.iter().any(has_break),
+            Cap(_, ref inner) | Opt(ref inner) |
+            ZeroPlus(ref inner) | OnePlus(ref inner) | Loop(ref inner) => has_break(inner),
+            SepBy(ref item, ref sep, _) => has_break(item) || has_break(sep),
+            Call(..) => unreachable!(),
+        }
+    }
+    
+    fn find_loops(pat: &Pat, spans: &[Span], index: &mut usize, rule: &Rc<String>, 
+        nullable: &HashSet<String>, lints: &mut Vec<GrammarError>) 
+    {
+        use grammar::Pat::*;
+        let span = next_span(spans, index);
+        match *pat {
+            Token(_) | Rule(_) | BreakOnToken(_) => {}
+            Seq(ref pats) | AnyOf(ref pats) => {
+                for pat in pats {
+                    find_loops(pat, spans, index, rule, nullable, lints);
+                }
+            }
+            Cap(_, ref inner) | Opt(ref inner) | ZeroPlus(ref inner) | OnePlus(ref inner) => {
+                find_loops(inner, spans, index, rule, nullable, lints);
+            }
+            Loop(ref body) => {
+                if is_nullable(body, nullable) {
+                    lints.push(GrammarError::at(span, format!(
+                        "{}: The body of loop {} can be parsed without reading a token, so it might never end", 
+                        rule, pat.fmt()
+                    )));
+                }
+                if ! has_break(body) {
+                    lints.push(GrammarError::at(span, format!(
+                        "{}: Loop {} has no break ('!') token, so it can never end", 
+                        rule, pat.fmt()
+                    )));
+                }
+                find_loops(body, spans, index, rule, nullable, lints);
+            }
+            SepBy(ref item, ref sep, _) => {
+                if is_nullable(item, nullable) && is_nullable(sep, nullable) {
+                    lints.push(GrammarError::at(span, format!(
+                        "{}: The items and separators of {} can be parsed without reading a token, so it might never end", 
+                        rule, pat.fmt()
+                    )));
+                }
+                find_loops(item, spans, index, rule, nullable, lints);
+                find_loops(sep, spans, index, rule, nullable, lints);
+            }
+            Call(..) => unreachable!(),
+        }
+    }
+    
+    let nullable = find_nullable_rules(parser_rules);
+    for (_, rule) in parser_rules {
+        find_loops(&rule.pat, &rule.spans, &mut 0, &rule.name, &nullable, lints);
+    }
 }
 
 /// Validates that the grammar doesn't contain left-recursive items, so that
 /// it won't get stuck in an endless loop trying to read a recursive set of
 /// rules.
-pub fn validate_left_recursion_into(_parser_rules: &ParserRules, _lints: &mut Vec<GrammarError>) {
+pub fn validate_left_recursion_into(parser_rules: &ParserRules, lints: &mut Vec<GrammarError>) {
     // Check which set of rules are reachable from the beginning of each rule,
     // and raise an error if the current rule is in it.
+    let nullable = find_nullable_rules(parser_rules);
+    let mut left_rules = HashMap::new();
+    for (name, rule) in parser_rules {
+        let mut rules = Vec::new();
+        find_left_rules(&rule.pat, &nullable, &mut rules);
+        left_rules.insert(name.as_str(), rules);
+    }
+    let mut names = left_rules.keys().cloned().collect::<Vec<_>>();
+    names.sort();
+    
+    /// Adds every path from 'current' back to the start of the path to 
+    /// 'cycles', only going through rules with a higher name than the start.
+    fn find_cycles<'a>(current: &'a str, left_rules: &'a HashMap<&str, Vec<String>>, 
+        path: &mut Vec<&'a str>, cycles: &mut Vec<Vec<&'a str>>) 
+    {
+        let start = path[0];
+        let mut visited = HashSet::new();
+        for next in &left_rules[current] {
+            let next = next.as_str();
+            if ! visited.insert(next) {
+                continue;
+            }
+            if next == start {
+                let mut cycle = path.clone();
+                cycle.push(start);
+                cycles.push(cycle);
+            } else if next > start && left_rules.contains_key(next) && ! path.contains(&next) {
+                path.push(next);
+                find_cycles(next, left_rules, path, cycles);
+                path.pop();
+            }
+        }
+    }
+    
+    // Report each cycle once, from the rule in it with the lowest name.
+    for &start in &names {
+        let mut cycles = Vec::new();
+        find_cycles(start, &left_rules, &mut vec![start], &mut cycles);
+        for cycle in cycles {
+            lints.push(GrammarError::at(parser_rules.get(start).unwrap().span, format!(
+                "{}: Left-recursive rule: {}", start, cycle.join(" -> ")
+            )));
+        }
+    }
 }
 
-// TODO: unreachable patterns (like my greedy optional commas)
+/// Validates that every rule can be reached from the start rules ('@start').
+/// Alternatives that can never be selected are reported by 
+/// 'validate_ll1_conflicts_with', along with the other conflicts.
+pub fn validate_unreachable_with<F: FnMut(GrammarError)>(parser_rules: &ParserRules, send_error: &mut F) {
+    let mut reached: HashSet<&str> = HashSet::new();
+    let mut queue: VecDeque<&str> = parser_rules.start_rules.iter().map(|name| name.as_str()).collect();
+    while let Some(name) = queue.pop_front() {
+        if ! reached.insert(name) {
+            continue;
+        }
+        if let Some(rule) = parser_rules.get(name) {
+            let mut used_rules = Vec::new();
+            find_used_rules(&rule.pat, &mut used_rules);
+            for used in used_rules {
+                if let Some(used) = parser_rules.get(&used) {
+                    queue.push_back(used.name.as_str());
+                }
+            }
+        }
+    }
+    let start_rules = parser_rules.start_rules.iter().map(|name| name.as_str()).collect::<Vec<_>>();
+    for (name, rule) in parser_rules {
+        if ! reached.contains(name.as_str()) {
+            send_error(GrammarError::at(rule.span, format!(
+                "{}: Rule can't be reached from the start rules ({})", name, start_rules.join(", ")
+            )));
+        }
+    }
+}
+
+/// Validates that the parser never has to choose between two ways to parse
+/// a token, since it always takes the first without looking further ahead.
+/// This reports alternatives that can start with the same token, where the
+/// later one is never parsed with it, and optional or repeated patterns that
+/// can start with a token that can also follow them, where the pattern is
+/// always parsed instead of what follows. An alternative whose tokens are
+/// all taken by the alternatives before it is reported once, as one that 
+/// can never be selected.
+pub fn validate_ll1_conflicts_with<F: FnMut(GrammarError)>(parser_rules: &ParserRules, send_error: &mut F) {
+    fn fmt_tokens(tokens: &[TokenId], symbols: &Symbols) -> String {
+        let names = tokens.iter().map(|&id| format!("<{}>", symbols.token_name(id))).collect::<Vec<_>>();
+        names.join(", ")
+    }
+    
+    fn find_conflicts<F: FnMut(GrammarError)>(pat: &Pat, node: usize, rule: &ParserRule, 
+        symbols: &Symbols, send_error: &mut F) 
+    {
+        use grammar::Pat::*;
+        let sets = rule.sets();
+        let span = rule.spans.get(node).cloned().unwrap_or_default();
+        match *pat {
+            Token(_) | Rule(_) | BreakOnToken(_) => {}
+            Seq(ref pats) | AnyOf(ref pats) => {
+                let children = child_nodes(sets, node, pats.len());
+                if let AnyOf(_) = *pat {
+                    for j in 1..children.len() {
+                        let first = sets[children[j]].first();
+                        let is_chosen = first.ids().into_iter().any(|id| sets[node].choice(id) == Some(j));
+                        if ! first.is_empty() && ! is_chosen {
+                            send_error(GrammarError::at(span, format!(
+                                "{}: Alternative {} of {} can never be selected, since every token it starts with selects an earlier alternative",
+                                rule.name, j + 1, pat.fmt()
+                            )));
+                            continue;
+                        }
+                        for i in 0..j {
+                            let shared = sets[children[i]].first().intersection(first).ids();
+                            if ! shared.is_empty() {
+                                send_error(GrammarError::at(span, format!(
+                                    "{}: Alternatives {} and {} of {} can both start with {}, so alternative {} is never parsed with it",
+                                    rule.name, i + 1, j + 1, pat.fmt(), fmt_tokens(&shared, symbols), j + 1
+                                )));
+                            }
+                        }
+                    }
+                }
+                for (pat, child) in pats.iter().zip(children) {
+                    find_conflicts(pat, child, rule, symbols, send_error);
+                }
+            }
+            Opt(ref inner) | ZeroPlus(ref inner) | OnePlus(ref inner) => {
+                let mut shared = sets[node + 1].first().intersection(sets[node].follow()).ids();
+                // Tokens are never read past EOF, so reading it early is harmless.
+                shared.retain(|&id| id != TokenId::EOF);
+                if ! shared.is_empty() {
+                    send_error(GrammarError::at(span, format!(
+                        "{}: {} can both start and follow {}, so the pattern is always parsed with it",
+                        rule.name, fmt_tokens(&shared, symbols), pat.fmt()
+                    )));
+                }
+                find_conflicts(inner, node + 1, rule, symbols, send_error);
+            }
+            Cap(_, ref inner) | Loop(ref inner) => {
+                find_conflicts(inner, node + 1, rule, symbols, send_error);
+            }
+            SepBy(ref item, ref sep, flags) => {
+                let children = child_nodes(sets, node, 2);
+                let follow = sets[node].follow();
+                let mut shared = sets[children[1]].first().intersection(follow).ids();
+                shared.retain(|&id| id != TokenId::EOF);
+                if ! shared.is_empty() {
+                    send_error(GrammarError::at(span, format!(
+                        "{}: {} can both separate the items of {} and follow it, so it is always parsed as a separator",
+                        rule.name, fmt_tokens(&shared, symbols), pat.fmt()
+                    )));
+                }
+                if flags.empty || flags.trailing {
+                    let mut shared = sets[children[0]].first().intersection(follow).ids();
+                    shared.retain(|&id| id != TokenId::EOF);
+                    if ! shared.is_empty() {
+                        send_error(GrammarError::at(span, format!(
+                            "{}: {} can both start and follow {}, so the pattern is always parsed with it",
+                            rule.name, fmt_tokens(&shared, symbols), pat.fmt()
+                        )));
+                    }
+                }
+                find_conflicts(item, children[0], rule, symbols, send_error);
+                find_conflicts(sep, children[1], rule, symbols, send_error);
+            }
+            Call(..) => unreachable!(),
+        }
+    }
+    
+    for (_, rule) in parser_rules {
+        find_conflicts(&rule.pat, 0, rule, &parser_rules.symbols, send_error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grammar::parse_raw_rules;
+    use lexer::find_lexer_rules;
+    use parser::find_parser_rules;
+    
+    /// Returns the messages of the errors found in the grammar.
+    fn errors(grammar: &str) -> Vec<String> {
+        let raw_rules = parse_raw_rules(grammar).unwrap();
+        let lexer_rules = find_lexer_rules(&raw_rules);
+        let parser_rules = find_parser_rules(&raw_rules);
+        validate_rules(&raw_rules, &lexer_rules, &parser_rules).into_iter().map(|err| err.message).collect()
+    }
+    
+    /// Returns the messages of the errors that one validator finds in the 
+    /// parser rules of the grammar.
+    fn found_by<V>(grammar: &str, validate: V) -> Vec<String> 
+        where V: FnOnce(&ParserRules, &mut Vec<GrammarError>)
+    {
+        let parser_rules = find_parser_rules(&parse_raw_rules(grammar).unwrap());
+        let mut errors = Vec::new();
+        validate(&parser_rules, &mut errors);
+        errors.into_iter().map(|err| err.message).collect()
+    }
+    
+    #[test]
+    fn loop_without_a_break_is_reported() {
+        let grammar = "program: (A)% EOF\nA: \"a\"\n";
+        assert_eq!(found_by(grammar, validate_endless_loops_into), vec![
+            "program: Loop <A>% has no break ('!') token, so it can never end",
+        ]);
+    }
+    
+    #[test]
+    fn loop_with_a_nullable_body_is_reported() {
+        let grammar = "program: (A? \"b\"!)% EOF\nA: \"a\"\n";
+        assert_eq!(found_by(grammar, validate_endless_loops_into), vec![
+            "program: The body of loop (<A>? <b>!)% can be parsed without reading a token, so it might never end",
+        ]);
+        let grammar = "program: (A \"b\"!)% EOF\nA: \"a\"\n";
+        assert!(found_by(grammar, validate_endless_loops_into).is_empty());
+    }
+    
+    #[test]
+    fn loop_that_is_nullable_through_a_rule_is_reported() {
+        let grammar = "program: (maybe \"b\"!)% EOF\nmaybe: A?\nA: \"a\"\n";
+        assert_eq!(found_by(grammar, validate_endless_loops_into), vec![
+            "program: The body of loop ('maybe' <b>!)% can be parsed without reading a token, so it might never end",
+        ]);
+    }
+    
+    #[test]
+    fn rules_that_cant_be_reached_are_reported() {
+        let grammar = r##"
+            @start program
+            program: item EOF
+            item: A
+            other: item
+            A: "a"
+        "##;
+        let found = found_by(grammar, |rules, errors| {
+            validate_unreachable_with(rules, &mut |err| errors.push(err))
+        });
+        assert_eq!(found, vec!["other: Rule can't be reached from the start rules (program)"]);
+    }
+    
+    #[test]
+    fn alternative_that_cant_be_selected_is_reported_once() {
+        let grammar = "s: (A B | A) EOF\nA: \"a\"\nB: \"b\"\n";
+        assert_eq!(errors(grammar), vec![
+            "s: Alternative 2 of ((<A> <B>) | <A>) can never be selected, since every token it starts with selects an earlier alternative",
+        ]);
+    }
+    
+    /// Returns the LL(1) conflicts found in the grammar.
+    fn conflicts(grammar: &str) -> Vec<String> {
+        found_by(grammar, |rules, errors| validate_ll1_conflicts_with(rules, &mut |err| errors.push(err)))
+    }
+
+    #[test]
+    fn alternatives_that_start_alike_are_reported() {
+        let grammar = "s: (A B | A? C) EOF\nA: \"a\"\nB: \"b\"\nC: \"c\"\n";
+        assert_eq!(conflicts(grammar), vec![
+            "s: Alternatives 1 and 2 of ((<A> <B>) | (<A>? <C>)) can both start with <A>, so alternative 2 is never parsed with it",
+        ]);
+        let grammar = "s: (A B | C) EOF\nA: \"a\"\nB: \"b\"\nC: \"c\"\n";
+        assert!(conflicts(grammar).is_empty());
+    }
+
+    #[test]
+    fn optional_pattern_that_can_be_followed_by_its_start_is_reported() {
+        let grammar = "s: A? A EOF\nA: \"a\"\n";
+        assert_eq!(conflicts(grammar), vec![
+            "s: <A> can both start and follow <A>?, so the pattern is always parsed with it",
+        ]);
+        let grammar = "s: A* EOF\nA: \"a\"\n";
+        assert!(conflicts(grammar).is_empty());
+    }
+
+    #[test]
+    fn separator_that_can_follow_the_list_is_reported() {
+        let grammar = "s: {A, C} C EOF\nA: \"a\"\nC: \"c\"\n";
+        assert_eq!(conflicts(grammar), vec![
+            "s: <C> can both separate the items of {<A>, <C>} and follow it, so it is always parsed as a separator",
+        ]);
+        let grammar = "s: {A, C} EOF\nA: \"a\"\nC: \"c\"\n";
+        assert!(conflicts(grammar).is_empty());
+    }
+
+    #[test]
+    fn direct_left_recursion() {
+        let grammar = "a: a B | C EOF\nB: \"b\"\nC: \"c\"\n";
+        assert_eq!(found_by(grammar, validate_left_recursion_into), vec!["a: Left-recursive rule: a -> a"]);
+    }
+    
+    #[test]
+    fn indirect_left_recursion() {
+        let grammar = r##"
+            a: b C EOF
+            b: c D | E
+            c: a
+            C: "c"
+            D: "d"
+            E: "e"
+        "##;
+        assert_eq!(found_by(grammar, validate_left_recursion_into), vec!["a: Left-recursive rule: a -> b -> c -> a"]);
+    }
+    
+    #[test]
+    fn left_recursion_through_a_nullable_prefix() {
+        let grammar = r##"
+            program: a EOF
+            a: B? a | C
+            b: opt c
+            opt: B*
+            c: b | C
+            B: "b"
+            C: "c"
+        "##;
+        assert_eq!(found_by(grammar, validate_left_recursion_into), vec![
+            "a: Left-recursive rule: a -> a",
+            "b: Left-recursive rule: b -> c -> b",
+        ]);
+    }
+    
+    #[test]
+    fn every_left_recursive_cycle_is_reported() {
+        let grammar = r##"
+            x: y A | z A | A
+            y: x
+            z: x | y
+            A: "a"
+        "##;
+        assert_eq!(found_by(grammar, validate_left_recursion_into), vec![
+            "x: Left-recursive rule: x -> y -> x",
+            "x: Left-recursive rule: x -> z -> x",
+            "x: Left-recursive rule: x -> z -> y -> x",
+        ]);
+    }
+    
+    #[test]
+    fn recursive_template_is_reported_once() {
+        let grammar = r##"
+            program: list<$$NUM> EOF
+            list<item>: item ("," list<item>)?
+            NUM: r#"[0-9]+"#
+        "##;
+        assert_eq!(errors(grammar), vec!["list: Parameterized rules can't use themselves"]);
+    }
+    
+    #[test]
+    fn template_given_the_wrong_arguments_is_reported_once() {
+        let grammar = r##"
+            program: list<A> EOF
+            list<item, sep>: item (sep item)*
+            A: "a"
+            C: ","
+        "##;
+        assert_eq!(errors(grammar), vec!["program: 'list' takes 2 argument(s), but is given 1"]);
+        let grammar = r##"
+            program: list<A, C, value> EOF
+            list<item, sep>: item (sep item)*
+            A: "a"
+            C: ","
+        "##;
+        assert_eq!(errors(grammar), vec![
+            "program: 'list' takes 2 argument(s), but is given 3",
+            "program: Unbound name 'value'",
+        ]);
+    }
+    
+    #[test]
+    fn mode_directives_are_validated() {
+        let grammar = r##"
+            program: ($NAME | QUOTE $TEXT* QUOTE)* EOF
+            NAME: r#"[a-z]+"#
+            TEXT: r#"[^']+"#
+            QUOTE: "'"
+            @mode string TEXT QUOTE
+            @mode NAME
+            @push nowhere QUOTE
+            @push string program
+            @pop QUOTE
+            @pop
+        "##;
+        let raw_rules = parse_raw_rules(grammar).unwrap();
+        let mut errors = Vec::new();
+        validate_directives_with(&raw_rules, &mut |err| errors.push(err.message));
+        assert_eq!(errors, vec![
+            "@mode: Expected the name of a mode, followed by its tokens",
+            "@push: Unknown lexer mode 'nowhere'",
+            "@push: 'program' is a rule, but only tokens change lexer modes",
+            "@pop: 'QUOTE' already changes the lexer mode",
+            "@pop: Expected at least one token",
+        ]);
+    }
+    
+    /// Returns the messages of the errors in the tokens of the grammar.
+    fn token_errors(grammar: &str) -> Vec<String> {
+        let lexer_rules = find_lexer_rules(&parse_raw_rules(grammar).unwrap());
+        let mut errors = Vec::new();
+        validate_tokens_with(&lexer_rules, &mut |err| errors.push(err.message));
+        errors
+    }
+    
+    #[test]
+    fn token_that_is_never_lexed_is_reported() {
+        let grammar = r##"
+            program: ($NAME | $ID | $IF | $OP | $ARROW)* EOF
+            NAME: r#"[a-z]+"#
+            ID: r#"[a-z][a-z0-9]*"#
+            IF: "if"
+            OP: r#"[-+>]+"#
+            ARROW: "->"
+        "##;
+        // Keywords are meant to be matched by names, so only other literals
+        // are reported.
+        assert_eq!(token_errors(grammar), vec![
+            "<NAME> and <ID> both match \"a\", which is lexed as <NAME>, since it is defined first",
+            "<OP> matches \"->\", which is lexed as the literal <ARROW>",
+        ]);
+        let grammar = r##"
+            program: ($NAME | $ID)* EOF
+            NAME: r#"[a-z]+"#
+            ID: r#"[a-c]+"#
+        "##;
+        assert_eq!(token_errors(grammar), vec![
+            "<ID> is never lexed, since <NAME> is preferred for all of its text",
+        ]);
+    }
+    
+    #[test]
+    fn tokens_that_cant_be_lexed_are_reported() {
+        let grammar = r##"
+            program: ($A | $B | $C | $D)* EOF
+            A: r#"(a"#
+            B: r#"b*"#
+            C: r#"^c"#
+            D: "d" | e
+            e: "e"
+        "##;
+        let mut errors = token_errors(grammar);
+        assert_eq!(errors.len(), 4);
+        assert_eq!(errors.remove(0), "D: Tokens can only be a literal or a regex, or alternatives of those");
+        assert!(errors[0].starts_with("The regex of <A> could not be compiled: "), "{}", errors[0]);
+        assert_eq!(errors[1], "The regex of <C> could not be compiled: Anchors and word boundaries can't be part of a token");
+        assert_eq!(errors[2], "<B> matches the empty text, which is never lexed");
+    }
+    
+    #[test]
+    fn unbound_name_is_reported_where_it_is_used() {
+        let grammar = "program: $$item* EOF\n\nitem: A (\"[\" ($B | value)* \"]\")?\nA: \"a\"\nB: \"b\"\n";
+        let raw_rules = parse_raw_rules(grammar).unwrap();
+        let lexer_rules = find_lexer_rules(&raw_rules);
+        let parser_rules = find_parser_rules(&raw_rules);
+        let errors = validate_rules(&raw_rules, &lexer_rules, &parser_rules);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "3:20: item: Unbound name 'value'");
+    }
+    
+    #[test]
+    fn unused_eof_is_reported_at_the_start_rule() {
+        let grammar = "A: \"a\"\nprogram: A\n";
+        let raw_rules = parse_raw_rules(grammar).unwrap();
+        let lexer_rules = find_lexer_rules(&raw_rules);
+        let parser_rules = find_parser_rules(&raw_rules);
+        let errors = validate_rules(&raw_rules, &lexer_rules, &parser_rules);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Unused token: <EOF>");
+        assert_eq!((errors[0].line, errors[0].col), (2, 1));
+    }
+}
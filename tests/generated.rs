@@ -0,0 +1,248 @@
+//! Checks that the code generated from the example grammars is current, and
+//! that it parses texts like the rules that it was generated from.
+
+extern crate heck;
+
+// Generated from 'toml.heck' with 'dero examples/toml.heck --generate-parser'.
+#[path = "../examples/generated/toml_parser.rs"]
+mod toml_parser;
+
+// Generated from 'toml.heck' with 'dero examples/toml.heck --generate-ast'.
+#[path = "../examples/generated/toml_ast.rs"]
+mod toml_ast;
+
+// Generated from 'lists.heck' with 'dero examples/lists.heck --generate-parser'.
+#[path = "../examples/generated/lists_parser.rs"]
+mod lists_parser;
+
+use heck::{ParseError, ParserRules, LexerRules};
+
+const TOML_GRAMMAR: &str = include_str!("../examples/toml.heck");
+
+const TOML_PARSER: &str = include_str!("../examples/generated/toml_parser.rs");
+
+const TOML_AST: &str = include_str!("../examples/generated/toml_ast.rs");
+
+const LISTS_GRAMMAR: &str = include_str!("../examples/lists.heck");
+
+const LISTS_PARSER: &str = include_str!("../examples/generated/lists_parser.rs");
+
+fn toml_rules() -> (LexerRules, ParserRules) {
+    rules(TOML_GRAMMAR)
+}
+
+fn rules(grammar: &str) -> (LexerRules, ParserRules) {
+    let raw_rules = heck::parse_raw_rules(grammar).unwrap();
+    let lexer_rules = heck::find_lexer_rules(&raw_rules);
+    let parser_rules = heck::find_parser_rules(&raw_rules);
+    assert!(heck::validate_rules(&raw_rules, &lexer_rules, &parser_rules).is_empty());
+    (lexer_rules, parser_rules)
+}
+
+/// Defines a function that formats the spans and captures of a match, so
+/// that the matches of the runtime and generated parsers can be compared.
+macro_rules! fmt_tree {
+    ($name:ident, $Match:ty) => {
+        fn $name(mtc: &$Match, out: &mut String) {
+            out.push_str(&format!("{}[{}..{}]", mtc.rule, mtc.start, mtc.end));
+            if mtc.token().is_some() {
+                return;
+            }
+            out.push('(');
+            for index in 0..mtc.captures.len() {
+                if let Some(value) = mtc.single(index) {
+                    $name(value, out);
+                } else if let Some(value) = mtc.optional(index) {
+                    match value {
+                        Some(value) => $name(value, out),
+                        None => out.push_str("None"),
+                    }
+                } else if let Some(values) = mtc.multiple(index) {
+                    out.push('[');
+                    for value in values {
+                        $name(value, out);
+                        out.push(' ');
+                    }
+                    out.push(']');
+                }
+                out.push(' ');
+            }
+            out.push(')');
+        }
+    }
+}
+
+fmt_tree!(fmt_match, heck::Match);
+fmt_tree!(fmt_generated_match, toml_parser::Match);
+fmt_tree!(fmt_generated_lists_match, lists_parser::Match);
+
+fn fmt_error(err: &ParseError) -> String {
+    let names = |names: &[std::rc::Rc<String>]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+    match *err {
+        ParseError::Lex { pos, .. } => format!("lex at {}", pos),
+        ParseError::UnexpectedToken { ref found, ref found_name, ref scope, ref expected, .. } => {
+            format!("unexpected {} at {} in {:?}, expected {:?}",
+                found_name, found.start, names(scope), names(expected))
+        }
+        ParseError::UnexpectedEof { ref scope, ref expected } => {
+            format!("unexpected EOF in {:?}, expected {:?}", names(scope), names(expected))
+        }
+        ParseError::UnclosedLoop { pos, ref scope, .. } => {
+            format!("unclosed loop at {} in {:?}", pos, names(scope))
+        }
+        _ => panic!("Unexpected error: {}", err),
+    }
+}
+
+/// Defines a function that formats the errors of a generated parser like
+/// 'fmt_error' formats those of the runtime parser.
+macro_rules! fmt_generated_error {
+    ($name:ident, $parser:ident) => {
+        fn $name(err: &$parser::ParseError) -> String {
+            use $parser::ParseError::*;
+            let names = |kinds: &[$parser::TokenKind]| kinds.iter().map(|kind| kind.name()).collect::<Vec<_>>();
+            match *err {
+                Lex { pos } => format!("lex at {}", pos),
+                UnexpectedToken { ref found, ref scope, ref expected } => {
+                    format!("unexpected {} at {} in {:?}, expected {:?}",
+                        found.name(), found.start, scope, names(expected))
+                }
+                UnexpectedEof { ref scope, ref expected } => {
+                    format!("unexpected EOF in {:?}, expected {:?}", scope, names(expected))
+                }
+                UnclosedLoop { pos, ref scope } => format!("unclosed loop at {} in {:?}", pos, scope),
+                Dedent { .. } => panic!("Unexpected error: {:?}", err),
+            }
+        }
+    }
+}
+
+fmt_generated_error!(fmt_generated_error, toml_parser);
+fmt_generated_error!(fmt_generated_lists_error, lists_parser);
+
+/// Returns the tree of the match that the runtime parser finds in the text,
+/// or its error.
+fn parse_tree(text: &str, rule: &str, lexer_rules: &LexerRules, parser_rules: &ParserRules) -> Result<String, String> {
+    heck::lex(text, lexer_rules)
+        .and_then(|tokens| heck::parse_with_rules(rule, parser_rules, tokens, text))
+        .map(|mtc| {
+            let mut tree = String::new();
+            fmt_match(&mtc, &mut tree);
+            tree
+        })
+        .map_err(|err| fmt_error(&err))
+}
+
+#[test]
+fn generated_parser_is_current() {
+    let (lexer_rules, parser_rules) = toml_rules();
+    let code = heck::generate_parser(&parser_rules, &lexer_rules);
+    assert!(code == TOML_PARSER,
+        "examples/generated/toml_parser.rs is stale; regenerate it with 'dero examples/toml.heck -p'");
+}
+
+#[test]
+fn generated_parser_parses_like_the_rules() {
+    let (lexer_rules, parser_rules) = toml_rules();
+    let texts = [
+        include_str!("../Cargo.toml"),
+        "a = +1\n[table]\nkey = \"value\"\n",
+        "[[items]]\nx = [ +1, +2.5 ]\ny = { z = true, w = [ \"a\" ] }",
+        "",
+        "a = \n",
+        "[a.b\nc = +1",
+        "a = [+1, +2",
+        "a = [+1 +2]",
+        "= +1",
+        "a = { b = +1 ",
+        "a = @",
+        "[[a]] b = false",
+    ];
+    for text in &texts {
+        let expected = parse_tree(text, "document", &lexer_rules, &parser_rules);
+        let found = toml_parser::parse_document(text)
+            .map(|mtc| {
+                let mut tree = String::new();
+                fmt_generated_match(&mtc, &mut tree);
+                tree
+            })
+            .map_err(|err| fmt_generated_error(&err));
+        assert_eq!(found, expected, "Parsing {:?}", text);
+    }
+}
+
+#[test]
+fn generated_ast_is_current() {
+    let (_, parser_rules) = toml_rules();
+    let code = heck::generate_ast(&parser_rules, "heck");
+    assert!(code == TOML_AST,
+        "examples/generated/toml_ast.rs is stale; regenerate it with 'dero examples/toml.heck -a'");
+}
+
+#[test]
+fn generated_ast_converts_matches() {
+    use toml_ast::{Document, DocumentItems, Expr, Key};
+    let (lexer_rules, parser_rules) = toml_rules();
+    let text = "a = +1\n[table]\n\"b\" = [ true, +2.5 ]\n";
+    let tokens = heck::lex(text, &lexer_rules).unwrap();
+    let mtc = heck::parse_with_rules("document", &parser_rules, tokens.into_iter(), text).unwrap();
+    let document = Document::from(&mtc);
+    assert_eq!(document.items.len(), 3);
+    match document.items[2] {
+        DocumentItems::Entry(ref entry) => {
+            match *entry.key {
+                Key::String(ref token) => assert_eq!(token.slice(text), "\"b\""),
+                _ => panic!("Unexpected key: {:?}", entry.key),
+            }
+            match *entry.expr {
+                Expr::Array(ref array) => {
+                    assert_eq!(array.expr.len(), 2);
+                    match array.expr[1] {
+                        Expr::Float(ref token) => assert_eq!(token.slice(text), "+2.5"),
+                        _ => panic!("Unexpected value: {:?}", array.expr[1]),
+                    }
+                }
+                _ => panic!("Unexpected value: {:?}", entry.expr),
+            }
+        }
+        _ => panic!("Unexpected item: {:?}", document.items[2]),
+    }
+}
+
+#[test]
+fn generated_lists_parser_is_current() {
+    let (lexer_rules, parser_rules) = rules(LISTS_GRAMMAR);
+    let code = heck::generate_parser(&parser_rules, &lexer_rules);
+    assert!(code == LISTS_PARSER,
+        "examples/generated/lists_parser.rs is stale; regenerate it with 'dero examples/lists.heck -p'");
+}
+
+#[test]
+fn generated_lists_parser_parses_like_the_rules() {
+    let (lexer_rules, parser_rules) = rules(LISTS_GRAMMAR);
+    let texts = [
+        "(1) (1, 2, 3) [1] [1,] [1, 2, 3,] {} {1} {1, 2,}",
+        "",
+        "()",
+        "(1,)",
+        "(1, 2",
+        "[]",
+        "[1,,]",
+        "[1, 2",
+        "{,}",
+        "{1 2}",
+        "{1,,}",
+    ];
+    assert!(parse_tree(texts[0], "lists", &lexer_rules, &parser_rules).is_ok());
+    for text in &texts {
+        let expected = parse_tree(text, "lists", &lexer_rules, &parser_rules);
+        let found = lists_parser::parse_lists(text)
+            .map(|mtc| {
+                let mut tree = String::new();
+                fmt_generated_lists_match(&mtc, &mut tree);
+                tree
+            })
+            .map_err(|err| fmt_generated_lists_error(&err));
+        assert_eq!(found, expected, "Parsing {:?}", text);
+    }
+}